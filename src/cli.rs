@@ -1,18 +1,83 @@
 use std::path::PathBuf;
+use std::str::FromStr;
 
+use anyhow::{anyhow, Error};
+
+use structopt::clap::ArgGroup;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
 #[structopt(about = "Apply startup scripts to a dev machine")]
+#[structopt(group = ArgGroup::with_name("env-dumps").multiple(true))]
+#[allow(clippy::struct_excessive_bools)] // One field per command-line flag.
 pub(crate) struct Opt {
     /// Allow Devmaker to ask for askable vars interactively.
     #[structopt(short, long)]
     pub interactive: bool,
 
+    /// Only check that the jobs parse and can be scheduled, then exit.
+    #[structopt(long)]
+    pub check: bool,
+
+    /// Only print the jobs in schedule order, with their descriptions, then exit.
+    #[structopt(long)]
+    pub list: bool,
+
+    /// Only check that every asked var resolves without prompting, reporting the ones that
+    /// don't, then exit.
+    #[structopt(long, conflicts_with_all = &["interactive", "prompt-missing", "only-changed-vars", "review-vars", "reask"])]
+    pub check_vars: bool,
+
+    /// Only print the dependency graph as a tree, then exit.
+    #[structopt(long)]
+    pub tree: bool,
+
+    /// Only print the jobs as JSON levels, each of which can run in parallel once the levels
+    /// before it are done, then exit.
+    #[structopt(long)]
+    pub print_levels_json: bool,
+
+    /// Only print each job in schedule order with why it's placed there, then exit.
+    #[structopt(long)]
+    pub explain_schedule: bool,
+
     /// Don't actually run anything, just report on how the process would have run.
     #[structopt(short = "n", long)]
     pub dry_run: bool,
 
+    /// During a dry run, actually evaluate each job's `when` condition.
+    #[structopt(long)]
+    pub eval_conditions: bool,
+
+    /// Prompt only for vars no other source provides, then summarize where each var came from.
+    #[structopt(long)]
+    pub prompt_missing: bool,
+
+    /// Prompt to change each var read from the askfile or answers file, keeping it on Enter.
+    #[structopt(long)]
+    pub only_changed_vars: bool,
+
+    /// Once every var is resolved, offer to edit each one before the run, keeping it on Enter.
+    #[structopt(long)]
+    pub review_vars: bool,
+
+    /// Prompt for this var even if another source has a value for it, like a stale askfile.
+    /// Can be given more than once.
+    #[structopt(long, value_name = "VAR", number_of_values = 1)]
+    pub reask: Vec<String>,
+
+    /// The signal sent to a job that runs past its `timeout`, by name or number.
+    #[structopt(long, default_value = "TERM")]
+    pub timeout_kill_signal: String,
+
+    /// Seconds to wait after `--timeout-kill-signal` before killing the job outright.
+    #[structopt(long, default_value = "10")]
+    pub kill_grace: u64,
+
+    /// Give up on an interactive prompt after this many seconds.
+    #[structopt(long)]
+    pub prompt_timeout: Option<u64>,
+
     /// Don't try to pull askable vars from env variables.
     #[structopt(short = "E", long)]
     pub no_allow_env: bool,
@@ -21,19 +86,338 @@ pub(crate) struct Opt {
     #[structopt(short, long)]
     pub ask_file: Option<String>,
 
-    /// One or more strings in the format `VARNAME=value`.
-    #[structopt(short = "w", long = "with-vars")]
+    /// A JSON (or `.toml`) file containing an object of `VARNAME: value` answers.
+    #[structopt(long)]
+    pub answers_file: Option<PathBuf>,
+
+    /// A `VARNAME_SECURE=value` formatted file of secrets, which must not be readable by others.
+    #[structopt(long)]
+    pub secrets_file: Option<PathBuf>,
+
+    /// After resolving vars, write the non-secure ones to this file in askfile format.
+    #[structopt(long, group = "env-dumps")]
+    pub env_file_out: Option<PathBuf>,
+
+    /// Also write secure vars to the `--env-file-out` and `--dump-env-on-failure` files.
+    #[structopt(long, requires = "env-dumps")]
+    pub include_secure: bool,
+
+    /// When a job fails, write the env it was given and its runner to `devmaker-failed-<job>.env`,
+    /// in the `--log-dir` if given.
+    #[structopt(long, group = "env-dumps")]
+    pub dump_env_on_failure: bool,
+
+    /// A variable in the format `VARNAME=value`, quoted if the value has spaces.  Repeat the
+    /// flag for more variables.
+    #[structopt(short = "w", long = "with-vars", number_of_values = 1)]
     pub ask_vars: Option<Vec<String>>,
 
+    /// Refuse secure variables given with `-w/--with-vars`, since they end up in shell history.
+    #[structopt(long)]
+    pub forbid_cli_secrets: bool,
+
+    /// Apply each job's `overrides` entry for this profile, such as `server` or `laptop`.  Jobs
+    /// with no entry for it are used as they are.
+    #[structopt(long)]
+    pub profile: Option<String>,
+
     /// A single job to run, ignoring dependencies.
     #[structopt(short, long)]
     pub single_job: Option<String>,
 
+    /// Skip the jobs scheduled before this one, assuming they've already run.
+    #[structopt(long, conflicts_with = "single-job")]
+    pub start_from: Option<String>,
+
+    /// Stop after this job, leaving the rest of the schedule unrun.
+    #[structopt(long, conflicts_with = "single-job")]
+    pub stop_after: Option<String>,
+
+    /// Choose the jobs to run from a menu; the jobs they depend on are run too.
+    #[structopt(long, conflicts_with_all = &["single-job", "start-from", "stop-after"])]
+    pub interactive_select: bool,
+
+    /// Run exactly these jobs, in this order, ignoring the schedule and their dependencies, like
+    /// following a runbook.  Comma separated.  The run isn't recorded for `--continue-from-last`.
+    #[structopt(
+        long,
+        use_delimiter = true,
+        number_of_values = 1,
+        conflicts_with_all = &["single-job", "start-from", "stop-after", "interactive-select", "jobs"]
+    )]
+    pub pipeline: Option<Vec<String>>,
+
+    /// Skip the jobs the last run of this root completed, resuming where it left off.
+    #[structopt(long, conflicts_with_all = &["single-job", "dry-run", "pipeline"])]
+    pub continue_from_last: bool,
+
+    /// Execute nothing, but go through the run as if these jobs failed and the rest succeeded,
+    /// to test tooling that reads devmaker's output and exit code.  Comma separated.
+    #[structopt(
+        long,
+        use_delimiter = true,
+        number_of_values = 1,
+        conflicts_with_all = &["single-job", "dry-run", "continue-from-last"]
+    )]
+    pub simulate_failures: Option<Vec<String>>,
+
+    /// When the run fails, run the `undo.sh` of each job it completed, in reverse order.
+    #[structopt(long, conflicts_with_all = &["single-job", "dry-run", "simulate-failures"])]
+    pub rollback: bool,
+
+    /// Run even if another run of this root holds its lock.
+    #[structopt(long)]
+    pub no_lock: bool,
+
+    /// Forget the jobs completed by earlier runs of this root, then exit.
+    #[structopt(long, conflicts_with = "continue-from-last")]
+    pub reset: bool,
+
+    /// Run up to this many jobs at once, each starting as soon as its dependencies complete.
+    #[structopt(short, long, default_value = "1")]
+    pub jobs: usize,
+
+    /// A shell command to run once every job has finished, with a summary of the run in its env.
+    #[structopt(long)]
+    pub on_complete: Option<String>,
+
+    /// What to do when a job fails: `abort` the run, `continue` with the jobs not depending on
+    /// it, or `ask` which to do.
+    #[structopt(long, default_value = "abort", possible_values = &["abort", "continue", "ask"])]
+    pub on_failure: FailurePolicy,
+
+    /// Run every job's `deps.sh` in a batch, in schedule order, before any main runner.
+    #[structopt(long)]
+    pub deps_first: bool,
+
+    /// Like `--deps-first`, but run all the `deps.sh` scripts concurrently.
+    #[structopt(long)]
+    pub parallel_deps: bool,
+
+    /// Don't run any job's `deps.sh`, only the main runners, for when dependencies are already
+    /// provisioned.
+    #[structopt(long, conflicts_with_all = &["deps-first", "parallel-deps", "only-deps"])]
+    pub skip_deps: bool,
+
+    /// Run only each job's `deps.sh`, skipping the main runners.  The run isn't recorded for
+    /// `--continue-from-last`.
+    #[structopt(
+        long,
+        conflicts_with_all = &["continue-from-last", "rollback", "deps-first", "parallel-deps"]
+    )]
+    pub only_deps: bool,
+
+    /// Only warn, rather than error, when a `depends` glob or tag matches no jobs.
+    #[structopt(long)]
+    pub allow_empty_depends: bool,
+
+    /// Re-run the single job each time Enter is pressed, until interrupted.
+    #[structopt(long, requires = "single-job")]
+    pub repeat: bool,
+
+    /// Discover jobs in nested directories too, naming them by their relative path.
+    #[structopt(short, long)]
+    pub recursive: bool,
+
+    /// With `--recursive`, how many directories deep to look for jobs.
+    #[structopt(long, requires = "recursive")]
+    pub max_discovery_depth: Option<usize>,
+
+    /// Discover jobs in symlinked directories, rather than ignoring them.
+    #[structopt(long)]
+    pub follow_symlinks: bool,
+
+    /// Don't show a progress bar while running jobs.
+    #[structopt(long)]
+    pub no_progress: bool,
+
+    /// Append each job's output to `<job>.log` in this directory, instead of the terminal.
+    #[structopt(long)]
+    pub log_dir: Option<PathBuf>,
+
+    /// Run every job through a login shell, so profile files like `~/.profile` are sourced.
+    #[structopt(long)]
+    pub login_shell: bool,
+
+    /// When to remove each job's temp dir: `always`, `on-success`, keeping it to debug a
+    /// failed job, or `never`.
+    #[structopt(
+        long,
+        default_value = "always",
+        possible_values = &["always", "on-success", "never"]
+    )]
+    pub temp_cleanup: TempCleanup,
+
+    /// Log each command devmaker runs at info level, with its working directory and the env it's
+    /// given, secure values redacted.
+    #[structopt(long)]
+    pub trace_exec: bool,
+
+    /// Prompt for this variable's value and store it in the keyring entry its `ask` entry names.
+    #[structopt(long, value_name = "VAR")]
+    pub keyring_set: Option<String>,
+
+    /// Don't start any more jobs once the run has taken this many seconds.  Jobs already
+    /// running are left to finish.
+    #[structopt(long, value_name = "SECS")]
+    pub max_runtime: Option<u64>,
+
+    /// Write Prometheus metrics about the run here, for `node_exporter`'s textfile collector.
+    #[structopt(long, conflicts_with_all = &["single-job", "dry-run"])]
+    pub metrics_file: Option<PathBuf>,
+
+    /// Write a JSON report here of each job's outcome and the env it was given, with secure
+    /// values redacted, for auditing which jobs saw which variables.
+    #[structopt(long, conflicts_with_all = &["single-job", "dry-run"])]
+    pub report_file: Option<PathBuf>,
+
+    /// Print only a one-line summary of the run, like `devmaker: 3 ok, 1 failed (db)`.
+    ///
+    /// Job output is discarded unless `--log-dir` is given.
+    #[structopt(long, conflicts_with_all = &["single-job", "dry-run"])]
+    pub oneline_summary: bool,
+
+    /// Set up for another user: jobs get this `USER`, and that user's `HOME` unless `--home` is given.
+    #[structopt(long)]
+    pub as_user: Option<String>,
+
+    /// The `HOME` given to jobs, instead of the current user's.
+    #[structopt(long)]
+    pub home: Option<PathBuf>,
+
+    /// Error, rather than warn, when dependent jobs set an env key to different values.
+    #[structopt(long)]
+    pub strict_env: bool,
+
+    /// Pass secure vars to jobs in a private file named by `DEVMAKER_SECRETS_FILE`, not the env.
+    #[structopt(long)]
+    pub secrets_as_file: bool,
+
+    /// The suffix marking a variable as secure.  Defaults to `_SECURE`.
+    #[structopt(long)]
+    pub secure_suffix: Option<String>,
+
     /// Sets all queried vars to empty strings.  Useful for testing.
     #[structopt(short = "e", long)]
     pub force_empty_vars: bool,
 
-    /// The root directory conatining all job specs.
+    /// The root directory conatining all job specs.  Required unless given to a subcommand.
     #[structopt(index = 1)]
-    pub script_root: PathBuf,
+    pub script_root: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub(crate) enum Command {
+    /// Run every static check against the job specs, without running or asking anything.
+    Doctor {
+        /// The root directory conatining all job specs.
+        script_root: PathBuf,
+    },
+    /// Print a single job's resolved spec as JSON.
+    Show {
+        /// The job to show.
+        job: String,
+        /// The root directory conatining all job specs.
+        script_root: PathBuf,
+        /// Also resolve the job's vars and include its env, with secure values redacted.
+        #[structopt(long)]
+        with_env: bool,
+    },
+    /// Print a JSON Schema for `info.json`, for editor completion and validation.
+    Schema,
+    /// Check that a single directory is a valid job, and print its spec as JSON.
+    ///
+    /// Exits with an error if it isn't a job, or its files can't be parsed.
+    Inspect {
+        /// The job's directory.
+        job_dir: PathBuf,
+    },
+}
+
+/// How a run carries on after a job fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FailurePolicy {
+    Abort,
+    Continue,
+    Ask,
+}
+
+impl FromStr for FailurePolicy {
+    type Err = Error;
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "abort" => Ok(Self::Abort),
+            "continue" => Ok(Self::Continue),
+            "ask" => Ok(Self::Ask),
+            _ => Err(anyhow!(format!("Unknown failure policy: {}", policy))),
+        }
+    }
+}
+
+/// When a job's temp dir is removed once it's done.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum TempCleanup {
+    #[default]
+    Always,
+    OnSuccess,
+    Never,
+}
+
+impl FromStr for TempCleanup {
+    type Err = Error;
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "always" => Ok(Self::Always),
+            "on-success" => Ok(Self::OnSuccess),
+            "never" => Ok(Self::Never),
+            _ => Err(anyhow!(format!("Unknown temp cleanup policy: {}", policy))),
+        }
+    }
+}
+
+impl Command {
+    pub const fn script_root(&self) -> Option<&PathBuf> {
+        match self {
+            Self::Doctor { script_root } | Self::Show { script_root, .. } => Some(script_root),
+            Self::Schema | Self::Inspect { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failure_policies_parse_by_name() {
+        assert_eq!(
+            "abort".parse::<FailurePolicy>().unwrap(),
+            FailurePolicy::Abort
+        );
+        assert_eq!(
+            "continue".parse::<FailurePolicy>().unwrap(),
+            FailurePolicy::Continue
+        );
+        assert_eq!("ask".parse::<FailurePolicy>().unwrap(), FailurePolicy::Ask);
+        let error = "keep-going".parse::<FailurePolicy>().unwrap_err();
+        assert_eq!(error.to_string(), "Unknown failure policy: keep-going");
+    }
+
+    #[test]
+    fn temp_cleanup_policies_parse_by_name() {
+        assert_eq!(
+            "always".parse::<TempCleanup>().unwrap(),
+            TempCleanup::Always
+        );
+        assert_eq!(
+            "on-success".parse::<TempCleanup>().unwrap(),
+            TempCleanup::OnSuccess
+        );
+        assert_eq!("never".parse::<TempCleanup>().unwrap(), TempCleanup::Never);
+        let error = "on-failure".parse::<TempCleanup>().unwrap_err();
+        assert_eq!(error.to_string(), "Unknown temp cleanup policy: on-failure");
+    }
 }
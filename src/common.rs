@@ -1,16 +1,194 @@
 use std::collections::HashMap;
+use std::fmt;
 
+use anyhow::{anyhow, Error, Result};
+
+pub(crate) const DEFAULT_SHELL: &str = "sh";
 pub(crate) const DEPS_SCRIPT: &str = "deps.sh";
+pub(crate) const DEVMAKER_PATH: &str = "DEVMAKER_PATH";
+pub(crate) const DEVMAKER_STATE_DIR: &str = "DEVMAKER_STATE_DIR";
 pub(crate) const INFO_FILE: &str = "info.json";
+pub(crate) const JOBS_FILES: [&str; 2] = ["jobs.json", "jobs.toml"];
+pub(crate) const JOBS_MANIFEST: &str = "devmaker.jobs";
+pub(crate) const CAPTURE_FILE: &str = "stdout.txt";
+pub(crate) const OUTPUT_FILE: &str = "outputs.env";
+pub(crate) const REDACTED: &str = "***";
+/// Env names devmaker sets for every job, overriding any value a job gives them.
+pub(crate) const RESERVED_ENV: [&str; 12] = [
+    "HOME",
+    "USER",
+    "USERNAME",
+    "SCRIPT_DIR",
+    "TMP_DIR",
+    "TEMP_DIR",
+    "DEVMAKER_SCHEDULED",
+    "DEVMAKER_COMPLETED",
+    "DEVMAKER_JOB_INDEX",
+    "DEVMAKER_JOB_TOTAL",
+    "DEVMAKER_OUTPUT",
+    "DEVMAKER_SECRETS_FILE",
+];
+/// The prefix of the env names carrying the outputs of a job's dependencies.
+pub(crate) const RESERVED_ENV_PREFIX: &str = "DEVMAKER_OUT_";
+pub(crate) const SECRETS_FILE: &str = "secrets.env";
+pub(crate) const SETTINGS_FILE: &str = "devmaker.toml";
+pub(crate) const UNDO_SCRIPT: &str = "undo.sh";
 pub(crate) const SECURE_SUFFIX: &str = "_SECURE";
 
 pub(crate) type EnvMap = HashMap<String, String>;
 
-pub(crate) fn secure_name_check<S: Into<String>>(name: S) -> (String, bool) {
+/// A failed run whose `--oneline-summary` line already says so, which `main` exits on quietly.
+#[derive(Debug)]
+pub(crate) struct Summarized(pub Error);
+
+impl fmt::Display for Summarized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for Summarized {}
+
+/// Strip the secure suffix (`_SECURE` unless `--secure-suffix` says otherwise) from a name.
+pub(crate) fn secure_name_check<S: Into<String>>(name: S, suffix: &str) -> (String, bool) {
     let mut name = name.into();
-    let has_secure_suffix = name.ends_with(SECURE_SUFFIX);
+    let has_secure_suffix = name.ends_with(suffix);
     if has_secure_suffix {
-        name = name.replace(SECURE_SUFFIX, "");
+        name.truncate(name.len() - suffix.len());
     }
     (name, has_secure_suffix)
 }
+
+/// The error for an unknown job name, suggesting the closest known names in case of a typo.
+pub(crate) fn unknown_job<'a, I: IntoIterator<Item = &'a String>>(name: &str, known: I) -> Error {
+    let max_distance = 1.max(name.len() / 3);
+    let mut close: Vec<(usize, &String)> = known
+        .into_iter()
+        .map(|candidate| (strsim::levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    close.sort();
+    let suggestions: Vec<String> = close
+        .iter()
+        .take(3)
+        .map(|(_, candidate)| format!("'{candidate}'"))
+        .collect();
+    if suggestions.is_empty() {
+        anyhow!(format!("Cannot locate job '{}'", name))
+    } else {
+        anyhow!(format!(
+            "Cannot locate job '{}'. Did you mean {}?",
+            name,
+            suggestions.join(" or ")
+        ))
+    }
+}
+
+/// Job names end up in paths, temp dir prefixes, and messages, so keep them plain.
+///
+/// Nested jobs are named by their relative path, so each `/`-separated part is checked.
+pub(crate) fn validate_job_name(name: &str) -> Result<()> {
+    let valid_part = |part: &str| {
+        !part.is_empty()
+            && !part.starts_with('.')
+            && !part.starts_with('-')
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+    };
+    if name.split('/').all(valid_part) {
+        Ok(())
+    } else {
+        Err(anyhow!(format!("Unsafe job name: '{}'", name)))
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    static LOGGED: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Keeps each thread's log records apart, so tests running at the same time don't mix.
+#[cfg(test)]
+struct ThreadLogger;
+
+#[cfg(test)]
+impl log::Log for ThreadLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        LOGGED.with(|logged| {
+            logged
+                .borrow_mut()
+                .push(format!("{}: {}", record.level(), record.args()));
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// What `f` logs on this thread, one `LEVEL: message` line per record.
+#[cfg(test)]
+pub(crate) fn capture_logs<F: FnOnce()>(f: F) -> Vec<String> {
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| {
+        log::set_logger(&ThreadLogger).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+    });
+    LOGGED.with(|logged| logged.borrow_mut().clear());
+    f();
+    LOGGED.with(std::cell::RefCell::take)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secure_name_check_strips_the_given_suffix() {
+        assert_eq!(
+            secure_name_check("TOKEN_SECURE", SECURE_SUFFIX),
+            ("TOKEN".to_owned(), true)
+        );
+        assert_eq!(
+            secure_name_check("TOKEN_SECURE", "_SECRET"),
+            ("TOKEN_SECURE".to_owned(), false)
+        );
+        assert_eq!(
+            secure_name_check("TOKEN_SECRET", "_SECRET"),
+            ("TOKEN".to_owned(), true)
+        );
+    }
+
+    fn known(names: &[&str]) -> Vec<String> {
+        names.iter().map(|&name| name.to_owned()).collect()
+    }
+
+    #[test]
+    fn unknown_job_suggests_near_misses() {
+        let jobs = known(&["foo", "food", "bar", "postgres"]);
+        assert_eq!(
+            unknown_job("foho", &jobs).to_string(),
+            "Cannot locate job 'foho'. Did you mean 'foo'?"
+        );
+        assert_eq!(
+            unknown_job("fod", &jobs).to_string(),
+            "Cannot locate job 'fod'. Did you mean 'foo' or 'food'?"
+        );
+        assert_eq!(
+            unknown_job("postgress", &jobs).to_string(),
+            "Cannot locate job 'postgress'. Did you mean 'postgres'?"
+        );
+    }
+
+    #[test]
+    fn unknown_job_suggests_nothing_for_a_different_name() {
+        let jobs = known(&["foo", "bar", "postgres"]);
+        assert_eq!(
+            unknown_job("kubernetes", &jobs).to_string(),
+            "Cannot locate job 'kubernetes'"
+        );
+    }
+}
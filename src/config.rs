@@ -1,95 +1,503 @@
 use std::convert::TryFrom;
-use std::fs::File;
+use std::env;
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::fs::{self, File};
 use std::io::{prelude::*, BufReader};
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::result::Result as StdResult;
+use std::sync::LazyLock;
+use std::time::Duration;
 
 use anyhow::{anyhow, Error, Result};
 use regex::Regex;
 
-use crate::cli::Opt;
-use crate::common::{secure_name_check, EnvMap};
+use crate::cli::{Command, FailurePolicy, Opt, TempCleanup};
+use crate::common::{secure_name_check, EnvMap, DEVMAKER_STATE_DIR, SECURE_SUFFIX};
+use crate::keyring::{Keyring, SystemKeyring};
 
+#[allow(clippy::struct_excessive_bools)] // Carries over Opt's flags.
 pub(crate) struct Config {
+    pub as_user: Option<String>,
     pub ask_file_vars: Option<EnvMap>,
     pub cmd_vars: Option<EnvMap>,
+    pub command: Option<Command>,
+    pub env_file_out: Option<PathBuf>,
+    pub home: Option<PathBuf>,
+    pub kill_grace: Duration,
+    pub kill_signal: i32,
+    pub jobs: usize,
+    pub keyring: Box<dyn Keyring>,
+    pub keyring_set: Option<String>,
+    pub log_dir: Option<PathBuf>,
+    pub max_discovery_depth: Option<usize>,
+    pub max_runtime: Option<Duration>,
+    pub metrics_file: Option<PathBuf>,
+    pub on_complete: Option<String>,
+    pub on_failure: FailurePolicy,
+    pub pipeline: Option<Vec<String>>,
+    pub profile: Option<String>,
+    pub prompt_timeout: Option<Duration>,
+    /// Vars to prompt for whatever other sources say, without the secure suffix.
+    pub reask: Vec<String>,
+    pub report_file: Option<PathBuf>,
     pub root_dir: PathBuf,
+    pub secure_suffix: String,
     pub single_job: Option<String>,
+    pub start_from: Option<String>,
+    pub stop_after: Option<String>,
+    pub simulate_failures: Option<Vec<String>>,
+    /// Where run state and locks are kept, or `None` if there's nowhere to keep them.
+    pub state_dir: Option<PathBuf>,
+    pub temp_cleanup: TempCleanup,
 
+    pub allow_empty_depends: bool,
     pub allow_env: bool,
+    pub check: bool,
+    pub check_vars: bool,
+    pub continue_from_last: bool,
+    pub deps_first: bool,
     pub dry_run: bool,
+    pub dump_env_on_failure: bool,
     pub empty_vars: bool,
+    pub eval_conditions: bool,
+    pub follow_symlinks: bool,
+    pub forbid_cli_secrets: bool,
+    pub include_secure: bool,
     pub interactive: bool,
+    pub interactive_select: bool,
+    pub list: bool,
+    pub login_shell: bool,
+    pub no_lock: bool,
+    pub no_progress: bool,
+    pub oneline_summary: bool,
+    pub only_deps: bool,
+    pub only_changed_vars: bool,
+    pub parallel_deps: bool,
+    pub skip_deps: bool,
+    pub print_levels_json: bool,
+    pub explain_schedule: bool,
+    pub prompt_missing: bool,
+    pub recursive: bool,
+    pub repeat: bool,
+    pub reset_state: bool,
+    pub review_vars: bool,
+    pub rollback: bool,
+    pub secrets_as_file: bool,
+    pub strict_env: bool,
+    pub trace_exec: bool,
+    pub tree: bool,
 }
 
 /// these functions are tough with the borrow checker.
 /// it's just map.get(name), but with option handling and reference balancing
 macro_rules! opt_map_helper {
-    ($map:expr, $name:expr) => {
+    ($map:expr, $name:expr, $suffix:expr) => {
         ($map.as_ref()?)
-            .get(&secure_name_check($name.as_ref()).0)
+            .get(&secure_name_check($name.as_ref(), &$suffix).0)
             .cloned()
     };
 }
 
 impl Config {
     pub fn get_cmd_var<S: AsRef<str>>(&self, name: S) -> Option<String> {
-        opt_map_helper!(&self.cmd_vars, name)
+        opt_map_helper!(&self.cmd_vars, name, self.secure_suffix)
     }
 
     pub fn get_file_var<S: AsRef<str>>(&self, name: S) -> Option<String> {
-        opt_map_helper!(&self.ask_file_vars, name)
+        opt_map_helper!(&self.ask_file_vars, name, self.secure_suffix)
+    }
+
+    /// Where run state and locks are kept: `DEVMAKER_STATE_DIR`, or under the user's data
+    /// directory.
+    pub fn state_dir(&self) -> Result<&Path> {
+        self.state_dir.as_deref().ok_or_else(|| {
+            anyhow!(format!(
+                "Cannot locate a data directory; set {DEVMAKER_STATE_DIR}"
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    /// The config of `devmaker <root> <flags>`.
+    ///
+    /// It has no state dir, so a test that records a run has to give it one of its own.
+    pub fn for_test(root: &Path, flags: &[&str]) -> Self {
+        use structopt::StructOpt;
+
+        let mut args = vec![OsStr::new("devmaker"), root.as_os_str()];
+        args.extend(flags.iter().map(OsStr::new));
+        Self {
+            state_dir: None,
+            ..Self::try_from(Opt::from_iter(args)).expect("test flags are valid")
+        }
     }
 }
 
 impl TryFrom<Opt> for Config {
     type Error = Error;
+    #[allow(clippy::too_many_lines)] // One line per option, so it grows with them.
     fn try_from(o: Opt) -> StdResult<Self, Self::Error> {
+        let allow_empty_depends = o.allow_empty_depends;
         let allow_env = !&o.no_allow_env;
-        let ask_file_vars = if let Some(file) = o.ask_file {
+        let secure_suffix = o.secure_suffix.unwrap_or_else(|| SECURE_SUFFIX.to_owned());
+        if secure_suffix.is_empty() {
+            return Err(anyhow!("The secure suffix can't be empty"));
+        }
+        let mut ask_file_vars = if let Some(file) = o.ask_file {
             parse_askfile(file)?
         } else {
             None
         };
+        if let Some(file) = o.answers_file {
+            ask_file_vars
+                .get_or_insert_with(EnvMap::new)
+                .extend(parse_answers_file(file, &secure_suffix)?);
+        }
+        if let Some(file) = o.secrets_file {
+            ask_file_vars
+                .get_or_insert_with(EnvMap::new)
+                .extend(parse_secrets_file(file, &secure_suffix)?);
+        }
         let cmd_vars = if let Some(pairs) = o.ask_vars {
-            parse_cmd_vars(pairs)?
+            parse_cmd_vars(pairs, &secure_suffix, o.forbid_cli_secrets)?
         } else {
             None
         };
+        let check = o.check;
+        let continue_from_last = o.continue_from_last;
+        let deps_first = o.deps_first || o.parallel_deps;
         let dry_run = o.dry_run;
         let empty_vars = o.force_empty_vars;
-        let interactive = o.interactive;
-        let root_dir: PathBuf = o.script_root;
+        let eval_conditions = o.eval_conditions;
+        let env_file_out = o.env_file_out;
+        let home = match (o.home, &o.as_user) {
+            (Some(home), _) => Some(home),
+            (None, Some(user)) => Some(user_home(user)?),
+            (None, None) => None,
+        };
+        let as_user = o.as_user;
+        let dump_env_on_failure = o.dump_env_on_failure;
+        let include_secure = o.include_secure;
+        let jobs = o.jobs.max(1);
+        let keyring: Box<dyn Keyring> = Box::new(SystemKeyring);
+        let keyring_set = o.keyring_set;
+        let kill_grace = Duration::from_secs(o.kill_grace);
+        let kill_signal = parse_signal(&o.timeout_kill_signal)?;
+        let on_complete = o.on_complete;
+        let on_failure = o.on_failure;
+        let check_vars = o.check_vars;
+        let interactive = o.interactive
+            || o.prompt_missing
+            || o.only_changed_vars
+            || o.review_vars
+            || !o.reask.is_empty();
+        let reask = o
+            .reask
+            .iter()
+            .map(|name| secure_name_check(name.as_str(), &secure_suffix).0)
+            .collect();
+        let review_vars = o.review_vars;
+        let rollback = o.rollback;
+        let only_changed_vars = o.only_changed_vars;
+        let interactive_select = o.interactive_select;
+        let prompt_missing = o.prompt_missing;
+        let no_lock = o.no_lock;
+        let no_progress = o.no_progress || o.oneline_summary;
+        let log_dir = o.log_dir;
+        let login_shell = o.login_shell;
+        let oneline_summary = o.oneline_summary;
+        let parallel_deps = o.parallel_deps;
+        let skip_deps = o.skip_deps;
+        let only_deps = o.only_deps;
+        let print_levels_json = o.print_levels_json;
+        let explain_schedule = o.explain_schedule;
+        let prompt_timeout = o.prompt_timeout.map(Duration::from_secs);
+        let recursive = o.recursive;
+        let max_discovery_depth = o.max_discovery_depth;
+        let max_runtime = o.max_runtime.map(Duration::from_secs);
+        let metrics_file = o.metrics_file;
+        let report_file = o.report_file;
+        let follow_symlinks = o.follow_symlinks;
+        let forbid_cli_secrets = o.forbid_cli_secrets;
+        let repeat = o.repeat;
+        let reset_state = o.reset;
+        let secrets_as_file = o.secrets_as_file;
+        let strict_env = o.strict_env;
+        let trace_exec = o.trace_exec;
+        let tree = o.tree;
+        let list = o.list;
+        let command = o.command;
+        let root_dir: PathBuf = command
+            .as_ref()
+            .and_then(Command::script_root)
+            .cloned()
+            .or(o.script_root)
+            .ok_or_else(|| anyhow!("No script root given"))
+            .and_then(|root| canonical_root(&root))?;
+        let pipeline = o.pipeline;
+        let profile = o.profile;
         let single_job = o.single_job;
+        let start_from = o.start_from;
+        let stop_after = o.stop_after;
+        let simulate_failures = o.simulate_failures;
+        let state_dir = env::var_os(DEVMAKER_STATE_DIR)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| dirs::data_dir().map(|dir| dir.join("devmaker").join("state")));
+        let temp_cleanup = o.temp_cleanup;
 
         Ok(Self {
-            allow_env,
+            as_user,
             ask_file_vars,
             cmd_vars,
+            command,
+            env_file_out,
+            home,
+            kill_grace,
+            kill_signal,
+            jobs,
+            keyring,
+            keyring_set,
+            log_dir,
+            max_discovery_depth,
+            max_runtime,
+            metrics_file,
+            on_complete,
+            on_failure,
+            pipeline,
+            profile,
+            prompt_timeout,
+            reask,
+            report_file,
+            root_dir,
+            secure_suffix,
+            single_job,
+            start_from,
+            stop_after,
+            simulate_failures,
+            state_dir,
+            temp_cleanup,
+            allow_empty_depends,
+            allow_env,
+            check,
+            check_vars,
+            continue_from_last,
+            deps_first,
             dry_run,
+            dump_env_on_failure,
             empty_vars,
+            eval_conditions,
+            follow_symlinks,
+            forbid_cli_secrets,
+            include_secure,
             interactive,
-            root_dir,
-            single_job,
+            interactive_select,
+            list,
+            login_shell,
+            no_lock,
+            no_progress,
+            oneline_summary,
+            only_deps,
+            only_changed_vars,
+            parallel_deps,
+            skip_deps,
+            print_levels_json,
+            explain_schedule,
+            prompt_missing,
+            recursive,
+            repeat,
+            reset_state,
+            review_vars,
+            rollback,
+            secrets_as_file,
+            strict_env,
+            trace_exec,
+            tree,
         })
     }
 }
 
+/// Resolve the script root to an absolute path free of symlinks, so every path built on it agrees.
+fn canonical_root(root: &Path) -> Result<PathBuf> {
+    let canonical = fs::canonicalize(root)
+        .map_err(|e| anyhow!(format!("Cannot use script root {}: {}", root.display(), e)))?;
+    if canonical.is_dir() {
+        Ok(canonical)
+    } else {
+        Err(anyhow!(format!(
+            "Script root {} is not a directory",
+            root.display()
+        )))
+    }
+}
+
+/// Parse a signal given by number, or by name with or without the `SIG` prefix.
+fn parse_signal(signal: &str) -> Result<i32> {
+    if let Ok(number) = signal.parse() {
+        return Ok(number);
+    }
+    let name = signal.to_uppercase();
+    match name.strip_prefix("SIG").unwrap_or(&name) {
+        "HUP" => Ok(libc::SIGHUP),
+        "INT" => Ok(libc::SIGINT),
+        "QUIT" => Ok(libc::SIGQUIT),
+        "KILL" => Ok(libc::SIGKILL),
+        "USR1" => Ok(libc::SIGUSR1),
+        "USR2" => Ok(libc::SIGUSR2),
+        "TERM" => Ok(libc::SIGTERM),
+        _ => Err(anyhow!(format!("Unknown signal: {}", signal))),
+    }
+}
+
+/// Look up a user's home directory in the system user database.
+fn user_home(user: &str) -> Result<PathBuf> {
+    let name = CString::new(user)?;
+    // getpwnam's result is only valid until the next call, so copy the path out right away.
+    // We're still starting up, so nothing else is calling it.
+    let home = unsafe {
+        let entry = libc::getpwnam(name.as_ptr());
+        if entry.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr((*entry).pw_dir).to_owned())
+        }
+    };
+    home.map(|home| PathBuf::from(OsString::from_vec(home.into_bytes())))
+        .ok_or_else(|| anyhow!(format!("Unknown user: {}", user)))
+}
+
 fn parse_askfile<P: AsRef<Path>>(file: P) -> Result<Option<EnvMap>> {
     debug!("Parsing askfile: {}", file.as_ref().display());
     let reader = BufReader::new(File::open(file)?);
     let pairs: Vec<_> = reader.lines().collect::<Result<_, _>>()?;
-    parse_var_strings(pairs)
+    parse_var_strings(pairs, "askfile")
+}
+
+fn parse_answers_file<P: AsRef<Path>>(file: P, secure_suffix: &str) -> Result<EnvMap> {
+    let file = file.as_ref();
+    debug!("Parsing answers file: {}", file.display());
+    let contents = fs::read_to_string(file)?;
+    let is_toml = file.extension() == Some(OsStr::new("toml"));
+    let pairs: Vec<(String, String)> = if is_toml {
+        let table: toml::value::Table = toml::from_str(&contents)?;
+        table
+            .into_iter()
+            .map(|(k, v)| Ok((k.clone(), toml_answer(&k, v)?)))
+            .collect::<Result<_>>()?
+    } else {
+        let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&contents)?;
+        object
+            .into_iter()
+            .map(|(k, v)| Ok((k.clone(), json_answer(&k, v)?)))
+            .collect::<Result<_>>()?
+    };
+    Ok(pairs
+        .into_iter()
+        .map(|(k, v)| (secure_name_check(k, secure_suffix).0, v))
+        .collect())
+}
+
+/// Parse a secrets file, refusing to if anyone besides the owner could read it.
+///
+/// Values are never echoed, so errors only point at line numbers.
+fn parse_secrets_file<P: AsRef<Path>>(file: P, secure_suffix: &str) -> Result<EnvMap> {
+    let file = file.as_ref();
+    debug!("Parsing secrets file: {}", file.display());
+    let mode = fs::metadata(file)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(anyhow!(format!(
+            "Refusing to read secrets file {}: permissions {:o} are too open, use 600",
+            file.display(),
+            mode & 0o777
+        )));
+    }
+    let reader = BufReader::new(File::open(file)?);
+    let mut map = EnvMap::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let (key, value) = try_parse_var_string(&line, "secrets file")
+            .ok()
+            .flatten()
+            .ok_or_else(|| anyhow!(format!("Unparseable line {} in secrets file", number + 1)))?;
+        let (name, is_secure) = secure_name_check(key, secure_suffix);
+        if !is_secure {
+            return Err(anyhow!(format!(
+                "Secrets file may only contain {} variables, found: {}",
+                secure_suffix, name
+            )));
+        }
+        map.insert(name, value);
+    }
+    Ok(map)
 }
 
-fn parse_cmd_vars(pairs: Vec<String>) -> Result<Option<EnvMap>> {
-    parse_var_strings(pairs)
+fn json_answer(key: &str, value: serde_json::Value) -> Result<String> {
+    use serde_json::Value;
+    match value {
+        Value::String(s) => Ok(s),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Null => Ok(String::new()),
+        Value::Array(_) | Value::Object(_) => Err(anyhow!(format!(
+            "Answer for {} must be a string, number, or bool",
+            key
+        ))),
+    }
+}
+
+fn toml_answer(key: &str, value: toml::Value) -> Result<String> {
+    use toml::Value;
+    match value {
+        Value::String(s) => Ok(s),
+        Value::Integer(i) => Ok(i.to_string()),
+        Value::Float(f) => Ok(f.to_string()),
+        Value::Boolean(b) => Ok(b.to_string()),
+        Value::Datetime(d) => Ok(d.to_string()),
+        Value::Array(_) | Value::Table(_) => Err(anyhow!(format!(
+            "Answer for {} must be a string, number, or bool",
+            key
+        ))),
+    }
 }
 
-fn parse_var_strings<I: IntoIterator<Item = String>>(iter: I) -> Result<Option<EnvMap>> {
+/// Parse `-w/--with-vars` pairs, warning about secure ones, or refusing them if `forbid_secure`.
+fn parse_cmd_vars(
+    pairs: Vec<String>,
+    secure_suffix: &str,
+    forbid_secure: bool,
+) -> Result<Option<EnvMap>> {
+    let mut vars = EnvMap::new();
+    for (key, value) in parse_var_strings(pairs, "--with-vars")?.unwrap_or_default() {
+        let (name, is_secure) = secure_name_check(key.as_str(), secure_suffix);
+        if is_secure {
+            if forbid_secure {
+                return Err(anyhow!(format!(
+                    "Secure variable {} can't be given with --with-vars; use an askfile, a \
+                     secrets file, the keyring or a prompt instead",
+                    key
+                )));
+            }
+            warn!(
+                "Secure variable {key} was given with --with-vars, which leaves it in your shell \
+                 history; consider an askfile, a secrets file, the keyring or a prompt instead"
+            );
+        }
+        vars.insert(name, value);
+    }
+    Ok(Some(vars))
+}
+
+fn parse_var_strings<I: IntoIterator<Item = String>>(
+    iter: I,
+    from: &str,
+) -> Result<Option<EnvMap>> {
     let mut map = EnvMap::new();
     for pair in iter {
-        if let Some((key, value)) = try_parse_var_string(&pair, "askfile")? {
+        if let Some((key, value)) = try_parse_var_string(&pair, from)? {
             // Overwrite conflicting lines
             map.insert(key, value);
         }
@@ -97,23 +505,299 @@ fn parse_var_strings<I: IntoIterator<Item = String>>(iter: I) -> Result<Option<E
     Ok(Some(map))
 }
 
-fn try_parse_var_string(line: &str, from: &str) -> Result<Option<(String, String)>> {
-    let pattern = Regex::new(r"^\s*([A-Z\d][A-Z\d_]+)\s*=\s*(.+?)\s*$")?;
-    let captures = pattern.captures(line).ok_or(anyhow!(format!(
-        "Unparseable line found in {}: {}",
-        from, line
-    )))?;
+static VAR_STRING_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\s*([A-Z\d][A-Z\d_]+)\s*=\s*(.+?)\s*$").expect("var string pattern is valid")
+});
+
+pub(crate) fn try_parse_var_string(line: &str, from: &str) -> Result<Option<(String, String)>> {
+    let captures = VAR_STRING_PATTERN
+        .captures(line)
+        .ok_or_else(|| anyhow!(format!("Unparseable line found in {}: {}", from, line)))?;
     // The groups are not optional
     let key = captures
         .get(1)
         .ok_or_else(|| anyhow!("Capture group 1 did not match"))?
         .as_str()
         .to_owned();
-    let value = captures
-        .get(2)
-        .ok_or_else(|| anyhow!("Capture group 2 did not match"))?
-        .as_str()
-        .to_owned();
+    let value = read_value(
+        captures
+            .get(2)
+            .ok_or_else(|| anyhow!("Capture group 2 did not match"))?
+            .as_str(),
+    )?;
 
     Ok(Some((key, value)))
 }
+
+/// Values written as `@path` are read from that file; `@@` escapes a literal leading `@`.
+fn read_value(raw: &str) -> Result<String> {
+    if let Some(literal) = raw.strip_prefix("@@") {
+        Ok(format!("@{literal}"))
+    } else if let Some(path) = raw.strip_prefix('@') {
+        debug!("Reading value from file: {path}");
+        Ok(normalize_file_value(&fs::read_to_string(path)?))
+    } else {
+        Ok(raw.to_owned())
+    }
+}
+
+/// Normalize CRLF line endings and drop a single trailing newline.
+///
+/// These values are put into the env verbatim, so a stray `\r` or newline would stick around.
+fn normalize_file_value(contents: &str) -> String {
+    let value = contents.replace("\r\n", "\n");
+    value
+        .strip_suffix('\n')
+        .map_or_else(|| value.clone(), ToOwned::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    use crate::common::capture_logs;
+
+    #[test]
+    fn var_strings_are_trimmed_around_the_equals_sign() {
+        let parsed = try_parse_var_string("  DB_HOST = db.local  ", "askfile").unwrap();
+        assert_eq!(parsed, Some(("DB_HOST".to_owned(), "db.local".to_owned())));
+        let escaped = try_parse_var_string("AT=@@home", "askfile").unwrap();
+        assert_eq!(escaped, Some(("AT".to_owned(), "@home".to_owned())));
+    }
+
+    #[test]
+    fn malformed_var_strings_are_an_error() {
+        for line in &["lower=case", "NO_VALUE=", "X=short key", "MISSING_EQUALS"] {
+            let error = try_parse_var_string(line, "askfile").unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("Unparseable line found in askfile: {line}")
+            );
+        }
+    }
+
+    #[test]
+    fn answers_files_take_scalars_and_drop_the_secure_suffix() {
+        let dir = TempDir::new("devmaker-test").unwrap();
+        let json = dir.path().join("answers.json");
+        fs::write(
+            &json,
+            r#"{"NAME": "app", "PORT": 8080, "DEBUG": true, "EMPTY": null, "TOKEN_SECURE": "x"}"#,
+        )
+        .unwrap();
+        let toml = dir.path().join("answers.toml");
+        fs::write(
+            &toml,
+            "NAME = \"app\"\nPORT = 8080\nDEBUG = true\nEMPTY = \"\"\nTOKEN_SECURE = \"x\"\n",
+        )
+        .unwrap();
+        for file in &[json, toml] {
+            let answers = parse_answers_file(file, SECURE_SUFFIX).unwrap();
+            assert_eq!(answers["NAME"], "app");
+            assert_eq!(answers["PORT"], "8080");
+            assert_eq!(answers["DEBUG"], "true");
+            assert_eq!(answers["EMPTY"], "");
+            assert_eq!(answers["TOKEN"], "x");
+        }
+    }
+
+    #[test]
+    fn nested_answers_are_an_error() {
+        let dir = TempDir::new("devmaker-test").unwrap();
+        let file = dir.path().join("answers.json");
+        fs::write(&file, r#"{"LIST": [1, 2]}"#).unwrap();
+        let error = parse_answers_file(&file, SECURE_SUFFIX).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Answer for LIST must be a string, number, or bool"
+        );
+    }
+
+    #[test]
+    fn answers_file_values_override_the_askfile() {
+        let dir = TempDir::new("devmaker-test").unwrap();
+        let askfile = dir.path().join("askfile");
+        fs::write(&askfile, "NAME=from-askfile\nPORT=80\n").unwrap();
+        let answers = dir.path().join("answers.toml");
+        fs::write(&answers, "NAME = \"from-answers\"\n").unwrap();
+        let config = Config::for_test(
+            dir.path(),
+            &[
+                "--ask-file",
+                askfile.to_str().unwrap(),
+                "--answers-file",
+                answers.to_str().unwrap(),
+            ],
+        );
+        assert_eq!(config.get_file_var("NAME").as_deref(), Some("from-answers"));
+        assert_eq!(config.get_file_var("PORT").as_deref(), Some("80"));
+    }
+
+    fn secrets_file(contents: &str, mode: u32) -> (TempDir, PathBuf) {
+        let dir = TempDir::new("devmaker-test").unwrap();
+        let file = dir.path().join("secrets.env");
+        fs::write(&file, contents).unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(mode)).unwrap();
+        (dir, file)
+    }
+
+    #[test]
+    fn secrets_files_hold_secure_vars() {
+        let (_dir, file) = secrets_file("API_TOKEN_SECURE=abc\n", 0o600);
+        let secrets = parse_secrets_file(&file, SECURE_SUFFIX).unwrap();
+        assert_eq!(secrets["API_TOKEN"], "abc");
+    }
+
+    #[test]
+    fn secrets_files_others_can_read_are_refused() {
+        let (_dir, file) = secrets_file("API_TOKEN_SECURE=abc\n", 0o644);
+        let error = parse_secrets_file(&file, SECURE_SUFFIX).unwrap_err();
+        assert!(error
+            .to_string()
+            .ends_with("permissions 644 are too open, use 600"));
+    }
+
+    #[test]
+    fn secrets_files_refuse_other_vars_without_echoing_values() {
+        let (_dir, file) = secrets_file("API_TOKEN_SECURE=abc\nHOST=db\n", 0o600);
+        let error = parse_secrets_file(&file, SECURE_SUFFIX).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Secrets file may only contain _SECURE variables, found: HOST"
+        );
+
+        let (_dir, file) = secrets_file("API_TOKEN_SECURE abc\n", 0o600);
+        let error = parse_secrets_file(&file, SECURE_SUFFIX).unwrap_err();
+        assert_eq!(error.to_string(), "Unparseable line 1 in secrets file");
+    }
+
+    #[test]
+    fn file_values_are_normalized() {
+        assert_eq!(normalize_file_value("one\r\ntwo\r\n"), "one\ntwo");
+        assert_eq!(normalize_file_value("kept\n\n"), "kept\n");
+        assert_eq!(normalize_file_value("bare"), "bare");
+
+        let dir = TempDir::new("devmaker-test").unwrap();
+        let file = dir.path().join("key.pem");
+        fs::write(&file, "-----BEGIN-----\r\nabc\r\n").unwrap();
+        let value = read_value(&format!("@{}", file.display())).unwrap();
+        assert_eq!(value, "-----BEGIN-----\nabc");
+    }
+
+    #[test]
+    fn secure_suffix_can_be_customized_but_not_emptied() {
+        use structopt::StructOpt;
+
+        let (_dir, file) = secrets_file("API_TOKEN_SECRET=abc\n", 0o600);
+        let secrets = parse_secrets_file(&file, "_SECRET").unwrap();
+        assert_eq!(secrets["API_TOKEN"], "abc");
+        let error = parse_secrets_file(&file, SECURE_SUFFIX).unwrap_err();
+        assert!(error.to_string().ends_with("found: API_TOKEN_SECRET"));
+
+        let dir = TempDir::new("devmaker-test").unwrap();
+        let config = Config::for_test(dir.path(), &["--secure-suffix", "_SECRET"]);
+        assert_eq!(config.secure_suffix, "_SECRET");
+        let opt = Opt::from_iter(vec![
+            OsStr::new("devmaker"),
+            dir.path().as_os_str(),
+            OsStr::new("--secure-suffix="),
+        ]);
+        let error = Config::try_from(opt).map(|_| ()).unwrap_err();
+        assert_eq!(error.to_string(), "The secure suffix can't be empty");
+    }
+
+    #[test]
+    fn secure_vars_on_the_command_line_warn_or_are_refused() {
+        let pairs = || vec!["TOKEN_SECURE=hunter2".to_owned(), "HOST=db".to_owned()];
+        let mut vars = None;
+        let logged = capture_logs(|| {
+            vars = parse_cmd_vars(pairs(), SECURE_SUFFIX, false).unwrap();
+        });
+        let vars = vars.unwrap();
+        assert_eq!(vars["TOKEN"], "hunter2");
+        assert_eq!(vars["HOST"], "db");
+        let warnings: Vec<&String> = logged
+            .iter()
+            .filter(|line| line.starts_with("WARN: "))
+            .collect();
+        assert_eq!(warnings.len(), 1, "{logged:?}");
+        assert!(warnings[0]
+            .starts_with("WARN: Secure variable TOKEN_SECURE was given with --with-vars"));
+        assert!(!warnings[0].contains("hunter2"));
+
+        let error = parse_cmd_vars(pairs(), SECURE_SUFFIX, true).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Secure variable TOKEN_SECURE can't be given with --with-vars; "));
+        assert!(!error.to_string().contains("hunter2"));
+        assert!(parse_cmd_vars(vec!["HOST=db".to_owned()], SECURE_SUFFIX, true).is_ok());
+    }
+
+    #[test]
+    fn with_vars_takes_one_pair_per_flag() {
+        use structopt::StructOpt;
+
+        let dir = TempDir::new("devmaker-test").unwrap();
+        let config = Config::for_test(
+            dir.path(),
+            &[
+                "-w",
+                "GREETING=hello world",
+                "--with-vars",
+                "PORT=5432",
+                "-w",
+                "PORT=8080",
+            ],
+        );
+        let vars = config.cmd_vars.unwrap();
+        assert_eq!(vars["GREETING"], "hello world");
+        assert_eq!(vars["PORT"], "8080");
+        assert_eq!(vars.len(), 2);
+
+        let args = ["devmaker", "-w", "A=1", "B=2"];
+        let opt = Opt::from_iter_safe(&args).unwrap();
+        assert_eq!(opt.ask_vars.unwrap(), ["A=1"]);
+        assert_eq!(opt.script_root, Some(PathBuf::from("B=2")));
+    }
+
+    #[test]
+    fn relative_and_symlinked_roots_resolve_to_the_same_path() {
+        let dir = TempDir::new("devmaker-test").unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir(&root).unwrap();
+        let canonical = canonical_root(&root).unwrap();
+        assert!(canonical.is_absolute());
+
+        // Enough `..` to reach `/` from wherever the tests run, then down to the root.
+        let depth = std::env::current_dir().unwrap().components().count();
+        let relative: PathBuf = std::iter::repeat_n(Path::new(".."), depth)
+            .collect::<PathBuf>()
+            .join(root.strip_prefix("/").unwrap());
+        assert!(relative.is_relative());
+        assert_eq!(canonical_root(&relative).unwrap(), canonical);
+
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&root, &link).unwrap();
+        assert_eq!(canonical_root(&link).unwrap(), canonical);
+    }
+
+    #[test]
+    fn roots_must_be_existing_directories() {
+        let dir = TempDir::new("devmaker-test").unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+        assert_eq!(
+            canonical_root(&file).unwrap_err().to_string(),
+            format!("Script root {} is not a directory", file.display())
+        );
+
+        let dangling = dir.path().join("dangling");
+        std::os::unix::fs::symlink(dir.path().join("missing"), &dangling).unwrap();
+        assert!(canonical_root(&dangling)
+            .unwrap_err()
+            .to_string()
+            .starts_with(&format!("Cannot use script root {}: ", dangling.display())));
+    }
+}
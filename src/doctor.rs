@@ -0,0 +1,338 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use console::Style;
+
+use crate::common::{secure_name_check, validate_job_name, EnvMap, SETTINGS_FILE};
+use crate::config::Config;
+use crate::jobs::{locate_runner, JobSpec, ReadyJob};
+use crate::settings::{load_root_settings, RootSettings, SecurePatterns};
+use crate::vars::{check_ask_keys, fill_asked};
+use crate::{
+    add_env_from_depends, check_self_depends, dep_tag, discover_jobs, env_conflicts,
+    expand_depends, is_glob, merge_env_from, parse_discovered_job, reserved_env_collisions,
+    schedule_specs, split_disabled, JobsFiles,
+};
+
+#[derive(Default)]
+struct DoctorReport {
+    lines: Vec<String>,
+    failures: usize,
+}
+
+impl DoctorReport {
+    fn pass<S: AsRef<str>>(&mut self, message: S) {
+        self.lines.push(format!(
+            "{} {}",
+            pass_style().apply_to("[ok]  "),
+            message.as_ref()
+        ));
+    }
+
+    fn warn<S: AsRef<str>>(&mut self, message: S) {
+        self.lines.push(format!(
+            "{} {}",
+            warn_style().apply_to("[warn]"),
+            message.as_ref()
+        ));
+    }
+
+    fn fail<S: AsRef<str>>(&mut self, message: S) {
+        self.failures += 1;
+        self.lines.push(format!(
+            "{} {}",
+            fail_style().apply_to("[FAIL]"),
+            message.as_ref()
+        ));
+    }
+
+    fn check<S: AsRef<str>>(&mut self, result: Result<()>, message: S) {
+        match result {
+            Ok(()) => self.pass(message),
+            Err(e) => self.fail(format!("{}: {}", message.as_ref(), e)),
+        }
+    }
+
+    /// Report env problems as failures with `--strict-env`, as a run would, or else warnings.
+    fn env_problems(&mut self, problems: Vec<String>, strict: bool, message: &str) {
+        if problems.is_empty() {
+            self.pass(message);
+        }
+        for problem in problems {
+            if strict {
+                self.fail(problem);
+            } else {
+                self.warn(problem);
+            }
+        }
+    }
+}
+
+/// Report env keys set to different values by dependent jobs, as a run would warn about them.
+///
+/// Nothing is asked for, so every var stands in as empty, and only literal env can conflict.
+fn check_env_conflicts(scheduled: Vec<JobSpec>, config: &Config, report: &mut DoctorReport) {
+    let suffix = &config.secure_suffix;
+    let answers: EnvMap = scheduled
+        .iter()
+        .flat_map(|spec| spec.get_ask_vars(suffix))
+        .map(|var| {
+            (
+                secure_name_check(var.name.as_str(), suffix).0,
+                String::new(),
+            )
+        })
+        .collect();
+    let queue: Result<Vec<ReadyJob>> = scheduled
+        .into_iter()
+        .map(|spec| fill_asked(spec, &answers, suffix, &SecurePatterns::default()))
+        .collect();
+    match queue {
+        Ok(mut queue) => {
+            merge_env_from(&mut queue);
+            report.env_problems(
+                env_conflicts(&queue),
+                config.strict_env,
+                "Dependent jobs agree on their env",
+            );
+        }
+        Err(e) => report.fail(format!("Preparing the env of jobs: {e}")),
+    }
+}
+
+/// Read the root's settings, reporting a file that can't be read and unknown jobs in `order`.
+fn check_settings(root: &Path, names: &[&String], report: &mut DoctorReport) -> RootSettings {
+    let settings = load_root_settings(root).unwrap_or_else(|e| {
+        report.fail(format!("Reading {SETTINGS_FILE}: {e}"));
+        RootSettings::default()
+    });
+    if let Err(e) = settings.secure_patterns() {
+        report.fail(format!("Reading {SETTINGS_FILE}: {e}"));
+    }
+    for name in settings.unknown_ordered(names) {
+        report.warn(format!(
+            "Job '{name}' in the order of {SETTINGS_FILE} does not exist"
+        ));
+    }
+    settings
+}
+
+/// Report dependencies on unknown jobs, and otherwise whether the jobs can be scheduled and
+/// agree on their env.
+fn check_schedule(
+    specs: &[JobSpec],
+    known: &HashSet<&String>,
+    settings: &RootSettings,
+    config: &Config,
+    report: &mut DoctorReport,
+) {
+    let mut dangling = false;
+    for spec in specs {
+        if let Err(e) = check_self_depends(spec) {
+            dangling = true;
+            report.fail(e.to_string());
+        }
+        for dep in spec
+            .depends
+            .iter()
+            .filter(|dep| !is_glob(dep) && dep_tag(dep).is_none() && !known.contains(dep))
+        {
+            dangling = true;
+            report.fail(format!(
+                "Job '{}' depends on unknown job '{}'",
+                spec.name, dep
+            ));
+        }
+    }
+    if !dangling {
+        let scheduled = split_disabled(specs.to_vec()).and_then(|(mut enabled, _)| {
+            add_env_from_depends(&mut enabled)?;
+            expand_depends(&mut enabled, config)?;
+            schedule_specs(&enabled, &settings.order)
+        });
+        match scheduled {
+            Ok(scheduled) => {
+                report.pass("Jobs can be scheduled without cycles");
+                check_env_conflicts(scheduled, config, report);
+            }
+            Err(e) => report.fail(format!("Jobs can be scheduled without cycles: {e}")),
+        }
+    }
+}
+
+/// Perform every static check on the job root, then print a consolidated report.
+///
+/// Nothing is run and no variables are asked for.  Returns an error if any check failed.
+pub(crate) fn run_doctor(config: &Config) -> Result<()> {
+    let root = &config.root_dir;
+    let mut report = DoctorReport::default();
+
+    let jobs = match discover_jobs(root, config) {
+        Ok(jobs) => {
+            report.pass(format!("Discovered {} jobs", jobs.len()));
+            jobs
+        }
+        Err(e) => {
+            report.fail(format!("Discovering jobs: {e}"));
+            Vec::new()
+        }
+    };
+
+    for (name, _) in &jobs {
+        if let Err(e) = validate_job_name(name) {
+            report.fail(e.to_string());
+        }
+    }
+
+    let names: Vec<&String> = jobs.iter().map(|(name, _)| name).collect();
+    let settings = check_settings(root, &names, &mut report);
+
+    let mut jobs_files = JobsFiles::default();
+    let specs: Vec<JobSpec> = jobs
+        .iter()
+        .filter_map(|(name, job_root)| {
+            let profile = config.profile.as_deref();
+            match parse_discovered_job(name, job_root, root, profile, &mut jobs_files) {
+                Ok(spec) => Some(spec),
+                Err(e) => {
+                    report.fail(format!("Job '{name}' could not be parsed: {e}"));
+                    None
+                }
+            }
+        })
+        .collect();
+
+    for spec in &specs {
+        let runner = locate_runner(
+            &root.join(&spec.dir),
+            &spec.name,
+            spec.inline_run.as_ref(),
+            spec.script.as_deref(),
+            spec.runner.as_deref(),
+            spec.exec.as_deref(),
+        );
+        report.check(
+            runner.map(drop),
+            format!("Job '{}' has a runner", spec.name),
+        );
+        for var in &spec.ask_for_vars {
+            if let Err(e) = check_ask_keys(var, &settings.resolvers) {
+                report.fail(format!("Job '{}': {}", spec.name, e));
+            }
+        }
+    }
+
+    let known: HashSet<&String> = jobs.iter().map(|(name, _)| name).collect();
+    report.env_problems(
+        reserved_env_collisions(&specs, &config.secure_suffix),
+        config.strict_env,
+        "No job sets env devmaker reserves",
+    );
+    check_schedule(&specs, &known, &settings, config, &mut report);
+
+    if let Some(file_vars) = &config.ask_file_vars {
+        let asked: HashSet<String> = specs
+            .iter()
+            .flat_map(|spec| spec.get_ask_vars(&config.secure_suffix))
+            .map(|var| secure_name_check(var.name.as_str(), &config.secure_suffix).0)
+            .collect();
+        let mut unused: Vec<&String> = file_vars
+            .keys()
+            .filter(|key| {
+                !asked.contains(&secure_name_check(key.as_str(), &config.secure_suffix).0)
+            })
+            .collect();
+        unused.sort();
+        for key in unused {
+            report.warn(format!("Askfile key '{key}' is not asked for by any job"));
+        }
+    }
+
+    for line in &report.lines {
+        println!("{line}");
+    }
+    if report.failures == 0 {
+        println!("{}", pass_style().apply_to("All checks passed"));
+        Ok(())
+    } else {
+        Err(anyhow!(format!(
+            "Doctor found {} problem(s)",
+            report.failures
+        )))
+    }
+}
+
+#[inline]
+fn pass_style() -> Style {
+    Style::new().green()
+}
+
+#[inline]
+fn warn_style() -> Style {
+    Style::new().yellow()
+}
+
+#[inline]
+fn fail_style() -> Style {
+    Style::new().red().bold()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    use crate::common::INFO_FILE;
+
+    /// Run the doctor on a root holding a job directory with each given `info.json`.
+    fn doctor(jobs: &[(&str, &str)], flags: &[&str]) -> Result<()> {
+        let root = TempDir::new("devmaker-test").unwrap();
+        for (name, info) in jobs {
+            let dir = root.path().join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(INFO_FILE), info).unwrap();
+        }
+        run_doctor(&Config::for_test(root.path(), flags))
+    }
+
+    #[test]
+    fn a_clean_root_passes() {
+        let jobs = [
+            ("a", r#"{"run": "true", "env": {"SHARED": "1"}}"#),
+            ("b", r#"{"run": "true", "depends": ["a"]}"#),
+        ];
+        doctor(&jobs, &["--strict-env"]).unwrap();
+    }
+
+    #[test]
+    fn dangling_deps_and_missing_runners_fail() {
+        let dangling = [("a", r#"{"run": "true", "depends": ["missing"]}"#)];
+        let error = doctor(&dangling, &[]).unwrap_err();
+        assert_eq!(error.to_string(), "Doctor found 1 problem(s)");
+
+        let no_runner = [("a", r#"{"runner": "missing.sh"}"#)];
+        let error = doctor(&no_runner, &[]).unwrap_err();
+        assert_eq!(error.to_string(), "Doctor found 1 problem(s)");
+    }
+
+    #[test]
+    fn env_problems_fail_only_with_strict_env() {
+        let reserved = [("a", r#"{"run": "true", "env": {"HOME": "/elsewhere"}}"#)];
+        let conflicting = [
+            ("a", r#"{"run": "true", "env": {"SHARED": "1"}}"#),
+            (
+                "b",
+                r#"{"run": "true", "env": {"SHARED": "2"}, "depends": ["a"]}"#,
+            ),
+        ];
+        for jobs in &[&reserved[..], &conflicting[..]] {
+            doctor(jobs, &[]).unwrap();
+            let error = doctor(jobs, &["--strict-env"]).unwrap_err();
+            assert_eq!(error.to_string(), "Doctor found 1 problem(s)");
+        }
+    }
+}
@@ -1,10 +1,649 @@
-use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
 
-use crate::common::EnvMap;
+use anyhow::{anyhow, Result};
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug, Default)]
+use crate::vars::merge_env;
+
+#[derive(Clone, Deserialize, Debug, JsonSchema, Default)]
 pub(crate) struct InfoSpec {
-    pub depends: Option<Vec<String>>,
-    pub env: Option<EnvMap>,
-    pub ask: Option<Vec<String>>,
+    /// What the job is for, shown in listings and dry runs.
+    pub description: Option<String>,
+    pub depends: Option<Vec<DependsEntry>>,
+    /// Labels other jobs can depend on as `tag:<label>`, meaning every job with the label.
+    pub tags: Option<Vec<String>>,
+    pub env: Option<HashMap<String, EnvValue>>,
+    /// Prepended to each `env` key, so generic names like `HOST` don't collide between jobs.
+    pub env_prefix: Option<String>,
+    pub ask: Option<Vec<AskEntry>>,
+    /// A file of shared `ask` entries, relative to the job directory.
+    pub ask_include: Option<String>,
+    /// A spec file, or another job's directory, relative to the job directory, whose settings
+    /// this job starts from.  In a combined jobs file, it can also name another job in it.
+    pub extends: Option<String>,
+    /// Jobs whose resolved env is merged into this one, later entries winning.
+    pub env_from: Option<Vec<String>>,
+    /// An inline command, used in place of a `run.*` file.
+    pub run: Option<String>,
+    /// A script, used in place of a `run.*` file, relative to the job directory.
+    pub script: Option<String>,
+    /// The file to run, relative to the job directory, even if there are `run.*` files.
+    pub runner: Option<String>,
+    /// A program and its arguments, run directly in place of any runner file.  `${VAR}`
+    /// references in them are expanded.
+    pub exec: Option<Vec<String>>,
+    /// Soft ordering hint; higher priority jobs run earlier when otherwise ready.
+    pub priority: Option<i32>,
+    /// Keeps the job in the repo, but excludes it from runs.
+    pub disabled: Option<bool>,
+    /// Runs the job even when `--continue-from-last` would skip it as already completed.
+    pub always: Option<bool>,
+    /// Only warns if the job fails, carrying on with the run and the jobs that depend on it.
+    pub allow_failure: Option<bool>,
+    /// Captures the runner's stdout, trimmed, and gives it to the jobs depending on this one
+    /// directly as an env var of this name.
+    pub capture_output_var: Option<String>,
+    /// A shell command gating the job; the job is skipped unless it exits successfully.
+    pub when: Option<String>,
+    /// A path which must exist after the job succeeds, relative to the job directory.
+    pub verify_path: Option<String>,
+    /// The shell used to execute the inline `run` command.
+    pub shell: Option<String>,
+    /// Runs the job's scripts through a login shell, so profile files are sourced first.
+    pub login_shell: Option<bool>,
+    /// Seconds the job's `deps.sh` and runner may each run before they're stopped.
+    pub timeout: Option<u64>,
+    /// The working directory for the job's `deps.sh` and runner, or `script_dir` for the job's
+    /// own directory.  `${VAR}` references and a leading `~` are expanded.
+    pub cwd: Option<String>,
+    /// Settings for a `--profile`, applied over the rest of the spec the way a spec is applied
+    /// over the one it extends.  Overrides for other profiles are ignored.
+    #[allow(clippy::use_self)] // serde's derive can't take `Self` here.
+    pub overrides: Option<HashMap<String, InfoSpec>>,
+}
+
+/// A `depends` entry: a job name, glob or `tag:`, or a job depended on only when an asked
+/// variable has a given value.
+#[derive(Clone, Deserialize, Debug, JsonSchema, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum DependsEntry {
+    Name(String),
+    Conditional(ConditionalDep),
+}
+
+/// A dependency on a single job, which is dropped once variables are resolved unless
+/// `when_var` equals `equals`.
+#[derive(Clone, Deserialize, Serialize, Debug, JsonSchema, PartialEq)]
+pub(crate) struct ConditionalDep {
+    pub job: String,
+    /// Resolved like any of the depending job's `ask` variables.
+    pub when_var: String,
+    pub equals: String,
+    /// When the dependency is dropped, leave the job out of the run too, unless another job
+    /// still depends on it.
+    #[serde(default)]
+    pub skip_unneeded: bool,
+}
+
+/// An `env` value: a literal string, or the value of a resolved `ask` variable.
+#[derive(Clone, Deserialize, Debug, JsonSchema)]
+#[serde(untagged)]
+pub(crate) enum EnvValue {
+    Literal(String),
+    FromVar { from_var: String },
+}
+
+/// The contents of an `ask_include` file: a bare list of entries, or an object which may
+/// include further files.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub(crate) enum AskInclude {
+    List(Vec<AskEntry>),
+    Nested {
+        ask: Option<Vec<AskEntry>>,
+        ask_include: Option<String>,
+    },
+}
+
+/// An `ask` list entry: either a bare variable name, or an object with extra settings.
+#[derive(Clone, Deserialize, Debug, JsonSchema)]
+#[serde(untagged)]
+pub(crate) enum AskEntry {
+    Name(String),
+    Detailed(Box<AskVar>),
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, JsonSchema, Default)]
+pub(crate) struct AskVar {
+    pub name: String,
+    /// Fall back to an empty value instead of erroring when the variable can't be resolved.
+    #[serde(default)]
+    pub default_empty: bool,
+    /// The only sources the variable may be resolved from, or `None` for any of them.
+    pub sources: Option<Vec<VarSource>>,
+    /// A fixed value, used instead of resolving the variable at all.
+    pub value: Option<String>,
+    /// Applied to the resolved value, in order, before it reaches the job.
+    pub transform: Option<Transforms>,
+    /// The fewest characters the value may have, checked after any transforms.
+    pub min_length: Option<usize>,
+    /// The most characters the value may have, checked after any transforms.
+    pub max_length: Option<usize>,
+    /// Where the value is kept in the system keyring, tried before prompting.
+    pub keyring: Option<KeyringEntry>,
+    /// A command from the root's `resolvers` whose stdout is the value, tried after the
+    /// keyring and before prompting.
+    pub resolver: Option<String>,
+    /// Any other keys, like `path` or `field`, filling the `{placeholders}` of the resolver's
+    /// command.  They're checked against it, so a misspelled setting isn't passed over.
+    #[serde(flatten)]
+    pub resolver_args: BTreeMap<String, serde_json::Value>,
+}
+
+/// An entry in the system keyring.
+#[derive(Clone, Deserialize, Serialize, Debug, JsonSchema, PartialEq, Eq)]
+pub(crate) struct KeyringEntry {
+    pub service: String,
+    pub account: String,
+}
+
+/// One transform, or a list of them to chain.
+#[derive(Clone, Deserialize, Serialize, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(untagged)]
+pub(crate) enum Transforms {
+    One(Transform),
+    Chain(Vec<Transform>),
+}
+
+/// A normalization applied to a resolved variable.
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Transform {
+    /// Strip leading and trailing whitespace.
+    Trim,
+    Lower,
+    Upper,
+    /// Decode standard base64, which must decode to UTF-8.
+    Base64Decode,
+    Base64Encode,
+}
+
+/// A place an asked variable's value can come from.
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum VarSource {
+    /// `-w/--with-vars` on the command line.
+    Command,
+    /// Environment variables.
+    Env,
+    /// The askfile, answers file, or secrets file.
+    Askfile,
+    /// The system keyring, for variables with a `keyring` entry.
+    Keyring,
+    /// A command from the root's `resolvers`, for variables with a `resolver`.
+    Resolver,
+    /// An interactive prompt.
+    Prompt,
+}
+
+impl InfoSpec {
+    /// Fill in this spec from `base`, the spec it extends.
+    ///
+    /// Lists are concatenated, base first, with duplicates dropped; `ask` entries for the same
+    /// variable are merged later, as they are between jobs.  `env` is overlaid, this spec's
+    /// values winning.  Any other setting comes from `base` only if this spec leaves it out.
+    pub fn extend_from(&mut self, base: Self) {
+        // Destructured, so a new setting can't be forgotten here.
+        let Self {
+            description,
+            depends,
+            tags,
+            env,
+            env_prefix,
+            ask,
+            ask_include: _,
+            extends: _,
+            env_from,
+            run,
+            script,
+            runner,
+            exec,
+            priority,
+            disabled,
+            always,
+            allow_failure,
+            capture_output_var,
+            when,
+            verify_path,
+            shell,
+            login_shell,
+            timeout,
+            cwd,
+            overrides,
+        } = base;
+        self.depends = concat_lists(depends, self.depends.take());
+        self.tags = concat_lists(tags, self.tags.take());
+        self.env_from = concat_lists(env_from, self.env_from.take());
+        self.env = match (env, self.env.take()) {
+            (Some(env), Some(own)) => Some(merge_env(env, own)),
+            (env, own) => own.or(env),
+        };
+        self.ask = match (ask, self.ask.take()) {
+            (Some(mut ask), Some(own)) => {
+                ask.extend(own);
+                Some(ask)
+            }
+            (ask, own) => own.or(ask),
+        };
+        self.description = self.description.take().or(description);
+        self.env_prefix = self.env_prefix.take().or(env_prefix);
+        self.run = self.run.take().or(run);
+        self.script = self.script.take().or(script);
+        self.runner = self.runner.take().or(runner);
+        self.exec = self.exec.take().or(exec);
+        self.priority = self.priority.or(priority);
+        self.disabled = self.disabled.or(disabled);
+        self.always = self.always.or(always);
+        self.allow_failure = self.allow_failure.or(allow_failure);
+        self.capture_output_var = self.capture_output_var.take().or(capture_output_var);
+        self.when = self.when.take().or(when);
+        self.verify_path = self.verify_path.take().or(verify_path);
+        self.shell = self.shell.take().or(shell);
+        self.login_shell = self.login_shell.or(login_shell);
+        self.timeout = self.timeout.or(timeout);
+        self.cwd = self.cwd.take().or(cwd);
+        self.overrides = match (overrides, self.overrides.take()) {
+            (Some(mut overrides), Some(own)) => {
+                for (profile, mut own) in own {
+                    if let Some(base) = overrides.remove(&profile) {
+                        own.extend_from(base);
+                    }
+                    overrides.insert(profile, own);
+                }
+                Some(overrides)
+            }
+            (overrides, own) => own.or(overrides),
+        };
+    }
+
+    /// Apply the override for `profile`, if there is one, over this spec, whose `extends` has
+    /// already been resolved.  The overrides for every other profile are dropped.
+    pub fn apply_profile(mut self, profile: Option<&str>) -> Result<Self> {
+        let overrides = self.overrides.take().unwrap_or_default();
+        let Some((profile, mut own)) =
+            profile.and_then(|profile| overrides.into_iter().find(|(name, _)| name == profile))
+        else {
+            return Ok(self);
+        };
+        if own.extends.is_some() || own.ask_include.is_some() || own.overrides.is_some() {
+            return Err(anyhow!(format!(
+                "The override for profile '{}' cannot set extends, ask_include or overrides",
+                profile
+            )));
+        }
+        own.extend_from(self);
+        Ok(own)
+    }
+}
+
+fn concat_lists<T: PartialEq>(base: Option<Vec<T>>, own: Option<Vec<T>>) -> Option<Vec<T>> {
+    match (base, own) {
+        (Some(mut list), Some(own)) => {
+            for item in own {
+                if !list.contains(&item) {
+                    list.push(item);
+                }
+            }
+            Some(list)
+        }
+        (base, own) => own.or(base),
+    }
+}
+
+impl From<AskEntry> for AskVar {
+    fn from(entry: AskEntry) -> Self {
+        match entry {
+            AskEntry::Name(name) => Self {
+                name,
+                ..Self::default()
+            },
+            AskEntry::Detailed(var) => *var,
+        }
+    }
+}
+
+impl VarSource {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Command => "command",
+            Self::Env => "env",
+            Self::Askfile => "askfile",
+            Self::Keyring => "keyring",
+            Self::Resolver => "resolver",
+            Self::Prompt => "prompt",
+        }
+    }
+}
+
+/// A JSON Schema for `info.json`, for editors to validate and complete it against.
+pub(crate) fn info_schema() -> Result<String> {
+    Ok(serde_json::to_string_pretty(&schema_for!(InfoSpec))?)
+}
+
+impl Transforms {
+    /// Apply each transform in turn.
+    pub fn apply(&self, value: String) -> Result<String> {
+        let steps = match self {
+            Self::One(transform) => std::slice::from_ref(transform),
+            Self::Chain(transforms) => transforms.as_slice(),
+        };
+        steps.iter().try_fold(value, |value, transform| {
+            transform
+                .apply(&value)
+                .map_err(|e| anyhow!(format!("{} failed: {}", transform.name(), e)))
+        })
+    }
+}
+
+impl Transform {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Trim => "trim",
+            Self::Lower => "lower",
+            Self::Upper => "upper",
+            Self::Base64Decode => "base64-decode",
+            Self::Base64Encode => "base64-encode",
+        }
+    }
+
+    fn apply(self, value: &str) -> Result<String> {
+        match self {
+            Self::Trim => Ok(value.trim().to_owned()),
+            Self::Lower => Ok(value.to_lowercase()),
+            Self::Upper => Ok(value.to_uppercase()),
+            // base64's errors quote the offending byte, which may be part of a secret.
+            Self::Base64Decode => base64::decode(value.trim())
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+                .ok_or_else(|| anyhow!("the value isn't base64-encoded UTF-8")),
+            Self::Base64Encode => Ok(base64::encode(value)),
+        }
+    }
+}
+
+impl AskVar {
+    /// Combine the settings of two jobs asking for the same variable.
+    pub fn merge(&mut self, other: &Self) -> Result<()> {
+        // Only optional if every job asking for it says so.
+        self.default_empty &= other.default_empty;
+        // Only sources every job allows.
+        if let Some(other_sources) = &other.sources {
+            let sources = self.sources.get_or_insert_with(|| other_sources.clone());
+            sources.retain(|source| other_sources.contains(source));
+        }
+        // The strictest bounds any job asks for.
+        self.min_length = self.min_length.max(other.min_length);
+        self.max_length = match (self.max_length, other.max_length) {
+            (Some(max), Some(other_max)) => Some(max.min(other_max)),
+            (max, other_max) => max.or(other_max),
+        };
+        match (&self.value, &other.value) {
+            (Some(value), Some(other_value)) if value != other_value => Err(anyhow!(format!(
+                "Variable {} is given conflicting fixed values",
+                self.name
+            ))),
+            (None, Some(other_value)) => {
+                self.value = Some(other_value.clone());
+                Ok(())
+            }
+            _ => Ok(()),
+        }?;
+        match (&self.transform, &other.transform) {
+            (Some(transform), Some(other_transform)) if transform != other_transform => {
+                Err(anyhow!(format!(
+                    "Variable {} is given conflicting transforms",
+                    self.name
+                )))
+            }
+            (None, Some(other_transform)) => {
+                self.transform = Some(other_transform.clone());
+                Ok(())
+            }
+            _ => Ok(()),
+        }?;
+        match (&self.keyring, &other.keyring) {
+            (Some(entry), Some(other_entry)) if entry != other_entry => Err(anyhow!(format!(
+                "Variable {} is given conflicting keyring entries",
+                self.name
+            ))),
+            (None, Some(other_entry)) => {
+                self.keyring = Some(other_entry.clone());
+                Ok(())
+            }
+            _ => Ok(()),
+        }?;
+        match (&self.resolver, &other.resolver) {
+            (Some(resolver), Some(other_resolver))
+                if resolver != other_resolver || self.resolver_args != other.resolver_args =>
+            {
+                Err(anyhow!(format!(
+                    "Variable {} is given conflicting resolvers",
+                    self.name
+                )))
+            }
+            (None, Some(other_resolver)) => {
+                self.resolver = Some(other_resolver.clone());
+                self.resolver_args = other.resolver_args.clone();
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the value is within `min_length` and `max_length`, without revealing it.
+    pub fn check_length(&self, value: &str) -> Result<()> {
+        let length = value.chars().count();
+        match (self.min_length, self.max_length) {
+            (Some(min), _) if length < min => Err(anyhow!(format!(
+                "Value for {} is too short (min {})",
+                self.name, min
+            ))),
+            (_, Some(max)) if length > max => Err(anyhow!(format!(
+                "Value for {} is too long (max {})",
+                self.name, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn allows(&self, source: VarSource) -> bool {
+        self.sources
+            .as_ref()
+            .is_none_or(|sources| sources.contains(&source))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_entry(json: &str) -> AskVar {
+        match serde_json::from_str(json).unwrap() {
+            AskEntry::Detailed(var) => *var,
+            AskEntry::Name(name) => panic!("parsed as a bare name: {}", name),
+        }
+    }
+
+    #[test]
+    fn resolver_args_are_the_other_keys() {
+        let var = parse_entry(
+            r#"{"name": "DB_PASS_SECURE", "resolver": "vault", "path": "secret/db",
+                "field": "password"}"#,
+        );
+        assert_eq!(var.resolver.as_deref(), Some("vault"));
+        assert_eq!(var.resolver_args["path"], "secret/db");
+        assert_eq!(var.resolver_args["field"], "password");
+        assert_eq!(var.resolver_args.len(), 2);
+    }
+
+    #[test]
+    fn misspelled_settings_are_kept_for_checking() {
+        let var = parse_entry(r#"{"name": "PASSWORD", "min_lenght": 5}"#);
+        assert_eq!(var.resolver_args["min_lenght"], 5);
+        assert_eq!(var.min_length, None);
+    }
+
+    #[test]
+    fn extending_overlays_the_base_env() {
+        let mut spec: InfoSpec =
+            serde_json::from_str(r#"{"env": {"shared": "own", "OWN": "own"}}"#).unwrap();
+        let base = serde_json::from_str(r#"{"env": {"SHARED": "base", "BASE": "base"}}"#).unwrap();
+        spec.extend_from(base);
+        let env = spec.env.unwrap();
+        let literal = |key: &str| match &env[key] {
+            EnvValue::Literal(value) => value.clone(),
+            EnvValue::FromVar { from_var } => panic!("{} is from var {}", key, from_var),
+        };
+        assert_eq!(literal("SHARED"), "own");
+        assert_eq!(literal("OWN"), "own");
+        assert_eq!(literal("BASE"), "base");
+        assert_eq!(env.len(), 3);
+    }
+
+    #[test]
+    fn profiles_apply_their_own_override_only() {
+        let spec = || -> InfoSpec {
+            serde_json::from_str(
+                r#"{"run": "make", "env": {"MODE": "laptop", "CACHE": "on"},
+                    "overrides": {
+                        "server": {"env": {"MODE": "server"}, "priority": 3},
+                        "ci": {"run": "make ci"}}}"#,
+            )
+            .unwrap()
+        };
+        let literal = |spec: &InfoSpec, key: &str| match &spec.env.as_ref().unwrap()[key] {
+            EnvValue::Literal(value) => value.clone(),
+            EnvValue::FromVar { from_var } => panic!("{} is from var {}", key, from_var),
+        };
+
+        let server = spec().apply_profile(Some("server")).unwrap();
+        assert_eq!(literal(&server, "MODE"), "server");
+        assert_eq!(literal(&server, "CACHE"), "on");
+        assert_eq!(server.run.as_deref(), Some("make"));
+        assert_eq!(server.priority, Some(3));
+        assert!(server.overrides.is_none());
+
+        for profile in &[None, Some("desktop")] {
+            let base = spec().apply_profile(*profile).unwrap();
+            assert_eq!(literal(&base, "MODE"), "laptop");
+            assert_eq!(base.run.as_deref(), Some("make"));
+            assert_eq!(base.priority, None);
+            assert!(base.overrides.is_none());
+        }
+
+        let nested: InfoSpec =
+            serde_json::from_str(r#"{"overrides": {"server": {"extends": "../base"}}}"#).unwrap();
+        assert_eq!(
+            nested
+                .apply_profile(Some("server"))
+                .unwrap_err()
+                .to_string(),
+            "The override for profile 'server' cannot set extends, ask_include or overrides"
+        );
+    }
+
+    #[test]
+    fn shared_vars_keep_only_the_sources_every_job_allows() {
+        let mut var = parse_entry(r#"{"name": "TOKEN", "sources": ["askfile", "env"]}"#);
+        var.merge(&parse_entry(r#"{"name": "TOKEN"}"#)).unwrap();
+        assert_eq!(var.sources, Some(vec![VarSource::Askfile, VarSource::Env]));
+        var.merge(&parse_entry(
+            r#"{"name": "TOKEN", "sources": ["env", "prompt"]}"#,
+        ))
+        .unwrap();
+        assert_eq!(var.sources, Some(vec![VarSource::Env]));
+        assert!(var.allows(VarSource::Env));
+        assert!(!var.allows(VarSource::Prompt));
+    }
+
+    fn transform(json: &str, value: &str) -> Result<String> {
+        serde_json::from_str::<Transforms>(json)
+            .unwrap()
+            .apply(value.to_owned())
+    }
+
+    #[test]
+    fn each_transform_normalizes_the_value() {
+        assert_eq!(transform(r#""trim""#, "  token\n").unwrap(), "token");
+        assert_eq!(transform(r#""lower""#, "MiXeD").unwrap(), "mixed");
+        assert_eq!(transform(r#""upper""#, "MiXeD").unwrap(), "MIXED");
+        assert_eq!(transform(r#""base64-encode""#, "hi!").unwrap(), "aGkh");
+        assert_eq!(transform(r#""base64-decode""#, "aGkh\n").unwrap(), "hi!");
+    }
+
+    #[test]
+    fn chained_transforms_apply_in_order() {
+        let chain = r#"["trim", "base64-decode", "upper"]"#;
+        assert_eq!(transform(chain, " aGkh ").unwrap(), "HI!");
+        assert_eq!(
+            transform(r#"["upper", "base64-encode"]"#, "hi!").unwrap(),
+            "SEkh"
+        );
+    }
+
+    #[test]
+    fn failed_transforms_name_the_step_without_the_value() {
+        let error = transform(r#"["trim", "base64-decode"]"#, "not base64!").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "base64-decode failed: the value isn't base64-encoded UTF-8"
+        );
+    }
+
+    #[test]
+    fn schema_describes_every_info_field() {
+        let schema: serde_json::Value = serde_json::from_str(&info_schema().unwrap()).unwrap();
+        assert_eq!(schema["title"], "InfoSpec");
+        let properties = schema["properties"].as_object().unwrap();
+        let fields = [
+            "description",
+            "depends",
+            "tags",
+            "env",
+            "env_prefix",
+            "ask",
+            "ask_include",
+            "extends",
+            "env_from",
+            "run",
+            "script",
+            "runner",
+            "exec",
+            "priority",
+            "disabled",
+            "always",
+            "allow_failure",
+            "capture_output_var",
+            "when",
+            "verify_path",
+            "shell",
+            "login_shell",
+            "timeout",
+            "cwd",
+            "overrides",
+        ];
+        let mut names: Vec<&str> = properties.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        let mut expected = fields.to_vec();
+        expected.sort_unstable();
+        assert_eq!(names, expected);
+        assert_eq!(
+            properties["shell"]["description"],
+            "The shell used to execute the inline `run` command."
+        );
+        let ask_var = &schema["definitions"]["AskVar"];
+        assert!(ask_var["properties"]["resolver_args"].is_null());
+        assert_eq!(ask_var["additionalProperties"], true);
+    }
 }
@@ -1,100 +1,409 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::env;
 use std::ffi::OsStr;
-use std::fs::{self, Permissions};
-use std::os::unix::fs::PermissionsExt;
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions, Permissions};
+use std::io::{self, Write};
+use std::mem;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::os::unix::process::ExitStatusExt;
 use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Error, Result};
 use console::Style;
 use derive_getters::Getters;
 use serde::Serialize;
 use tempdir::TempDir;
 
-use crate::common::{EnvMap, DEPS_SCRIPT};
+use crate::cli::TempCleanup;
+use crate::common::{
+    secure_name_check, EnvMap, CAPTURE_FILE, DEFAULT_SHELL, DEPS_SCRIPT, OUTPUT_FILE, REDACTED,
+    SECRETS_FILE, UNDO_SCRIPT,
+};
+use crate::config::Config;
+use crate::info::{AskVar, ConditionalDep, DependsEntry, EnvValue, InfoSpec};
+use crate::signals::{interrupted, run_in_own_group, TimeLimit};
+use crate::vars::{expand_vars, merge_env, resolve_env_references};
 
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct InlineRun {
+    pub command: String,
+    pub shell: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[allow(clippy::struct_excessive_bools)] // One field per switch in the info file.
 pub(crate) struct JobSpec {
     pub name: String,
+    pub description: Option<String>,
+    /// Where the job's files live, relative to the root.  The root itself for jobs defined in
+    /// a combined jobs file, and an absolute path for jobs from a `DEVMAKER_PATH` root.
+    pub dir: PathBuf,
     pub provided_env: EnvMap,
+    /// Env keys whose values are taken from the named `ask` variables.
+    pub env_from_vars: EnvMap,
+    /// Every dependency, including the conditional ones until their conditions are checked.
     pub depends: Vec<String>,
-    pub ask_for_vars: Vec<String>,
+    pub conditional_depends: Vec<ConditionalDep>,
+    pub tags: Vec<String>,
+    pub ask_for_vars: Vec<AskVar>,
     pub has_deps_script: bool,
+    /// Whether the job has an `undo.sh`, run by `--rollback` to unwind it.
+    pub has_undo_script: bool,
+    pub inline_run: Option<InlineRun>,
+    pub script: Option<String>,
+    pub runner: Option<String>,
+    pub exec: Option<Vec<String>>,
+    pub priority: i32,
+    pub disabled: bool,
+    pub always: bool,
+    pub allow_failure: bool,
+    pub capture_output_var: Option<String>,
+    pub login_shell: bool,
+    pub when: Option<InlineRun>,
+    pub verify_path: Option<String>,
+    pub env_from: Vec<String>,
+    pub timeout: Option<Duration>,
+    pub cwd: Option<String>,
 }
 
 #[derive(Clone, Debug, Getters)]
+#[allow(clippy::struct_excessive_bools)] // One field per switch in the info file.
 pub(crate) struct ReadyJob {
     name: String,
+    description: Option<String>,
+    dir: PathBuf,
     env: EnvMap,
     depends: Vec<String>,
     has_deps_script: bool,
+    has_undo_script: bool,
+    inline_run: Option<InlineRun>,
+    script: Option<String>,
+    runner: Option<String>,
+    exec: Option<Vec<String>>,
+    priority: i32,
+    always: bool,
+    allow_failure: bool,
+    capture_output_var: Option<String>,
+    login_shell: bool,
+    secure_vars: HashSet<String>,
+    provided_keys: HashSet<String>,
+    when: Option<InlineRun>,
+    verify_path: Option<String>,
+    env_from: Vec<String>,
+    timeout: Option<Duration>,
+    cwd: Option<String>,
+}
+
+/// State of the overall run, shared with each job's process.
+#[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)] // Carries over the flags jobs need from Config.
+pub(crate) struct RunContext {
+    pub scheduled: Vec<String>,
+    /// When the run started, for the elapsed time and ETA after each job.
+    started: Instant,
+    /// How many jobs run at once, per `--jobs`.
+    parallel: usize,
+    /// Jobs completed so far, in the order they finished.
+    completed: Mutex<Vec<String>>,
+    /// Of those, the jobs `--continue-from-last` skipped because the last run completed them.
+    already_done: Mutex<Vec<String>>,
+    /// Jobs that failed, whether in their condition, `deps.sh` or runner.
+    failed: Mutex<Vec<String>>,
+    /// Jobs with `allow_failure` that failed, which the run carried on past.
+    tolerated: Mutex<Vec<String>>,
+    /// How long each job that ran took, whether it succeeded or not.
+    durations: Mutex<Vec<(String, Duration)>>,
+    /// Overrides the injected `USER` and `USERNAME`.
+    pub user: Option<String>,
+    /// Overrides the injected `HOME`.
+    pub home: Option<PathBuf>,
+    /// Pass secure vars in a file named by `DEVMAKER_SECRETS_FILE`, rather than the env.
+    pub secrets_as_file: bool,
+    /// Write the env of a job that fails to a file in the current directory.
+    pub dump_env_on_failure: bool,
+    /// Leave secure values unredacted in that file.
+    pub include_secure: bool,
+    /// Sent to a job that runs past its `timeout`, before it's killed after `kill_grace`.
+    pub kill_signal: i32,
+    pub kill_grace: Duration,
+    /// Where job output goes, rather than the terminal.
+    pub log_dir: Option<PathBuf>,
+    /// Discard job output, unless it goes to `log_dir`.
+    pub quiet: bool,
+    pub temp_cleanup: TempCleanup,
+    /// Run every job through a login shell, as if each had `login_shell`.
+    pub login_shell: bool,
+    /// With `--skip-deps`, no `deps.sh` runs.
+    pub skip_deps: bool,
+    /// With `--only-deps`, no main runner does.
+    pub only_deps: bool,
+    /// With `--parallel-deps`, every `deps.sh` runs at once, before the runners.
+    pub parallel_deps: bool,
+    /// Log every command before it runs.
+    pub trace_exec: bool,
+    /// When `--max-runtime` runs out, after which no more jobs start.
+    pub out_of_time_at: Option<Instant>,
+    /// With `--simulate-failures`, nothing is executed: these jobs fail and the rest succeed.
+    pub simulate_failures: Option<Vec<String>>,
+    /// What each completed job wrote to its `DEVMAKER_OUTPUT` file.
+    outputs: Mutex<HashMap<String, EnvMap>>,
+    /// The stdout of each completed job with `capture_output_var`, keyed by that var.
+    captured: Mutex<HashMap<String, EnvMap>>,
+    /// The env each job's runner was given, with secure values redacted, for `--report-file`.
+    envs: Mutex<HashMap<String, BTreeMap<String, String>>>,
+}
+
+/// The thing a job actually executes: either a script file or an inline command.
+#[derive(Debug)]
+pub(crate) enum Runner {
+    Script(PathBuf),
+    Inline(InlineRun),
+    /// A program and its arguments, from `exec`.
+    Exec(Vec<String>),
 }
 
 impl JobSpec {
-    pub fn new(
-        name: String,
-        provided_env: EnvMap,
-        depends: Vec<String>,
-        ask_for_vars: Vec<String>,
-        has_deps_script: bool,
-    ) -> Self {
+    pub fn new(name: String, info: InfoSpec, has_deps_script: bool) -> Self {
+        let shell = info.shell.unwrap_or_else(|| DEFAULT_SHELL.to_owned());
+        let when = info.when.map(|command| InlineRun {
+            command,
+            shell: shell.clone(),
+        });
+        let inline_run = info.run.map(|command| InlineRun { command, shell });
+        let dir = PathBuf::from(&name);
+        let mut provided_env = EnvMap::new();
+        let mut env_from_vars = EnvMap::new();
+        let prefix = info.env_prefix.unwrap_or_default();
+        for (key, value) in info.env.unwrap_or_default() {
+            let key = format!("{prefix}{key}");
+            match value {
+                EnvValue::Literal(value) => provided_env.insert(key, value),
+                EnvValue::FromVar { from_var } => env_from_vars.insert(key, from_var),
+            };
+        }
+        let mut depends = Vec::new();
+        let mut conditional_depends = Vec::new();
+        for entry in info.depends.unwrap_or_default() {
+            match entry {
+                DependsEntry::Name(dep) => depends.push(dep),
+                DependsEntry::Conditional(dep) => {
+                    depends.push(dep.job.clone());
+                    conditional_depends.push(dep);
+                }
+            }
+        }
         Self {
             name,
+            description: info.description,
+            dir,
             provided_env,
+            env_from_vars,
             depends,
-            ask_for_vars,
+            conditional_depends,
+            tags: info.tags.unwrap_or_default(),
+            ask_for_vars: info
+                .ask
+                .unwrap_or_default()
+                .into_iter()
+                .map(AskVar::from)
+                .collect(),
             has_deps_script,
+            has_undo_script: false,
+            inline_run,
+            script: info.script,
+            runner: info.runner,
+            exec: info.exec,
+            priority: info.priority.unwrap_or_default(),
+            disabled: info.disabled.unwrap_or_default(),
+            always: info.always.unwrap_or_default(),
+            allow_failure: info.allow_failure.unwrap_or_default(),
+            capture_output_var: info.capture_output_var,
+            login_shell: info.login_shell.unwrap_or_default(),
+            when,
+            verify_path: info.verify_path,
+            env_from: info.env_from.unwrap_or_default(),
+            timeout: info.timeout.map(Duration::from_secs),
+            cwd: info.cwd,
         }
     }
 
-    #[inline]
-    pub const fn get_ask_vars(&self) -> &Vec<String> {
-        &self.ask_for_vars
+    /// Every variable the job needs resolved: its `ask` list, plus those `from_var` env and
+    /// conditional dependencies refer to.
+    pub fn get_ask_vars(&self, secure_suffix: &str) -> Vec<AskVar> {
+        let mut vars = self.ask_for_vars.clone();
+        let referenced = self
+            .env_from_vars
+            .values()
+            .chain(self.conditional_depends.iter().map(|dep| &dep.when_var));
+        for name in referenced {
+            let stripped = secure_name_check(name.as_str(), secure_suffix).0;
+            if !vars
+                .iter()
+                .any(|var| secure_name_check(var.name.as_str(), secure_suffix).0 == stripped)
+            {
+                vars.push(AskVar {
+                    name: name.clone(),
+                    ..AskVar::default()
+                });
+            }
+        }
+        vars
     }
 }
 
 impl ReadyJob {
-    pub fn new(name: String, env: EnvMap, depends: Vec<String>, has_deps_script: bool) -> Self {
+    pub fn new(
+        spec: JobSpec,
+        env: EnvMap,
+        secure_vars: HashSet<String>,
+        provided_keys: HashSet<String>,
+    ) -> Self {
         Self {
-            name,
+            name: spec.name,
+            description: spec.description,
+            dir: spec.dir,
             env,
-            depends,
-            has_deps_script,
+            depends: spec.depends,
+            has_deps_script: spec.has_deps_script,
+            has_undo_script: spec.has_undo_script,
+            inline_run: spec.inline_run,
+            script: spec.script,
+            runner: spec.runner,
+            exec: spec.exec,
+            priority: spec.priority,
+            always: spec.always,
+            allow_failure: spec.allow_failure,
+            capture_output_var: spec.capture_output_var,
+            login_shell: spec.login_shell,
+            secure_vars,
+            provided_keys,
+            when: spec.when,
+            verify_path: spec.verify_path,
+            env_from: spec.env_from,
+            timeout: spec.timeout,
+            cwd: spec.cwd,
+        }
+    }
+
+    /// Merge in the env of the jobs named in `env_from`, in order; this job's own values win.
+    pub fn inherit_env(&mut self, bases: &[Self]) {
+        let mut env = EnvMap::new();
+        for base in bases {
+            env = merge_env(env, base.env.clone());
+            self.secure_vars.extend(base.secure_vars.iter().cloned());
+            self.provided_keys
+                .extend(base.provided_keys.iter().cloned());
         }
+        self.env = merge_env(env, mem::take(&mut self.env));
     }
 
     #[inline]
     fn script_dir<P: AsRef<Path>>(&self, root: P) -> PathBuf {
-        root.as_ref().join(&self.name)
+        root.as_ref().join(&self.dir)
     }
 
-    fn create_proc_env<P: AsRef<Path>>(&self, root: P) -> Result<EnvMap> {
-        let mut map = EnvMap::with_capacity(self.env.len());
-        for (k, v) in &self.env {
-            map.insert(k.clone(), v.clone());
-        }
-        map.insert(
+    /// Build the env a job's scripts run with, merging layers with `merge_env`, from lowest to
+    /// highest precedence:
+    ///
+    /// 1. the job's own env: its vars, `env` entries and whatever it inherited;
+    /// 2. what devmaker reserves, like `HOME`, `SCRIPT_DIR` and the `DEVMAKER_*` progress
+    ///    variables, so a job can't shadow them;
+    /// 3. the outputs of the jobs it depends on, which were produced for this job to read.
+    pub fn create_proc_env<P: AsRef<Path>>(&self, root: P, context: &RunContext) -> Result<EnvMap> {
+        let env = merge_env(self.env.clone(), self.reserved_env(root, context)?);
+        let mut env = merge_env(env, context.outputs_env(&self.depends));
+        resolve_env_references(&mut env, &self.provided_keys)?;
+        Ok(env)
+    }
+
+    /// The `RESERVED_ENV` known before the job runs; its temp dir and files are set on spawning.
+    fn reserved_env<P: AsRef<Path>>(&self, root: P, context: &RunContext) -> Result<EnvMap> {
+        let mut env = EnvMap::new();
+        env.insert(
             "HOME".into(),
-            dirs::home_dir()
+            context
+                .home
+                .clone()
+                .or_else(dirs::home_dir)
                 .ok_or_else(|| anyhow!("Cannot find home dir"))?
                 .display()
                 .to_string(),
         );
-        map.insert("USER".into(), whoami::username());
-        map.insert("USERNAME".into(), whoami::username());
-        map.insert(
+        let user = context.user.clone().unwrap_or_else(whoami::username);
+        env.insert("USER".into(), user.clone());
+        env.insert("USERNAME".into(), user);
+        env.insert(
             "SCRIPT_DIR".into(),
             self.script_dir(root).display().to_string(),
         );
-        Ok(map)
+        env.insert("DEVMAKER_SCHEDULED".into(), context.scheduled.join("\n"));
+        env.insert(
+            "DEVMAKER_COMPLETED".into(),
+            context.lock_completed().join("\n"),
+        );
+        // The position in the schedule, even when jobs run in parallel and finish out of order.
+        if let Some(index) = context.scheduled.iter().position(|name| name == &self.name) {
+            env.insert("DEVMAKER_JOB_INDEX".into(), (index + 1).to_string());
+        }
+        env.insert(
+            "DEVMAKER_JOB_TOTAL".into(),
+            context.scheduled.len().to_string(),
+        );
+        Ok(env)
     }
 
-    pub fn report(&self, job_num: usize) -> String {
+    /// Describe the job for a dry run.
+    ///
+    /// `condition` is the result of the `when` check, or `None` if it wasn't evaluated.  The
+    /// runner and working directory are worked out as they would be for a real run, but
+    /// failing to isn't an error.
+    pub fn report<P: AsRef<Path>>(
+        &self,
+        root: P,
+        job_num: usize,
+        condition: Option<bool>,
+        context: &RunContext,
+    ) -> String {
         let mut report = String::new();
-        report.push_str("Would run job ");
-        report.push_str(&format!("{:03}", job_num));
+        if condition == Some(false) {
+            report.push_str("Would skip job ");
+        } else {
+            report.push_str("Would run job ");
+        }
+        let _ = write!(report, "{job_num:03}");
         report.push_str(": ");
         report.push_str(&job_style().apply_to(&self.name).to_string());
+        if let Some(description) = &self.description {
+            report.push('\n');
+            report.push_str(&info_style().apply_to("  ").to_string());
+            report.push_str(&info_style().apply_to(description).to_string());
+        }
+        if let Some(when) = &self.when {
+            let outcome = match condition {
+                None => "not evaluated",
+                Some(true) => "met",
+                Some(false) => "not met",
+            };
+            report.push('\n');
+            report.push_str(&info_style().apply_to("  When: ").to_string());
+            report.push_str(
+                &info_style()
+                    .apply_to(format!("{} ({})", when.describe(), outcome))
+                    .to_string(),
+            );
+        }
         // report.push('\n');
+        if self.priority != 0 {
+            report.push('\n');
+            report.push_str(&info_style().apply_to("  Priority: ").to_string());
+            report.push_str(&info_style().apply_to(self.priority).to_string());
+        }
         for d in &self.depends {
             report.push('\n');
             report.push_str(&info_style().apply_to("  Depends on: ").to_string());
@@ -102,73 +411,1076 @@ impl ReadyJob {
         }
         if self.has_deps_script {
             report.push('\n');
-            report.push_str(&info_style().apply_to("  Deps.sh: yes").to_string());
+            report.push_str(&info_style().apply_to("  Deps.sh: ").to_string());
+            report.push_str(
+                &info_style()
+                    .apply_to(self.script_dir(&root).join(DEPS_SCRIPT).display())
+                    .to_string(),
+            );
+            if context.skip_deps {
+                report.push_str(&info_style().apply_to(" (skipped: --skip-deps)").to_string());
+            }
+        }
+        if self.has_undo_script {
+            report.push('\n');
+            report.push_str(&info_style().apply_to("  Undo.sh: ").to_string());
+            report.push_str(
+                &info_style()
+                    .apply_to(self.script_dir(&root).join(UNDO_SCRIPT).display())
+                    .to_string(),
+            );
+        }
+        self.report_runner(&root, context, &mut report);
+        let working_dir = self
+            .create_proc_env(&root, context)
+            .and_then(|env| self.working_dir(&env));
+        let working_dir = match working_dir {
+            Ok(Some(dir)) => dir.display().to_string(),
+            Ok(None) => env::current_dir().map_or_else(
+                |_| "inherited".to_owned(),
+                |dir| format!("{} (inherited)", dir.display()),
+            ),
+            Err(e) => format!("cannot be resolved ({e})"),
         };
-        for (k, v) in &self.env {
+        report.push('\n');
+        report.push_str(&info_style().apply_to("  Working dir: ").to_string());
+        report.push_str(&info_style().apply_to(working_dir).to_string());
+        if let Some(var) = &self.capture_output_var {
+            report.push('\n');
+            report.push_str(&info_style().apply_to("  Captures stdout as: ").to_string());
+            report.push_str(&env_key_style().apply_to(var).to_string());
+        }
+        self.report_env(&mut report);
+        report
+    }
+
+    /// Append the runner to a report, and how it's run.
+    fn report_runner<P: AsRef<Path>>(&self, root: P, context: &RunContext, report: &mut String) {
+        match self.find_runner(root) {
+            Ok(Runner::Inline(inline)) => {
+                report.push('\n');
+                report.push_str(&info_style().apply_to("  Inline run: ").to_string());
+                report.push_str(&info_style().apply_to(inline.describe()).to_string());
+            }
+            Ok(Runner::Script(path)) => {
+                report.push('\n');
+                report.push_str(&info_style().apply_to("  Runner: ").to_string());
+                report.push_str(&info_style().apply_to(path.display()).to_string());
+            }
+            Ok(exec @ Runner::Exec(_)) => {
+                report.push('\n');
+                report.push_str(&info_style().apply_to("  Exec: ").to_string());
+                report.push_str(&info_style().apply_to(exec.describe()).to_string());
+            }
+            Err(e) => {
+                report.push('\n');
+                report.push_str(&info_style().apply_to("  Runner: ").to_string());
+                report.push_str(
+                    &info_style()
+                        .apply_to(format!("no runner found ({e})"))
+                        .to_string(),
+                );
+            }
+        }
+        if context.only_deps {
+            report.push_str(&info_style().apply_to(" (skipped: --only-deps)").to_string());
+        }
+        if self.uses_login_shell(context) {
+            report.push('\n');
+            report.push_str(&info_style().apply_to("  Login shell: ").to_string());
+            report.push_str(
+                &info_style()
+                    .apply_to("profile files are sourced first")
+                    .to_string(),
+            );
+        }
+    }
+
+    /// Append the env to a report, sorted by key, aligned, and with secure values redacted.
+    fn report_env(&self, report: &mut String) {
+        let mut keys: Vec<&String> = self.env.keys().collect();
+        keys.sort();
+        let width = keys.iter().map(|k| k.len()).max().unwrap_or_default();
+        for k in keys {
             report.push('\n');
             report.push_str(&info_style().apply_to("  Env: ").to_string());
-            report.push_str(&info_style().apply_to(k).to_string());
+            report.push_str(&env_key_style().apply_to(format!("{k:width$}")).to_string());
             report.push_str(&info_style().apply_to(" -> ").to_string());
-            report.push_str(&info_style().apply_to(v).to_string());
+            if self.secure_vars.contains(k) {
+                report.push_str(&secure_style().apply_to(REDACTED).to_string());
+            } else {
+                report.push_str(&info_style().apply_to(&self.env[k]).to_string());
+            }
         }
-        report
     }
 
-    fn run_process<P: AsRef<OsStr>>(&self, env: &EnvMap, runnable: P) -> Result<()> {
-        debug!(
-            "Executing runnable: {}",
-            runnable.as_ref().to_string_lossy()
-        );
-        ensure_executable(&runnable.as_ref())?;
-        let tmp_dir = TempDir::new(&self.name)?;
-        let status = process::Command::new(runnable)
-            .envs(env)
+    /// Run one of the job's scripts or its runner.  With `capture`, stdout goes to a file in
+    /// the temp dir rather than the terminal or log, for `capture_output_var`.
+    fn run_process(
+        &self,
+        env: &EnvMap,
+        runner: &Runner,
+        capture: Option<&str>,
+        context: &RunContext,
+    ) -> Result<()> {
+        debug!("Executing runner: {}", runner.describe());
+        let mut command = runner.command(&self.name, self.uses_login_shell(context))?;
+        if let Some(dir) = self.working_dir(env)? {
+            command.current_dir(dir);
+        }
+        if let Some(dir) = &context.log_dir {
+            let log = self.open_log(dir)?;
+            command.stdout(log.try_clone()?).stderr(log);
+        } else if context.quiet {
+            command
+                .stdout(process::Stdio::null())
+                .stderr(process::Stdio::null());
+        }
+        let tmp_dir = TempDir::new(&self.name.replace('/', "-"))?;
+        // A file rather than a pipe, so a job printing a lot can't fill it up and stall.
+        let capture_path = tmp_dir.path().join(CAPTURE_FILE);
+        if capture.is_some() {
+            command.stdout(fs::File::create(&capture_path)?);
+        }
+        if context.secrets_as_file {
+            let (secrets, public): (EnvMap, EnvMap) = env
+                .clone()
+                .into_iter()
+                .partition(|(key, _)| self.secure_vars.contains(key));
+            let secrets_file = write_secrets_file(tmp_dir.path(), &secrets)?;
+            // They may also be in our own env, which the job would otherwise inherit.
+            for key in secrets.keys() {
+                command.env_remove(key);
+            }
+            command
+                .envs(public)
+                .env("DEVMAKER_SECRETS_FILE", secrets_file);
+        } else {
+            command.envs(env);
+        }
+        let output_path = tmp_dir.path().join(OUTPUT_FILE);
+        command
             .env("TMP_DIR", tmp_dir.path())
             .env("TEMP_DIR", tmp_dir.path())
-            .status()?;
-        debug!("Dropping temp dir: {}", tmp_dir.path().display());
-        drop(tmp_dir); // Statically enforce that we didn't drop until here.
-        if status.success() {
-            Ok(())
+            .env("DEVMAKER_OUTPUT", &output_path);
+        if context.trace_exec {
+            trace_command(&command, &self.secure_vars);
+        }
+        let limit = self.timeout.map(|after| TimeLimit {
+            after,
+            signal: context.kill_signal,
+            grace: context.kill_grace,
+        });
+        let status = run_in_own_group(&mut command, limit.as_ref(), context.runs_serially())
+            .map_err(|e| not_executable_error(&self.name, runner, e))?;
+        let succeeded = status.is_some_and(|status| status.success());
+        let outputs = if succeeded && output_path.is_file() {
+            Some(fs::read_to_string(&output_path)?)
+        } else {
+            None
+        };
+        if let Some(var) = capture.filter(|_| succeeded) {
+            let stdout = fs::read_to_string(&capture_path).map_err(|e| {
+                anyhow!(format!(
+                    "Cannot read the stdout of job '{}': {}",
+                    self.name, e
+                ))
+            })?;
+            context.record_captured(&self.name, var, &stdout);
+        }
+        // Taking the temp dir by value keeps it alive until the job and its outputs are done with.
+        self.clean_up_temp_dir(tmp_dir, succeeded && interrupted().is_none(), context);
+        let Some(status) = status else {
+            return Err(anyhow!(format!(
+                "Job '{}' timed out after {} seconds",
+                self.name,
+                self.timeout.unwrap_or_default().as_secs()
+            )));
+        };
+        match interrupted().or_else(|| status.signal()) {
+            Some(signal) => Err(anyhow!(format!(
+                "Job '{}' was interrupted by signal {}",
+                self.name, signal
+            ))),
+            None if status.success() => outputs.map_or(Ok(()), |outputs| {
+                context.record_outputs(&self.name, &outputs)
+            }),
+            None => {
+                let code = status.code().unwrap_or(-1);
+                if context.dump_env_on_failure {
+                    self.dump_env(env, runner, code, context)?;
+                }
+                Err(anyhow!(format!(
+                    "Job '{}' failed with exit code {}",
+                    self.name, code
+                )))
+            }
+        }
+    }
+
+    /// Remove the job's temp dir, or keep it if `--temp-cleanup` says to, saying where it is.
+    ///
+    /// A kept dir loses its secrets file, so secure values aren't left on disk.
+    fn clean_up_temp_dir(&self, tmp_dir: TempDir, succeeded: bool, context: &RunContext) {
+        let keep = match context.temp_cleanup {
+            TempCleanup::Always => false,
+            TempCleanup::OnSuccess => !succeeded,
+            TempCleanup::Never => true,
+        };
+        if !keep {
+            debug!("Dropping temp dir: {}", tmp_dir.path().display());
+            drop(tmp_dir);
+            return;
+        }
+        let path = tmp_dir.into_path();
+        if let Err(e) = fs::remove_file(path.join(SECRETS_FILE)) {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!("Cannot remove the secrets file of job {}: {}", self.name, e);
+            }
+        }
+        if !succeeded || !context.quiet {
+            eprintln!("Kept temp dir of job '{}': {}", self.name, path.display());
+        }
+    }
+
+    /// Write the env and runner of a failed job to a file, so it can be rerun by hand.
+    fn dump_env(
+        &self,
+        env: &EnvMap,
+        runner: &Runner,
+        code: i32,
+        context: &RunContext,
+    ) -> Result<()> {
+        // Beside the logs if there are any, or else the current directory.
+        let path = context.log_dir.clone().unwrap_or_default().join(format!(
+            "devmaker-failed-{}.env",
+            self.name.replace('/', "-")
+        ));
+        let mut keys: Vec<&String> = env.keys().collect();
+        keys.sort();
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&path)?;
+        writeln!(
+            file,
+            "# Env of job '{}', which failed with exit code {}",
+            self.name, code
+        )?;
+        writeln!(file, "# Runner: {}", runner.describe())?;
+        for key in keys {
+            if self.secure_vars.contains(key) && !context.include_secure {
+                writeln!(file, "{key}={REDACTED}")?;
+            } else {
+                writeln!(file, "{}={}", key, env[key])?;
+            }
+        }
+        eprintln!(
+            "Wrote the env of failed job '{}' to {}",
+            self.name,
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Open the job's log in `dir` for appending, so `deps.sh` and the runner share it.
+    fn open_log(&self, dir: &Path) -> Result<File> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}.log", self.name.replace('/', "-")));
+        debug!("Logging output of {} to {}", self.name, path.display());
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| anyhow!(format!("Cannot open log {}: {}", path.display(), e)))
+    }
+
+    /// The working directory given by `cwd`, checked to exist before anything is launched.
+    fn working_dir(&self, env: &EnvMap) -> Result<Option<PathBuf>> {
+        let Some(cwd) = &self.cwd else {
+            return Ok(None);
+        };
+        let cwd = if cwd == "script_dir" {
+            "${SCRIPT_DIR}".to_owned()
+        } else if cwd == "~" || cwd.starts_with("~/") {
+            cwd.replacen('~', "${HOME}", 1)
+        } else {
+            cwd.clone()
+        };
+        let dir = PathBuf::from(expand_vars(&cwd, env)?);
+        if dir.is_dir() {
+            Ok(Some(dir))
         } else {
             Err(anyhow!(format!(
-                "Job '{}' failed with exit code {}",
+                "Working directory for job '{}' doesn't exist: {}",
                 self.name,
-                status.code().unwrap_or(-1)
+                dir.display()
             )))
         }
     }
 
-    fn find_runner<P: AsRef<Path>>(&self, root: P) -> Result<PathBuf> {
-        let job_dir = root.as_ref().join(&self.name);
-        let default = job_dir.join("run.sh");
-        if default.is_file() {
-            return Ok(default);
+    fn find_runner<P: AsRef<Path>>(&self, root: P) -> Result<Runner> {
+        locate_runner(
+            &self.script_dir(root),
+            &self.name,
+            self.inline_run.as_ref(),
+            self.script.as_deref(),
+            self.runner.as_deref(),
+            self.exec.as_deref(),
+        )
+    }
+
+    /// Check the job's `when` condition, if any, with the same env the job would get.
+    pub fn condition_met<P: AsRef<Path>>(&self, root: P, context: &RunContext) -> Result<bool> {
+        if let Some(when) = &self.when {
+            debug!("Checking condition for {}: {}", self.name, when.describe());
+            let env = self.create_proc_env(root, context)?;
+            let mut command =
+                Runner::Inline(when.clone()).command(&self.name, self.uses_login_shell(context))?;
+            command.envs(&env).stdout(process::Stdio::null());
+            if context.trace_exec {
+                trace_command(&command, &self.secure_vars);
+            }
+            let status = command.status()?;
+            Ok(status.success())
+        } else {
+            Ok(true)
         }
-        let pattern = format!("{}/run.*", job_dir.display());
-        Ok(glob::glob(&pattern)?
-            .next()
-            .ok_or_else(|| anyhow!("No runner found"))??)
     }
 
-    pub fn run<P: AsRef<Path>>(&self, root: P) -> Result<()> {
-        let env = self.create_proc_env(&root)?;
-        if self.has_deps_script {
-            let deps_runnable = root.as_ref().join(&self.name).join(DEPS_SCRIPT);
-            self.run_process(&env, deps_runnable)?;
+    const fn uses_login_shell(&self, context: &RunContext) -> bool {
+        self.login_shell || context.login_shell
+    }
+
+    /// Run only the `deps.sh` script, if the job has one and `--skip-deps` wasn't given.
+    pub fn run_deps<P: AsRef<Path>>(&self, root: P, context: &RunContext) -> Result<()> {
+        if self.has_deps_script && context.skip_deps {
+            debug!("Skipping deps.sh of {}: --skip-deps", self.name);
+        } else if self.has_deps_script {
+            let env = self.create_proc_env(&root, context)?;
+            let deps_runnable = self.script_dir(&root).join(DEPS_SCRIPT);
+            self.run_process(&env, &Runner::Script(deps_runnable), None, context)?;
+        }
+        Ok(())
+    }
+
+    /// Run the job's `undo.sh`, if it has one, with the same env as the job itself.
+    pub fn run_undo<P: AsRef<Path>>(&self, root: P, context: &RunContext) -> Result<()> {
+        if self.has_undo_script {
+            let env = self.create_proc_env(&root, context)?;
+            let undo_runnable = self.script_dir(&root).join(UNDO_SCRIPT);
+            self.run_process(&env, &Runner::Script(undo_runnable), None, context)?;
+        }
+        Ok(())
+    }
+
+    /// Run only the main runner, skipping `deps.sh`.  Does nothing with `--only-deps`.
+    pub fn run_main<P: AsRef<Path>>(&self, root: P, context: &RunContext) -> Result<()> {
+        if context.only_deps {
+            debug!("Skipping runner of {}: --only-deps", self.name);
+            return Ok(());
+        }
+        let env = self.create_proc_env(&root, context)?;
+        let runner = match self.find_runner(&root)? {
+            Runner::Exec(argv) => Runner::Exec(
+                argv.iter()
+                    .map(|arg| expand_vars(arg, &env))
+                    .collect::<Result<_>>()?,
+            ),
+            runner => runner,
         };
-        let runner = self.find_runner(root)?;
-        self.run_process(&env, runner)
+        context.record_env(&self.name, self.redacted_env(&env));
+        self.run_process(&env, &runner, self.capture_output_var.as_deref(), context)?;
+        self.verify(root, &env)
+    }
+
+    /// The env, with the values of the job's secure vars replaced by a placeholder.
+    fn redacted_env(&self, env: &EnvMap) -> BTreeMap<String, String> {
+        env.iter()
+            .map(|(key, value)| {
+                if self.secure_vars.contains(key) {
+                    (key.clone(), REDACTED.to_owned())
+                } else {
+                    (key.clone(), value.clone())
+                }
+            })
+            .collect()
+    }
+
+    /// Some scripts exit successfully without doing their job, so check for `verify_path`.
+    fn verify<P: AsRef<Path>>(&self, root: P, env: &EnvMap) -> Result<()> {
+        if let Some(verify_path) = &self.verify_path {
+            let path = self.script_dir(root).join(expand_vars(verify_path, env)?);
+            debug!("Verifying path exists: {}", path.display());
+            if !path.exists() {
+                return Err(anyhow!(format!(
+                    "Job '{}' succeeded, but did not create {}",
+                    self.name,
+                    path.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn run<P: AsRef<Path>>(&self, root: P, context: &RunContext) -> Result<()> {
+        self.run_deps(&root, context)?;
+        self.run_main(root, context)
     }
 }
 
-fn ensure_executable<P: AsRef<Path>>(file: P) -> Result<()> {
-    if is_executable::is_executable(&file) {
+impl RunContext {
+    pub fn new(scheduled: Vec<String>, config: &Config) -> Self {
+        Self {
+            scheduled,
+            started: Instant::now(),
+            parallel: config.jobs,
+            completed: Mutex::default(),
+            already_done: Mutex::default(),
+            failed: Mutex::default(),
+            tolerated: Mutex::default(),
+            durations: Mutex::default(),
+            user: config.as_user.clone(),
+            home: config.home.clone(),
+            secrets_as_file: config.secrets_as_file,
+            dump_env_on_failure: config.dump_env_on_failure,
+            include_secure: config.include_secure,
+            kill_signal: config.kill_signal,
+            kill_grace: config.kill_grace,
+            log_dir: config.log_dir.clone(),
+            quiet: config.oneline_summary,
+            temp_cleanup: config.temp_cleanup,
+            login_shell: config.login_shell,
+            skip_deps: config.skip_deps,
+            only_deps: config.only_deps,
+            parallel_deps: config.parallel_deps,
+            trace_exec: config.trace_exec,
+            out_of_time_at: config.max_runtime.map(|budget| Instant::now() + budget),
+            simulate_failures: config.simulate_failures.clone(),
+            outputs: Mutex::default(),
+            captured: Mutex::default(),
+            envs: Mutex::default(),
+        }
+    }
+
+    /// Whether jobs run one at a time, so each can have the terminal to itself.
+    const fn runs_serially(&self) -> bool {
+        self.parallel == 1 && !self.parallel_deps
+    }
+
+    /// Parse the `KEY=value` lines a job wrote to its `DEVMAKER_OUTPUT` file, and keep them.
+    fn record_outputs(&self, job: &str, contents: &str) -> Result<()> {
+        let mut outputs = EnvMap::new();
+        for (number, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .filter(|(key, _)| is_output_key(key.trim()))
+                .ok_or_else(|| {
+                    anyhow!(format!(
+                        "Job '{}' wrote an unparseable output on line {}",
+                        job,
+                        number + 1
+                    ))
+                })?;
+            outputs.insert(key.trim().to_owned(), value.to_owned());
+        }
+        debug!("Job {} produced {} output(s)", job, outputs.len());
+        self.lock_outputs()
+            .entry(job.to_owned())
+            .or_default()
+            .extend(outputs);
+        Ok(())
+    }
+
+    /// Keep the captured stdout of a job as `var`, without surrounding whitespace.
+    fn record_captured(&self, job: &str, var: &str, stdout: &str) {
+        debug!(
+            "Job {} captured {} bytes of stdout as {}",
+            job,
+            stdout.len(),
+            var
+        );
+        self.lock_captured()
+            .entry(job.to_owned())
+            .or_default()
+            .insert(var.to_owned(), stdout.trim().to_owned());
+    }
+
+    /// The outputs of the given jobs, as `DEVMAKER_OUT_<JOB>_<KEY>`, along with the stdout they
+    /// captured, under the names they gave it.
+    fn outputs_env(&self, jobs: &[String]) -> EnvMap {
+        let captured = self.lock_captured();
+        let captured_env = jobs
+            .iter()
+            .filter_map(|job| captured.get(job).cloned())
+            .fold(EnvMap::new(), merge_env);
+        drop(captured);
+        let outputs = self.lock_outputs();
+        let outputs_env = jobs
+            .iter()
+            .flat_map(|job| {
+                outputs
+                    .get(job)
+                    .into_iter()
+                    .flatten()
+                    .map(move |(key, value)| (output_env_name(job, key), value.clone()))
+            })
+            .collect();
+        merge_env(captured_env, outputs_env)
+    }
+
+    fn lock_captured(&self) -> MutexGuard<'_, HashMap<String, EnvMap>> {
+        self.captured.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lock_outputs(&self) -> MutexGuard<'_, HashMap<String, EnvMap>> {
+        self.outputs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn mark_completed(&self, job: &str) {
+        self.lock_completed().push(job.to_owned());
+    }
+
+    /// Note a job the last run completed as done, without it having run this time.
+    pub fn mark_already_done(&self, job: &str) {
+        self.mark_completed(job);
+        self.lock_already_done().push(job.to_owned());
+    }
+
+    fn lock_already_done(&self) -> MutexGuard<'_, Vec<String>> {
+        self.already_done
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lock_completed(&self) -> MutexGuard<'_, Vec<String>> {
+        self.completed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Pass on the result of running part of a job, noting the job as failed on an error.
+    pub fn track<T>(&self, job: &str, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.lock_failed().push(job.to_owned());
+        }
+        result
+    }
+
+    /// Like `track`, but the failure of a job with `allow_failure` is only warned about, and
+    /// the job is treated as not having run.
+    pub fn track_job<T: Default>(&self, job: &ReadyJob, result: Result<T>) -> Result<T> {
+        match result {
+            Err(e) if job.allow_failure => {
+                eprintln!("warning: {e}; carrying on, as it's allowed to fail");
+                self.lock_tolerated().push(job.name.clone());
+                Ok(T::default())
+            }
+            result => self.track(&job.name, result),
+        }
+    }
+
+    /// How the job has fared so far: `ok`, `failed`, `tolerated`, or `skipped` if it hasn't
+    /// done any of those.
+    pub fn status(&self, job: &str) -> &'static str {
+        if self.lock_completed().iter().any(|done| done == job) {
+            "ok"
+        } else if self.failed(job) {
+            "failed"
+        } else if self.tolerated(job) {
+            "tolerated"
+        } else {
+            "skipped"
+        }
+    }
+
+    fn record_env(&self, job: &str, env: BTreeMap<String, String>) {
+        self.lock_envs().insert(job.to_owned(), env);
+    }
+
+    /// The env the job's runner was given, redacted, if it got as far as starting.
+    pub fn env_given(&self, job: &str) -> Option<BTreeMap<String, String>> {
+        self.lock_envs().get(job).cloned()
+    }
+
+    fn lock_envs(&self) -> MutexGuard<'_, HashMap<String, BTreeMap<String, String>>> {
+        self.envs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn failed(&self, job: &str) -> bool {
+        self.lock_failed().iter().any(|failed| failed == job)
+    }
+
+    pub fn tolerated(&self, job: &str) -> bool {
+        self.lock_tolerated()
+            .iter()
+            .any(|tolerated| tolerated == job)
+    }
+
+    fn lock_tolerated(&self) -> MutexGuard<'_, Vec<String>> {
+        self.tolerated
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Whether the job ran during this run and succeeded, rather than being skipped.
+    pub fn ran_successfully(&self, job: &str) -> bool {
+        !self.failed(job)
+            && !self.tolerated(job)
+            && self.lock_durations().iter().any(|(ran, _)| ran == job)
+    }
+
+    fn lock_failed(&self) -> MutexGuard<'_, Vec<String>> {
+        self.failed.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Whether the run has used up its `--max-runtime`.
+    pub fn out_of_time(&self) -> bool {
+        self.out_of_time_at
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    pub fn record_duration(&self, job: &str, took: Duration) {
+        self.lock_durations().push((job.to_owned(), took));
+    }
+
+    /// A line reporting a job that just completed and how far along the run is, like
+    /// `[3/12] foo done in 8.2s (elapsed 41s, ETA 1m30s)`.
+    ///
+    /// The ETA is naive: the average time of the jobs run so far, times the jobs left, shared
+    /// among `--jobs`.
+    pub fn completion_line(&self, job: &str) -> String {
+        let index = self
+            .scheduled
+            .iter()
+            .position(|name| name == job)
+            .map_or(0, |index| index + 1);
+        let durations = self.lock_durations();
+        let took = durations
+            .iter()
+            .rev()
+            .find(|(ran, _)| ran == job)
+            .map(|(_, took)| *took)
+            .unwrap_or_default();
+        let average = durations.iter().map(|(_, took)| *took).sum::<Duration>()
+            / u32::try_from(durations.len().max(1)).unwrap_or(u32::MAX);
+        drop(durations);
+        let finished =
+            self.lock_completed().len() + self.lock_failed().len() + self.lock_tolerated().len();
+        let remaining = self.scheduled.len().saturating_sub(finished);
+        let mut line = format!(
+            "[{}/{}] {} done in {:.1}s (elapsed {}",
+            index,
+            self.scheduled.len(),
+            job,
+            took.as_secs_f64(),
+            format_secs(self.started.elapsed())
+        );
+        if remaining > 0 {
+            let eta = average * u32::try_from(remaining).unwrap_or(u32::MAX)
+                / u32::try_from(self.parallel.max(1)).unwrap_or(u32::MAX);
+            let _ = write!(line, ", ETA {}", format_secs(eta));
+        }
+        line.push(')');
+        line
+    }
+
+    fn lock_durations(&self) -> MutexGuard<'_, Vec<(String, Duration)>> {
+        self.durations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Each job that ran, in the order they finished, with how long it took and whether it
+    /// succeeded.
+    pub fn results(&self) -> Vec<(String, Duration, bool)> {
+        self.lock_durations()
+            .iter()
+            .map(|(job, took)| {
+                let succeeded = !self.failed(job) && !self.tolerated(job);
+                (job.clone(), *took, succeeded)
+            })
+            .collect()
+    }
+
+    /// A single line summing up the run, like `devmaker: 12 ok, 1 failed (db), 2 skipped`.
+    ///
+    /// Jobs that neither completed nor failed were skipped, whether by their condition or
+    /// because of an earlier failure.  Failures of `allow_failure` jobs are counted as
+    /// tolerated, and jobs the last run completed as already done.  Counts of zero are left out,
+    /// other than `ok`.
+    pub fn summary(&self) -> String {
+        let completed = self.lock_completed().len();
+        let already_done = self.lock_already_done().len();
+        let failed = self.lock_failed().clone();
+        let tolerated = self.lock_tolerated().clone();
+        let skipped = self
+            .scheduled
+            .len()
+            .saturating_sub(completed + failed.len() + tolerated.len());
+        let mut summary = format!("devmaker: {} ok", completed - already_done);
+        if already_done > 0 {
+            let _ = write!(summary, ", {already_done} already done");
+        }
+        if !failed.is_empty() {
+            let _ = write!(summary, ", {} failed ({})", failed.len(), failed.join(", "));
+        }
+        if !tolerated.is_empty() {
+            let _ = write!(
+                summary,
+                ", {} tolerated ({})",
+                tolerated.len(),
+                tolerated.join(", ")
+            );
+        }
+        if skipped > 0 {
+            let _ = write!(summary, ", {skipped} skipped");
+        }
+        summary
+    }
+
+    /// Run the `--on-complete` command, with a summary of the run in its env.
+    ///
+    /// Its own failure is only logged, so it can't change the outcome of the run.
+    pub fn notify(&self, config: &Config, succeeded: bool) {
+        if let Some(command) = &config.on_complete {
+            debug!("Running notify command: {command}");
+            let mut notify = process::Command::new(DEFAULT_SHELL);
+            notify
+                .arg("-c")
+                .arg(command)
+                .env(
+                    "DEVMAKER_RESULT",
+                    if succeeded { "success" } else { "failure" },
+                )
+                .env("DEVMAKER_SCHEDULED", self.scheduled.join("\n"))
+                .env("DEVMAKER_COMPLETED", self.lock_completed().join("\n"))
+                .env("DEVMAKER_FAILED", self.lock_failed().join("\n"));
+            if self.trace_exec {
+                trace_command(&notify, &HashSet::new());
+            }
+            let status = notify.status();
+            match status {
+                Ok(status) if status.success() => {}
+                Ok(status) => warn!("Notify command failed with {status}: {command}"),
+                Err(e) => warn!("Cannot run notify command {command}: {e}"),
+            }
+        }
+    }
+}
+
+/// Log a command about to run, for `--trace-exec`, like
+/// `exec: sh run.sh (cwd=/src, env: FOO=bar TOKEN=***)`.  The env is what the command is given
+/// on top of our own, with the values of `secure_vars` redacted.
+fn trace_command(command: &process::Command, secure_vars: &HashSet<String>) {
+    let mut line = format!("exec: {}", quote_arg(command.get_program()));
+    for arg in command.get_args() {
+        line.push(' ');
+        line.push_str(&quote_arg(arg));
+    }
+    let cwd = command
+        .get_current_dir()
+        .map(Path::to_path_buf)
+        .or_else(|| env::current_dir().ok())
+        .unwrap_or_default();
+    // Removed vars have no value, and aren't shown.
+    let mut vars: Vec<String> = command
+        .get_envs()
+        .filter_map(|(key, value)| Some((key.to_string_lossy(), value?)))
+        .map(|(key, value)| {
+            if secure_vars.contains(key.as_ref()) {
+                format!("{key}={REDACTED}")
+            } else {
+                format!("{}={}", key, quote_arg(value))
+            }
+        })
+        .collect();
+    vars.sort();
+    info!("{} (cwd={}, env: {})", line, cwd.display(), vars.join(" "));
+}
+
+/// An argument or value as written in the trace, quoted if it wouldn't read as one word.
+fn quote_arg(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || "\"'\\".contains(c)) {
+        format!("{arg:?}")
+    } else {
+        arg.into_owned()
+    }
+}
+
+impl InlineRun {
+    #[inline]
+    fn describe(&self) -> String {
+        format!("{} -c {:?}", self.shell, self.command)
+    }
+}
+
+impl Runner {
+    fn describe(&self) -> String {
+        match self {
+            Self::Script(path) => path.display().to_string(),
+            Self::Inline(inline) => inline.describe(),
+            Self::Exec(argv) => argv.join(" "),
+        }
+    }
+
+    /// With `login`, the runner goes through a login shell, which sources the profile files.
+    fn command(&self, job: &str, login: bool) -> Result<process::Command> {
+        match self {
+            Self::Script(path) if login => {
+                ensure_executable(path, job)?;
+                let mut command = process::Command::new(DEFAULT_SHELL);
+                command.arg("-l").arg("-c").arg("exec \"$0\"").arg(path);
+                Ok(command)
+            }
+            Self::Script(path) => {
+                ensure_executable(path, job)?;
+                Ok(process::Command::new(path))
+            }
+            Self::Inline(inline) => {
+                let mut command = process::Command::new(&inline.shell);
+                if login {
+                    command.arg("-l");
+                }
+                command.arg("-c").arg(&inline.command);
+                Ok(command)
+            }
+            Self::Exec(argv) if login => {
+                let mut command = process::Command::new(DEFAULT_SHELL);
+                command
+                    .arg("-l")
+                    .arg("-c")
+                    .arg("exec \"$0\" \"$@\"")
+                    .args(argv);
+                Ok(command)
+            }
+            Self::Exec(argv) => {
+                // `locate_runner` rejects an empty argv.
+                let mut command = process::Command::new(&argv[0]);
+                command.args(&argv[1..]);
+                Ok(command)
+            }
+        }
+    }
+}
+
+fn not_regular_error(what: &str, name: &str, path: &Path) -> Error {
+    let file_name = file_name(path);
+    let shown = match fs::read_link(path) {
+        Ok(target) => format!("{} -> {}", file_name, target.display()),
+        Err(_) => file_name.into_owned(),
+    };
+    anyhow!(format!(
+        "{} for '{}' is not a regular file: {}",
+        what, name, shown
+    ))
+}
+
+/// Find a `run.*` file, skipping directories, dangling symlinks and the like.
+///
+/// If the only matches aren't regular files, that's an error rather than a missing runner.
+fn find_script(job_dir: &Path, name: &str) -> Result<Option<PathBuf>> {
+    let default = job_dir.join("run.sh");
+    if default.is_file() {
+        return Ok(Some(default));
+    }
+    let pattern = format!("{}/run.*", job_dir.display());
+    let mut irregular = None;
+    for candidate in glob::glob(&pattern)? {
+        let candidate = candidate?;
+        if candidate.is_file() {
+            return Ok(Some(candidate));
+        }
+        debug!("Skipping non-regular runner: {}", candidate.display());
+        irregular.get_or_insert(candidate);
+    }
+    irregular.map_or(Ok(None), |path| {
+        Err(not_regular_error("Runner", name, &path))
+    })
+}
+
+/// Whether the job in `job_dir` has the optional script `file`, such as `deps.sh` or `undo.sh`.
+///
+/// Errors if `file` exists but isn't a regular file.
+pub(crate) fn find_job_script(job_dir: &Path, name: &str, file: &str) -> Result<bool> {
+    let path = job_dir.join(file);
+    if path.is_file() {
+        Ok(true)
+    } else if fs::symlink_metadata(&path).is_ok() {
+        Err(not_regular_error(file, name, &path))
+    } else {
+        Ok(false)
+    }
+}
+
+/// Find the runner for the job in `job_dir`, preferring `run.sh` over other `run.*` files.
+///
+/// An inline `run` command or a `script` path is used instead, but only one kind may be given.
+/// A `runner` path skips the search entirely, so it may sit alongside `run.*` files.
+pub(crate) fn locate_runner(
+    job_dir: &Path,
+    name: &str,
+    inline_run: Option<&InlineRun>,
+    script: Option<&str>,
+    runner: Option<&str>,
+    exec: Option<&[String]>,
+) -> Result<Runner> {
+    if let Some(exec) = exec {
+        if inline_run.is_some()
+            || script.is_some()
+            || runner.is_some()
+            || find_script(job_dir, name)?.is_some()
+        {
+            return Err(anyhow!(format!(
+                "Job '{}' has an exec command, so it can't also have a run.* file, an inline run \
+                 command, a script or a runner",
+                name
+            )));
+        }
+        if exec.is_empty() {
+            return Err(anyhow!(format!("Job '{}' has an empty exec command", name)));
+        }
+        return Ok(Runner::Exec(exec.to_vec()));
+    }
+    if let Some(runner) = runner {
+        if inline_run.is_some() || script.is_some() {
+            return Err(anyhow!(format!(
+                "Job '{}' names a runner, so it can't also have an inline run command or a script",
+                name
+            )));
+        }
+        return named_runner("Runner", name, job_dir.join(runner));
+    }
+    let named_script = script.map(|script| job_dir.join(script));
+    match (find_script(job_dir, name)?, inline_run, named_script) {
+        (None, Some(inline), None) => Ok(Runner::Inline(inline.clone())),
+        (Some(script), None, None) => Ok(Runner::Script(script)),
+        (None, None, Some(script)) => named_runner("Script", name, script),
+        (None, None, None) => Err(anyhow!(format!("No runner found for job '{}'", name))),
+        _ => Err(anyhow!(format!(
+            "Job '{}' has more than one of a run.* file, an inline run command, and a script",
+            name
+        ))),
+    }
+}
+
+/// A runner file named in `info.json`, which must be a regular file.
+fn named_runner(what: &str, name: &str, path: PathBuf) -> Result<Runner> {
+    if path.is_file() {
+        Ok(Runner::Script(path))
+    } else if fs::symlink_metadata(&path).is_ok() {
+        Err(not_regular_error(what, name, &path))
+    } else {
+        Err(anyhow!(format!(
+            "{} for job '{}' doesn't exist: {}",
+            what,
+            name,
+            path.display()
+        )))
+    }
+}
+
+/// Write `KEY=value` lines for the secrets into a file only we can read, returning its path.
+///
+/// It lives in the job's temp dir, so it's removed along with it.
+fn write_secrets_file(tmp_dir: &Path, secrets: &EnvMap) -> Result<PathBuf> {
+    let path = tmp_dir.join(SECRETS_FILE);
+    let mut keys: Vec<&String> = secrets.keys().collect();
+    keys.sort();
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    for key in keys {
+        writeln!(file, "{}={}", key, secrets[key])?;
+    }
+    Ok(path)
+}
+
+fn is_output_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Job names may contain characters env names can't, so those become `_`.
+fn output_env_name(job: &str, key: &str) -> String {
+    let job: String = job
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("DEVMAKER_OUT_{}_{}", job, key.to_uppercase())
+}
+
+fn ensure_executable(file: &Path, job: &str) -> Result<()> {
+    if is_executable::is_executable(file) {
         return Ok(());
+    }
+    let mode: u32 = fs::metadata(file)?.permissions().mode() | 0o100;
+    fs::set_permissions(file, Permissions::from_mode(mode)).map_err(|e| chmod_error(job, file, &e))
+}
+
+/// Explain why a script that isn't executable couldn't be made so.
+fn chmod_error(job: &str, file: &Path, error: &io::Error) -> Error {
+    let reason = match error.raw_os_error() {
+        Some(libc::EROFS) => "read-only filesystem".to_owned(),
+        Some(libc::EPERM) => "not the file's owner".to_owned(),
+        _ => error.to_string(),
     };
-    let mode: u32 = fs::metadata(&file)?.permissions().mode() | 100;
-    fs::set_permissions(&file, Permissions::from_mode(mode))?;
-    Ok(())
+    anyhow!(format!(
+        "Cannot execute job '{}': {} is not executable and permissions could not be changed ({})",
+        job,
+        file_name(file),
+        reason
+    ))
+}
+
+/// Explain a runner the OS refused to execute, which its mode alone doesn't rule out.
+fn not_executable_error(job: &str, runner: &Runner, error: Error) -> Error {
+    let kind = error.downcast_ref::<io::Error>().map(io::Error::kind);
+    let denied = kind == Some(io::ErrorKind::PermissionDenied);
+    match runner {
+        Runner::Exec(argv) if kind == Some(io::ErrorKind::NotFound) => anyhow!(format!(
+            "Cannot execute job '{}': {} was not found",
+            job, argv[0]
+        )),
+        Runner::Script(path) if denied => anyhow!(format!(
+            "Cannot execute job '{}': {} is executable, but running it was denied \
+             (is its filesystem mounted noexec?)",
+            job,
+            file_name(path)
+        )),
+        _ => error,
+    }
+}
+
+/// Format a duration in whole seconds, like `41s`, `2m05s` or `1h10m`.
+fn format_secs(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+fn file_name(path: &Path) -> Cow<'_, str> {
+    path.file_name().unwrap_or_default().to_string_lossy()
 }
 
 #[inline]
@@ -176,7 +1488,832 @@ fn info_style() -> Style {
     Style::new().dim()
 }
 
+#[inline]
+fn env_key_style() -> Style {
+    Style::new().cyan()
+}
+
+#[inline]
+fn secure_style() -> Style {
+    Style::new().yellow().dim()
+}
+
 #[inline]
 fn job_style() -> Style {
     Style::new().blue().bold()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    use crate::signals::running_jobs;
+
+    #[test]
+    fn ensure_executable_sets_the_owner_execute_bit() {
+        let dir = TempDir::new("devmaker-test").unwrap();
+        let script = dir.path().join("run.sh");
+        fs::write(&script, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&script, Permissions::from_mode(0o600)).unwrap();
+
+        ensure_executable(&script, "job").unwrap();
+
+        let mode = fs::metadata(&script).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        assert!(is_executable::is_executable(&script));
+    }
+
+    #[test]
+    fn chmod_error_explains_common_failures() {
+        let script = Path::new("/jobs/db/run.sh");
+        let read_only = chmod_error("db", script, &io::Error::from_raw_os_error(libc::EROFS));
+        assert_eq!(
+            read_only.to_string(),
+            "Cannot execute job 'db': run.sh is not executable and permissions could not be \
+             changed (read-only filesystem)"
+        );
+        let not_owner = chmod_error("db", script, &io::Error::from_raw_os_error(libc::EPERM));
+        assert!(not_owner.to_string().ends_with("(not the file's owner)"));
+    }
+
+    fn ready_job(name: &str, env: &[(&str, &str)]) -> ReadyJob {
+        ready_job_with_info(name, "{}", env)
+    }
+
+    fn ready_job_with_info(name: &str, info: &str, env: &[(&str, &str)]) -> ReadyJob {
+        let spec = JobSpec::new(name.to_owned(), serde_json::from_str(info).unwrap(), false);
+        let env = env
+            .iter()
+            .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+            .collect();
+        ReadyJob::new(spec, env, HashSet::new(), HashSet::new())
+    }
+
+    #[test]
+    fn inherited_env_gives_way_to_later_bases_and_the_job_itself() {
+        let first = ready_job("first", &[("A", "first"), ("B", "first"), ("C", "first")]);
+        let second = ready_job("second", &[("b", "second"), ("c", "second")]);
+        let mut job = ready_job("job", &[("c", "job")]);
+        job.inherit_env(&[first, second]);
+        assert_eq!(job.env()["A"], "first");
+        assert_eq!(job.env()["B"], "second");
+        assert_eq!(job.env()["C"], "job");
+        assert_eq!(job.env().len(), 3);
+    }
+
+    #[test]
+    fn dry_run_reports_jobs_whose_condition_fails_as_skipped() {
+        console::set_colors_enabled(false);
+        let root = TempDir::new("devmaker-test").unwrap();
+        let config = Config::for_test(root.path(), &["--dry-run", "--eval-conditions"]);
+        let context = RunContext::new(vec!["cached".to_owned()], &config);
+        let info = r#"{"run": "make", "when": "test \"$TARGET\" != built"}"#;
+        let job = ready_job_with_info("cached", info, &[("TARGET", "built")]);
+
+        let condition = job.condition_met(root.path(), &context).unwrap();
+        assert!(!condition);
+        let report = job.report(root.path(), 0, Some(condition), &context);
+        let mut lines = report.lines();
+        assert_eq!(lines.next(), Some("Would skip job 000: cached"));
+        assert!(lines.next().unwrap().ends_with("(not met)"));
+
+        let stale = ready_job_with_info("cached", info, &[("TARGET", "stale")]);
+        assert!(stale.condition_met(root.path(), &context).unwrap());
+        let report = stale.report(root.path(), 0, Some(true), &context);
+        assert!(report.starts_with("Would run job 000: cached"));
+    }
+
+    #[test]
+    fn dry_run_reports_the_runner_that_would_be_picked() {
+        console::set_colors_enabled(false);
+        let root = TempDir::new("devmaker-test").unwrap();
+        let job_dir = root.path().join("build");
+        fs::create_dir(&job_dir).unwrap();
+        for file in &["run.py", "run.sh", DEPS_SCRIPT] {
+            fs::write(job_dir.join(file), "").unwrap();
+        }
+        let config = Config::for_test(root.path(), &["--dry-run"]);
+        let context = RunContext::new(vec!["build".to_owned()], &config);
+        let spec = JobSpec::new(
+            "build".to_owned(),
+            serde_json::from_str("{}").unwrap(),
+            true,
+        );
+        let job = ReadyJob::new(spec, EnvMap::new(), HashSet::new(), HashSet::new());
+
+        let report = job.report(root.path(), 0, None, &context);
+        let deps = format!("  Deps.sh: {}", job_dir.join(DEPS_SCRIPT).display());
+        let runner = format!("  Runner: {}", job_dir.join("run.sh").display());
+        assert!(report.lines().any(|line| line == deps), "{}", report);
+        assert!(report.lines().any(|line| line == runner), "{}", report);
+
+        fs::remove_file(job_dir.join("run.sh")).unwrap();
+        fs::remove_file(job_dir.join("run.py")).unwrap();
+        let report = job.report(root.path(), 0, None, &context);
+        assert!(report.contains("  Runner: no runner found (No runner found for job 'build')"));
+    }
+
+    #[test]
+    fn dry_run_reports_the_command_line_and_working_dir() {
+        console::set_colors_enabled(false);
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::create_dir_all(root.path().join("build/src")).unwrap();
+        let config = Config::for_test(root.path(), &["--dry-run"]);
+        let context = RunContext::new(vec!["build".to_owned()], &config);
+        let info = r#"{"exec": ["make", "-j4", "install"], "cwd": "${SCRIPT_DIR}/src"}"#;
+        let job = ready_job_with_info("build", info, &[]);
+
+        let report = job.report(root.path(), 0, None, &context);
+        let working_dir = format!("  Working dir: {}", root.path().join("build/src").display());
+        assert!(
+            report
+                .lines()
+                .any(|line| line == "  Exec: make -j4 install"),
+            "{}",
+            report
+        );
+        assert!(report.lines().any(|line| line == working_dir), "{}", report);
+
+        let info = r#"{"run": "make", "shell": "bash", "cwd": "${SCRIPT_DIR}/missing"}"#;
+        let job = ready_job_with_info("build", info, &[]);
+        let report = job.report(root.path(), 0, None, &context);
+        assert!(
+            report.contains("  Inline run: bash -c \"make\""),
+            "{}",
+            report
+        );
+        assert!(report.contains("  Working dir: cannot be resolved (Working directory for job"));
+    }
+
+    #[test]
+    fn report_lists_env_sorted_aligned_and_redacted() {
+        console::set_colors_enabled(false);
+        let spec = JobSpec::new("db".to_owned(), serde_json::from_str("{}").unwrap(), false);
+        let env = [
+            ("PORT", "5432"),
+            ("HOST", "db.local"),
+            ("PASSWORD", "hunter2"),
+        ]
+        .iter()
+        .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+        .collect();
+        let secure = HashSet::from(["PASSWORD".to_owned()]);
+        let job = ReadyJob::new(spec, env, secure, HashSet::new());
+        let mut report = String::new();
+        job.report_env(&mut report);
+        assert_eq!(
+            report,
+            "\n  Env: HOST     -> db.local\n  Env: PASSWORD -> ***\n  Env: PORT     -> 5432"
+        );
+    }
+
+    #[test]
+    fn job_fails_when_it_does_not_create_its_verify_path() {
+        let _jobs = running_jobs();
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::create_dir(root.path().join("build")).unwrap();
+        let config = Config::for_test(root.path(), &[]);
+        let context = RunContext::new(vec!["build".to_owned()], &config);
+        let info = r#"{"run": "true", "verify_path": "${TARGET}/done"}"#;
+        let job = ready_job_with_info("build", info, &[("TARGET", "out")]);
+
+        let error = job.run(root.path(), &context).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Job 'build' succeeded, but did not create {}",
+                root.path().join("build/out/done").display()
+            )
+        );
+
+        fs::create_dir_all(root.path().join("build/out")).unwrap();
+        fs::write(root.path().join("build/out/done"), "").unwrap();
+        job.run(root.path(), &context).unwrap();
+    }
+
+    #[test]
+    fn a_named_runner_is_used_over_run_files() {
+        let dir = TempDir::new("devmaker-test").unwrap();
+        for file in &["run.sh", "setup.sh"] {
+            fs::write(dir.path().join(file), "").unwrap();
+        }
+        let runner = locate_runner(dir.path(), "foo", None, None, Some("setup.sh"), None).unwrap();
+        assert!(matches!(runner, Runner::Script(path) if path == dir.path().join("setup.sh")));
+
+        let error = locate_runner(dir.path(), "foo", None, None, Some("install.sh"), None)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            format!(
+                "Runner for job 'foo' doesn't exist: {}",
+                dir.path().join("install.sh").display()
+            )
+        );
+
+        let error = locate_runner(
+            dir.path(),
+            "foo",
+            None,
+            Some("run.sh"),
+            Some("setup.sh"),
+            None,
+        )
+        .unwrap_err()
+        .to_string();
+        assert_eq!(
+            error,
+            "Job 'foo' names a runner, so it can't also have an inline run command or a script"
+        );
+    }
+
+    #[test]
+    fn exec_jobs_run_their_argv_with_vars_expanded() {
+        let _jobs = running_jobs();
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::create_dir(root.path().join("greet")).unwrap();
+        let config = Config::for_test(root.path(), &[]);
+        let context = RunContext::new(vec!["greet".to_owned()], &config);
+        let info = serde_json::json!({
+            "exec": ["sh", "-c", r#"printf '%s\n' "$@" > "$SCRIPT_DIR/seen""#, "sh",
+                     "${GREETING}, world", "$GREETING"],
+        });
+        let job = ready_job_with_info("greet", &info.to_string(), &[("GREETING", "hello")]);
+        job.run(root.path(), &context).unwrap();
+        let seen = fs::read_to_string(root.path().join("greet/seen")).unwrap();
+        // Only `${VAR}` is expanded, so a bare `$` reaches the program as it is.
+        assert_eq!(seen, "hello, world\n$GREETING\n");
+
+        fs::write(root.path().join("greet/run.sh"), "").unwrap();
+        let error = job.run(root.path(), &context).unwrap_err().to_string();
+        assert!(error.starts_with("Job 'greet' has an exec command, so it can't also have"));
+        let empty = ready_job_with_info("greet", r#"{"exec": []}"#, &[]);
+        fs::remove_file(root.path().join("greet/run.sh")).unwrap();
+        let error = empty.run(root.path(), &context).unwrap_err().to_string();
+        assert_eq!(error, "Job 'greet' has an empty exec command");
+    }
+
+    #[test]
+    fn traced_commands_are_logged_with_secure_values_redacted() {
+        let _jobs = running_jobs();
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::create_dir(root.path().join("greet")).unwrap();
+        let info = r#"{"exec": ["echo", "hello world"]}"#;
+        let spec = JobSpec::new(
+            "greet".to_owned(),
+            serde_json::from_str(info).unwrap(),
+            false,
+        );
+        let env = [("GREETING", "hi"), ("API_TOKEN", "hunter2")]
+            .iter()
+            .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+            .collect();
+        let secure = std::iter::once("API_TOKEN".to_owned()).collect();
+        let job = ReadyJob::new(spec, env, secure, HashSet::new());
+
+        let traced = |flags: &[&str]| {
+            let config = Config::for_test(root.path(), flags);
+            let context = RunContext::new(vec!["greet".to_owned()], &config);
+            crate::common::capture_logs(|| job.run(root.path(), &context).unwrap())
+                .into_iter()
+                .filter(|line| line.starts_with("INFO: exec: "))
+                .collect::<Vec<_>>()
+        };
+        let lines = traced(&["--trace-exec"]);
+        assert_eq!(lines.len(), 1, "{lines:?}");
+        let cwd = format!("(cwd={}, env: ", env::current_dir().unwrap().display());
+        assert!(
+            lines[0].starts_with(r#"INFO: exec: echo "hello world" "#),
+            "{}",
+            lines[0]
+        );
+        assert!(lines[0].contains(&cwd), "{}", lines[0]);
+        assert!(lines[0].contains(" API_TOKEN=*** "), "{}", lines[0]);
+        assert!(lines[0].contains(" GREETING=hi "), "{}", lines[0]);
+        assert!(!lines[0].contains("hunter2"));
+
+        assert!(traced(&[]).is_empty());
+    }
+
+    fn runner_error(job_dir: &Path) -> String {
+        locate_runner(job_dir, "foo", None, None, None, None)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn runners_and_deps_scripts_must_be_regular_files() {
+        let dir = TempDir::new("devmaker-test").unwrap();
+        fs::create_dir(dir.path().join("run.sh")).unwrap();
+        assert_eq!(
+            runner_error(dir.path()),
+            "Runner for 'foo' is not a regular file: run.sh"
+        );
+
+        fs::write(dir.path().join("run.py"), "").unwrap();
+        let runner = locate_runner(dir.path(), "foo", None, None, None, None).unwrap();
+        assert!(matches!(runner, Runner::Script(path) if path.ends_with("run.py")));
+
+        let dangling = TempDir::new("devmaker-test").unwrap();
+        std::os::unix::fs::symlink("/nonexistent", dangling.path().join("run.sh")).unwrap();
+        assert_eq!(
+            runner_error(dangling.path()),
+            "Runner for 'foo' is not a regular file: run.sh -> /nonexistent"
+        );
+        std::os::unix::fs::symlink("/nonexistent", dangling.path().join(DEPS_SCRIPT)).unwrap();
+        assert_eq!(
+            find_job_script(dangling.path(), "foo", DEPS_SCRIPT)
+                .unwrap_err()
+                .to_string(),
+            format!("{DEPS_SCRIPT} for 'foo' is not a regular file: {DEPS_SCRIPT} -> /nonexistent")
+        );
+        assert!(!find_job_script(dir.path(), "foo", DEPS_SCRIPT).unwrap());
+    }
+
+    fn proc_env(root: &Path, flags: &[&str]) -> EnvMap {
+        let config = Config::for_test(root, flags);
+        let context = RunContext::new(vec!["job".to_owned()], &config);
+        ready_job("job", &[])
+            .create_proc_env(root, &context)
+            .unwrap()
+    }
+
+    #[test]
+    fn as_user_and_home_override_the_injected_env() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let env = proc_env(root.path(), &["--as-user", "root"]);
+        assert_eq!(env["USER"], "root");
+        assert_eq!(env["USERNAME"], "root");
+        assert_eq!(env["HOME"], "/root");
+
+        let env = proc_env(root.path(), &["--as-user", "alice", "--home", "/srv/alice"]);
+        assert_eq!(env["USER"], "alice");
+        assert_eq!(env["HOME"], "/srv/alice");
+
+        let env = proc_env(root.path(), &["--home", "/srv/shared"]);
+        assert_eq!(env["USER"], whoami::username());
+        assert_eq!(env["HOME"], "/srv/shared");
+    }
+
+    #[test]
+    fn secrets_as_file_writes_a_private_file_removed_after_the_run() {
+        let _jobs = running_jobs();
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::create_dir(root.path().join("deploy")).unwrap();
+        let config = Config::for_test(root.path(), &["--secrets-as-file"]);
+        let context = RunContext::new(vec!["deploy".to_owned()], &config);
+        let script = r#"cd "$SCRIPT_DIR"
+            cp "$DEVMAKER_SECRETS_FILE" secrets
+            stat -c %a "$DEVMAKER_SECRETS_FILE" > mode
+            echo "$DEVMAKER_SECRETS_FILE" > path
+            echo "${PASSWORD-unset} $USER_NAME" > env"#;
+        let info = serde_json::json!({ "run": script }).to_string();
+        let spec = JobSpec::new(
+            "deploy".to_owned(),
+            serde_json::from_str(&info).unwrap(),
+            false,
+        );
+        let env = [("PASSWORD", "hunter2"), ("USER_NAME", "admin")]
+            .iter()
+            .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+            .collect();
+        let secure = HashSet::from(["PASSWORD".to_owned()]);
+        let job = ReadyJob::new(spec, env, secure, HashSet::new());
+        job.run(root.path(), &context).unwrap();
+
+        let read = |file: &str| fs::read_to_string(root.path().join("deploy").join(file)).unwrap();
+        assert_eq!(read("secrets"), "PASSWORD=hunter2\n");
+        assert_eq!(read("mode"), "600\n");
+        assert_eq!(read("env"), "unset admin\n");
+        let path = read("path");
+        let path = Path::new(path.trim());
+        assert!(path.ends_with(SECRETS_FILE));
+        assert!(!path.parent().unwrap().exists());
+
+        let config = Config::for_test(
+            root.path(),
+            &["--secrets-as-file", "--temp-cleanup", "never"],
+        );
+        let context = RunContext::new(vec!["deploy".to_owned()], &config);
+        job.run(root.path(), &context).unwrap();
+        let path = read("path");
+        let kept = Path::new(path.trim()).parent().unwrap();
+        assert!(kept.is_dir());
+        assert!(!kept.join(SECRETS_FILE).exists());
+        fs::remove_dir_all(kept).unwrap();
+    }
+
+    #[test]
+    fn outputs_reach_dependent_jobs_under_prefixed_names() {
+        let _jobs = running_jobs();
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::create_dir(root.path().join("install-tool")).unwrap();
+        fs::create_dir(root.path().join("use-tool")).unwrap();
+        let config = Config::for_test(root.path(), &[]);
+        let scheduled = vec!["install-tool".to_owned(), "use-tool".to_owned()];
+        let context = RunContext::new(scheduled, &config);
+        let producer = ready_job_with_info(
+            "install-tool",
+            r#"{"run": "printf '# installed\nversion=1.2.3\n' > \"$DEVMAKER_OUTPUT\""}"#,
+            &[],
+        );
+        let consumer = ready_job_with_info(
+            "use-tool",
+            r#"{"run": "echo \"$DEVMAKER_OUT_INSTALL_TOOL_VERSION\" > \"$SCRIPT_DIR/seen\"",
+                "depends": ["install-tool"]}"#,
+            &[],
+        );
+        producer.run(root.path(), &context).unwrap();
+        consumer.run(root.path(), &context).unwrap();
+        let seen = fs::read_to_string(root.path().join("use-tool/seen")).unwrap();
+        assert_eq!(seen, "1.2.3\n");
+    }
+
+    #[test]
+    fn captured_stdout_reaches_dependent_jobs_trimmed() {
+        let _jobs = running_jobs();
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::create_dir(root.path().join("version")).unwrap();
+        fs::create_dir(root.path().join("release")).unwrap();
+        fs::create_dir(root.path().join("unrelated")).unwrap();
+        let config = Config::for_test(root.path(), &[]);
+        let scheduled = vec![
+            "version".to_owned(),
+            "release".to_owned(),
+            "unrelated".to_owned(),
+        ];
+        let context = RunContext::new(scheduled, &config);
+        let producer = ready_job_with_info(
+            "version",
+            r#"{"run": "printf '  2.0.1\n\n'", "capture_output_var": "VERSION"}"#,
+            &[],
+        );
+        let seen = r#"echo "[${VERSION-unset}]" > "$SCRIPT_DIR/seen""#;
+        let consumer = ready_job_with_info(
+            "release",
+            &serde_json::json!({"run": seen, "depends": ["version"]}).to_string(),
+            &[],
+        );
+        let unrelated = ready_job_with_info(
+            "unrelated",
+            &serde_json::json!({ "run": seen }).to_string(),
+            &[],
+        );
+        producer.run(root.path(), &context).unwrap();
+        consumer.run(root.path(), &context).unwrap();
+        unrelated.run(root.path(), &context).unwrap();
+        let seen = |job: &str| fs::read_to_string(root.path().join(job).join("seen")).unwrap();
+        assert_eq!(seen("release"), "[2.0.1]\n");
+        assert_eq!(seen("unrelated"), "[unset]\n");
+    }
+
+    #[test]
+    fn unparseable_outputs_fail_the_job() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let config = Config::for_test(root.path(), &[]);
+        let context = RunContext::new(vec!["tool".to_owned()], &config);
+        let error = context
+            .record_outputs("tool", "VERSION=1\nnot an output\n")
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Job 'tool' wrote an unparseable output on line 2"
+        );
+    }
+
+    #[test]
+    fn failing_jobs_dump_their_env_beside_the_logs() {
+        let _jobs = running_jobs();
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::create_dir(root.path().join("db")).unwrap();
+        let logs = root.path().join("logs");
+        let logs_arg = logs.to_str().unwrap();
+        let config = Config::for_test(
+            root.path(),
+            &["--dump-env-on-failure", "--log-dir", logs_arg],
+        );
+        let context = RunContext::new(vec!["db".to_owned()], &config);
+        let spec = JobSpec::new(
+            "db".to_owned(),
+            serde_json::from_str(r#"{"run": "exit 3"}"#).unwrap(),
+            false,
+        );
+        let env = [("HOST", "db.local"), ("PASSWORD", "hunter2")]
+            .iter()
+            .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+            .collect();
+        let secure = HashSet::from(["PASSWORD".to_owned()]);
+        let job = ReadyJob::new(spec, env, secure, HashSet::new());
+        let error = job.run(root.path(), &context).unwrap_err();
+        assert_eq!(error.to_string(), "Job 'db' failed with exit code 3");
+
+        let dump = fs::read_to_string(logs.join("devmaker-failed-db.env")).unwrap();
+        let mut lines = dump.lines();
+        assert_eq!(
+            lines.next(),
+            Some("# Env of job 'db', which failed with exit code 3")
+        );
+        assert!(lines.next().unwrap().starts_with("# Runner: "));
+        assert!(dump.contains("\nHOST=db.local\n"));
+        assert!(dump.contains("\nPASSWORD=***\n"));
+        assert!(dump.contains(&format!(
+            "\nSCRIPT_DIR={}\n",
+            root.path().join("db").display()
+        )));
+        let mode = fs::metadata(logs.join("devmaker-failed-db.env"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn notify_command_gets_the_run_result() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let out = root.path().join("notified");
+        let command = format!(
+            "printf '%s|%s|%s' \"$DEVMAKER_RESULT\" \"$DEVMAKER_COMPLETED\" \"$DEVMAKER_FAILED\" > {}",
+            out.display()
+        );
+        let config = Config::for_test(root.path(), &["--on-complete", &command]);
+        let scheduled = vec!["db".to_owned(), "app".to_owned(), "web".to_owned()];
+        let context = RunContext::new(scheduled, &config);
+        context.mark_completed("db");
+        context.mark_completed("web");
+        let _ = context.track("app", Err::<(), _>(anyhow!("failed")));
+
+        context.notify(&config, false);
+        let notified = fs::read_to_string(&out).unwrap();
+        assert_eq!(notified, "failure|db\nweb|app");
+    }
+
+    /// Run a job printing its working directory to `pwd` in its own directory.
+    fn run_in_cwd(root: &Path, cwd: &str, env: &[(&str, &str)]) -> Result<String> {
+        let _jobs = running_jobs();
+        let dir = root.join("job");
+        fs::create_dir_all(&dir).unwrap();
+        let config = Config::for_test(root, &[]);
+        let context = RunContext::new(vec!["job".to_owned()], &config);
+        let info = serde_json::json!({ "run": "pwd -P > \"$SCRIPT_DIR/pwd\"", "cwd": cwd });
+        ready_job_with_info("job", &info.to_string(), env).run(root, &context)?;
+        Ok(fs::read_to_string(dir.join("pwd"))?.trim().to_owned())
+    }
+
+    #[test]
+    fn jobs_run_in_their_configured_cwd() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let root = fs::canonicalize(root.path()).unwrap();
+        let job_dir = root.join("job").display().to_string();
+        assert_eq!(run_in_cwd(&root, "script_dir", &[]).unwrap(), job_dir);
+
+        fs::create_dir(root.join("build")).unwrap();
+        let build = root.join("build").display().to_string();
+        let cwd = run_in_cwd(
+            &root,
+            "${OUT}/build",
+            &[("OUT", &root.display().to_string())],
+        );
+        assert_eq!(cwd.unwrap(), build);
+    }
+
+    #[test]
+    fn a_missing_cwd_fails_the_job_before_it_runs() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let error = run_in_cwd(root.path(), "/nonexistent/dir", &[]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Working directory for job 'job' doesn't exist: /nonexistent/dir"
+        );
+        assert!(!root.path().join("job/pwd").exists());
+    }
+
+    fn context_for(jobs: usize) -> (TempDir, RunContext) {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let config = Config::for_test(root.path(), &["--oneline-summary"]);
+        let scheduled = (0..jobs).map(|job| format!("job{job}")).collect();
+        let context = RunContext::new(scheduled, &config);
+        (root, context)
+    }
+
+    #[test]
+    fn jobs_get_their_position_in_the_schedule() {
+        let (root, context) = context_for(3);
+        let env = ready_job("job1", &[])
+            .create_proc_env(root.path(), &context)
+            .unwrap();
+        assert_eq!(env["DEVMAKER_JOB_INDEX"], "2");
+        assert_eq!(env["DEVMAKER_JOB_TOTAL"], "3");
+        assert_eq!(env["DEVMAKER_SCHEDULED"], "job0\njob1\njob2");
+
+        let unscheduled = ready_job("extra", &[])
+            .create_proc_env(root.path(), &context)
+            .unwrap();
+        assert!(!unscheduled.contains_key("DEVMAKER_JOB_INDEX"));
+        assert_eq!(unscheduled["DEVMAKER_JOB_TOTAL"], "3");
+    }
+
+    #[test]
+    fn reserved_env_beats_the_job_and_dependency_outputs_beat_both() {
+        let (root, context) = context_for(1);
+        context.record_captured("dep", "VERSION", "1.2\n");
+        context
+            .record_outputs("dep", "url=http://localhost\n")
+            .unwrap();
+        let env = ready_job_with_info(
+            "job0",
+            r#"{"depends": ["dep"]}"#,
+            &[("home", "/elsewhere"), ("VERSION", "job"), ("NAME", "job")],
+        )
+        .create_proc_env(root.path(), &context)
+        .unwrap();
+        assert_ne!(env["HOME"], "/elsewhere");
+        assert!(!env.contains_key("home"));
+        assert_eq!(env["VERSION"], "1.2");
+        assert_eq!(env["DEVMAKER_OUT_DEP_URL"], "http://localhost");
+        assert_eq!(env["NAME"], "job");
+    }
+
+    #[test]
+    fn temp_dirs_are_kept_per_the_cleanup_policy() {
+        let _jobs = running_jobs();
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::create_dir(root.path().join("job")).unwrap();
+        let kept = |policy: &str, exit: i32| {
+            let config = Config::for_test(root.path(), &["--temp-cleanup", policy]);
+            let context = RunContext::new(vec!["job".to_owned()], &config);
+            let run = format!(r#"echo "$TMP_DIR" > "$SCRIPT_DIR/tmp"; exit {exit}"#);
+            let job =
+                ready_job_with_info("job", &serde_json::json!({ "run": run }).to_string(), &[]);
+            assert_eq!(job.run(root.path(), &context).is_ok(), exit == 0);
+            let tmp_dir = fs::read_to_string(root.path().join("job/tmp")).unwrap();
+            let tmp_dir = Path::new(tmp_dir.trim_end());
+            let kept = tmp_dir.is_dir();
+            if kept {
+                fs::remove_dir_all(tmp_dir).unwrap();
+            }
+            kept
+        };
+        assert!(!kept("always", 0));
+        assert!(!kept("always", 1));
+        assert!(!kept("on-success", 0));
+        assert!(kept("on-success", 1));
+        assert!(kept("never", 0));
+        assert!(kept("never", 1));
+    }
+
+    #[test]
+    fn login_shells_see_what_the_profile_sets() {
+        let _jobs = running_jobs();
+        let root = TempDir::new("devmaker-test").unwrap();
+        let home = TempDir::new("devmaker-test").unwrap();
+        fs::write(
+            home.path().join(".profile"),
+            "export FROM_PROFILE=sourced\n",
+        )
+        .unwrap();
+        let job_dir = root.path().join("job");
+        fs::create_dir(&job_dir).unwrap();
+        let script = job_dir.join("run.sh");
+        fs::write(
+            &script,
+            "#!/bin/sh\necho \"${FROM_PROFILE-unset}\" >> \"$SCRIPT_DIR/seen\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let home = home.path().to_str().unwrap();
+        let run = |flags: &[&str], info: &str| {
+            let config = Config::for_test(root.path(), flags);
+            let context = RunContext::new(vec!["job".to_owned()], &config);
+            ready_job_with_info("job", info, &[])
+                .run(root.path(), &context)
+                .unwrap();
+        };
+        run(&["--home", home], "{}");
+        run(&["--home", home, "--login-shell"], "{}");
+        run(&["--home", home], r#"{"login_shell": true}"#);
+        let seen = fs::read_to_string(job_dir.join("seen")).unwrap();
+        assert_eq!(seen, "unset\nsourced\nsourced\n");
+    }
+
+    #[test]
+    fn skip_deps_and_only_deps_split_the_phases() {
+        let _jobs = running_jobs();
+        console::set_colors_enabled(false);
+        let root = TempDir::new("devmaker-test").unwrap();
+        let job_dir = root.path().join("job");
+        fs::create_dir(&job_dir).unwrap();
+        for (file, step) in &[(DEPS_SCRIPT, "deps"), ("run.sh", "run")] {
+            let script = job_dir.join(file);
+            fs::write(
+                &script,
+                format!("#!/bin/sh\necho {step} >> \"$SCRIPT_DIR/seen\"\n"),
+            )
+            .unwrap();
+            fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let spec = JobSpec::new("job".to_owned(), serde_json::from_str("{}").unwrap(), true);
+        let job = ReadyJob::new(spec, EnvMap::new(), HashSet::new(), HashSet::new());
+        // What ran, and the dry-run report, with the given flags.
+        let run = |flags: &[&str]| {
+            let config = Config::for_test(root.path(), flags);
+            let context = RunContext::new(vec!["job".to_owned()], &config);
+            job.run(root.path(), &context).unwrap();
+            let seen = fs::read_to_string(job_dir.join("seen")).unwrap();
+            fs::remove_file(job_dir.join("seen")).unwrap();
+            let dry_run = Config::for_test(root.path(), &[&["--dry-run"], flags].concat());
+            let context = RunContext::new(vec!["job".to_owned()], &dry_run);
+            (seen, job.report(root.path(), 0, None, &context))
+        };
+
+        let (seen, report) = run(&[]);
+        assert_eq!(seen, "deps\nrun\n");
+        assert!(!report.contains("(skipped"));
+        let (seen, report) = run(&["--skip-deps"]);
+        assert_eq!(seen, "run\n");
+        assert!(report.contains(" (skipped: --skip-deps)"), "{}", report);
+        assert!(!report.contains(" (skipped: --only-deps)"));
+        let (seen, report) = run(&["--only-deps"]);
+        assert_eq!(seen, "deps\n");
+        assert!(report.contains(" (skipped: --only-deps)"), "{}", report);
+        assert!(!report.contains(" (skipped: --skip-deps)"));
+    }
+
+    #[test]
+    fn completion_lines_show_progress_timing_and_eta() {
+        let (_root, context) = context_for(12);
+        context.record_duration("job0", Duration::from_millis(8200));
+        context.mark_completed("job0");
+        context.record_duration("job1", Duration::from_secs(10));
+        context.mark_completed("job1");
+        let line = context.completion_line("job1");
+        assert!(
+            line.starts_with("[2/12] job1 done in 10.0s (elapsed "),
+            "{}",
+            line
+        );
+        // Ten jobs left at the 9.1s average so far.
+        assert!(line.ends_with("s, ETA 1m31s)"), "{}", line);
+
+        let (_root, context) = context_for(1);
+        context.record_duration("job0", Duration::from_millis(50));
+        context.mark_completed("job0");
+        let line = context.completion_line("job0");
+        assert!(
+            line.starts_with("[1/1] job0 done in 0.1s (elapsed "),
+            "{}",
+            line
+        );
+        assert!(!line.contains("ETA"));
+
+        assert_eq!(format_secs(Duration::from_secs(59)), "59s");
+        assert_eq!(format_secs(Duration::from_secs(61)), "1m01s");
+        assert_eq!(format_secs(Duration::from_secs(3725)), "1h02m");
+    }
+
+    #[test]
+    fn summary_counts_each_outcome() {
+        let (_root, context) = context_for(15);
+        for job in 0..12 {
+            context.mark_completed(&format!("job{job}"));
+        }
+        let _ = context.track("db", Err::<(), _>(anyhow!("failed")));
+        assert_eq!(
+            context.summary(),
+            "devmaker: 12 ok, 1 failed (db), 2 skipped"
+        );
+
+        let flaky = ready_job_with_info("flaky", r#"{"allow_failure": true}"#, &[]);
+        context
+            .track_job(&flaky, Err::<(), _>(anyhow!("failed")))
+            .unwrap();
+        assert_eq!(
+            context.summary(),
+            "devmaker: 12 ok, 1 failed (db), 1 tolerated (flaky), 1 skipped"
+        );
+    }
+
+    #[test]
+    fn summary_of_a_clean_run_only_counts_ok() {
+        let (_root, context) = context_for(3);
+        assert_eq!(context.summary(), "devmaker: 0 ok, 3 skipped");
+        for job in 0..3 {
+            context.mark_completed(&format!("job{job}"));
+        }
+        assert_eq!(context.summary(), "devmaker: 3 ok");
+    }
+
+    #[test]
+    fn summary_counts_jobs_the_last_run_completed_apart() {
+        let (_root, context) = context_for(4);
+        context.mark_already_done("job0");
+        context.mark_already_done("job1");
+        context.mark_completed("job2");
+        assert_eq!(
+            context.summary(),
+            "devmaker: 1 ok, 2 already done, 1 skipped"
+        );
+        assert_eq!(context.status("job0"), "ok");
+    }
+}
@@ -0,0 +1,83 @@
+use anyhow::{anyhow, Result};
+
+use crate::info::KeyringEntry;
+
+/// Where secrets are kept between runs, tried for a var before prompting.
+pub(crate) trait Keyring: Send + Sync {
+    /// Read a secret, or `None` if the keyring has no such entry.
+    fn get(&self, entry: &KeyringEntry) -> Result<Option<String>>;
+    /// Store a secret, replacing any value it had.
+    fn set(&self, entry: &KeyringEntry, value: &str) -> Result<()>;
+}
+
+/// The system keyring: the Secret Service on Linux, the Keychain on macOS and the Credential
+/// Manager on Windows.
+pub(crate) struct SystemKeyring;
+
+#[cfg(feature = "keyring")]
+impl Keyring for SystemKeyring {
+    fn get(&self, entry: &KeyringEntry) -> Result<Option<String>> {
+        match system_entry(entry)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(::keyring::Error::NoEntry) => {
+                debug!(
+                    "No keyring entry for service {}, account {}",
+                    entry.service, entry.account
+                );
+                Ok(None)
+            }
+            Err(e) => Err(anyhow!(format!("Cannot read the keyring: {}", e))),
+        }
+    }
+
+    fn set(&self, entry: &KeyringEntry, value: &str) -> Result<()> {
+        system_entry(entry)?
+            .set_password(value)
+            .map_err(|e| anyhow!(format!("Cannot store the value in the keyring: {}", e)))
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn system_entry(entry: &KeyringEntry) -> Result<::keyring::Entry> {
+    ::keyring::Entry::new(&entry.service, &entry.account).map_err(|e| {
+        anyhow!(format!(
+            "Cannot use the keyring entry for service {}, account {}: {}",
+            entry.service, entry.account, e
+        ))
+    })
+}
+
+#[cfg(not(feature = "keyring"))]
+impl Keyring for SystemKeyring {
+    fn get(&self, _entry: &KeyringEntry) -> Result<Option<String>> {
+        check_keyring().map(|()| None)
+    }
+
+    fn set(&self, _entry: &KeyringEntry, _value: &str) -> Result<()> {
+        check_keyring()
+    }
+}
+
+/// Fail early, before asking for a value, if there's no keyring support.
+pub(crate) fn check_keyring() -> Result<()> {
+    if cfg!(feature = "keyring") {
+        Ok(())
+    } else {
+        Err(anyhow!("devmaker was built without the `keyring` feature"))
+    }
+}
+
+#[cfg(all(test, feature = "keyring"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_entries_read_as_none() {
+        ::keyring::set_default_credential_builder(::keyring::mock::default_credential_builder());
+        let entry = KeyringEntry {
+            service: "devmaker-test".to_string(),
+            account: "missing".to_string(),
+        };
+        assert_eq!(SystemKeyring.get(&entry).unwrap(), None);
+    }
+}
@@ -3,6 +3,8 @@
 #![warn(clippy::cargo)]
 #![deny(clippy::all)]
 #![allow(clippy::multiple_crate_versions)]
+// The modules are private, but the crate marks its items pub(crate) all the same.
+#![allow(clippy::redundant_pub_crate)]
 
 #[macro_use]
 extern crate log;
@@ -10,68 +12,164 @@ extern crate log;
 mod cli;
 mod common;
 mod config;
+mod doctor;
 mod info;
 mod jobs;
+mod keyring;
+mod metrics;
+mod report;
+mod settings;
+mod show;
+mod signals;
+mod state;
+mod tree;
 mod vars;
 
 use std::borrow::ToOwned;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::convert::TryInto;
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Error, Result};
+use console::Term;
+use dialoguer::{Checkboxes, Confirmation};
+use indicatif::{ProgressBar, ProgressStyle};
 use structopt::StructOpt;
 
-use cli::Opt;
-use common::{EnvMap, DEPS_SCRIPT, INFO_FILE};
+use cli::{Command, FailurePolicy, Opt};
+use common::{
+    secure_name_check, unknown_job, validate_job_name, EnvMap, Summarized, DEPS_SCRIPT,
+    DEVMAKER_PATH, INFO_FILE, JOBS_FILES, JOBS_MANIFEST, RESERVED_ENV, RESERVED_ENV_PREFIX,
+    SETTINGS_FILE, UNDO_SCRIPT,
+};
 use config::Config;
-use info::InfoSpec;
-use jobs::{JobSpec, ReadyJob};
-use vars::{fill_asked, query};
+use doctor::run_doctor;
+use info::{info_schema, AskEntry, AskInclude, InfoSpec};
+use jobs::{find_job_script, locate_runner, JobSpec, ReadyJob, RunContext};
+use metrics::write_metrics;
+use report::write_report;
+use settings::{load_root_settings, load_secure_patterns};
+use show::run_show;
+use signals::install_forwarding;
+use state::{reset_state, RunLock, RunState};
+use tree::{dependency_levels, explain_schedule, render_tree};
+use vars::{
+    check_ask_keys, check_vars, encode_key, fill_asked, query, store_in_keyring, write_env_file,
+};
 
-fn cycle_error(scheduled: &HashSet<&String>, all: &[ReadyJob]) -> Error {
+fn cycle_error(scheduled: &HashSet<&String>, all: &[JobSpec]) -> Error {
     let v: Vec<String> = all
         .iter()
         .filter_map(|j| {
-            if scheduled.contains(j.name()) {
+            if scheduled.contains(&j.name) {
                 None
             } else {
-                Some((j.name()).clone())
+                Some(j.name.clone())
             }
         })
         .collect();
     anyhow!(format!("Unschedulable jobs: {}", v.join(", ")))
 }
 
-fn get_job_names<P: AsRef<Path>>(root: P) -> Result<Vec<String>> {
-    let pattern = root.as_ref().join("*/run.*").display().to_string();
+/// A job's name is its directory's path relative to the root, `/`-separated when nested.
+fn job_name_from_dir(root: &Path, dir: &Path) -> Result<String> {
+    let relative = dir.strip_prefix(root).map_err(|_| {
+        anyhow!(format!(
+            "Job directory {} is outside the root",
+            dir.display()
+        ))
+    })?;
+    let parts: Vec<&str> = relative
+        .iter()
+        .map(|part| {
+            part.to_str()
+                .ok_or_else(|| anyhow!(format!("Invalid job name: {}", relative.to_string_lossy())))
+        })
+        .collect::<Result<_>>()?;
+    if parts.is_empty() {
+        return Err(anyhow!(format!(
+            "Unusable directory name {}",
+            dir.display()
+        )));
+    }
+    Ok(parts.join("/"))
+}
+
+/// Read the explicit list of jobs in a `devmaker.jobs` manifest, one job directory per line.
+///
+/// Blank lines and `#` comments are ignored.  Every listed job must exist and have a runner.
+fn read_jobs_manifest(root: &Path, manifest: &Path) -> Result<Vec<String>> {
+    debug!("Reading jobs manifest: {}", manifest.display());
+    let mut names: Vec<String> = Vec::new();
+    for line in fs::read_to_string(manifest)?.lines() {
+        let name = line.trim().trim_end_matches('/');
+        if name.is_empty() || name.starts_with('#') {
+            continue;
+        }
+        validate_job_name(name)?;
+        if names.iter().any(|listed| listed == name) {
+            return Err(anyhow!(format!(
+                "Job '{}' is listed twice in {}",
+                name, JOBS_MANIFEST
+            )));
+        }
+        let job_dir = root.join(name);
+        if !job_dir.is_dir() {
+            return Err(anyhow!(format!(
+                "Job '{}' is listed in {}, but doesn't exist",
+                name, JOBS_MANIFEST
+            )));
+        }
+        let spec = JobSpec::new(name.to_owned(), parse_resolved_info(&job_dir)?, false);
+        locate_runner(
+            &job_dir,
+            name,
+            spec.inline_run.as_ref(),
+            spec.script.as_deref(),
+            spec.runner.as_deref(),
+            spec.exec.as_deref(),
+        )?;
+        names.push(name.to_owned());
+    }
+    Ok(names)
+}
+
+fn get_job_names<P: AsRef<Path>>(root: P, config: &Config) -> Result<Vec<String>> {
+    let root = root.as_ref();
+    if let Some(jobs_file) = find_jobs_file(root)? {
+        let jobs = parse_jobs_file(&jobs_file)?;
+        for name in jobs.keys() {
+            validate_job_name(name)?;
+        }
+        return Ok(jobs.into_keys().collect());
+    }
+    let manifest = root.join(JOBS_MANIFEST);
+    if manifest.is_file() {
+        return read_jobs_manifest(root, &manifest);
+    }
     let mut match_collector = Vec::<String>::new();
     let mut hit_error = false;
-    for runfile in glob::glob(&pattern)? {
-        if let Ok(path) = runfile {
-            let name = path
-                .parent()
-                .ok_or(anyhow!(format!("Unexpectable path {}", path.display())))?
-                .file_name()
-                .ok_or(anyhow!(format!(
-                    "Unusable directory name {}",
-                    path.display()
-                )))?;
-            if let Some(valid_name) = name.to_str() {
-                match_collector.push(valid_name.to_string());
-            } else {
+    for dir in candidate_dirs(root, config)? {
+        if !is_job_dir(&dir)? {
+            continue;
+        }
+        match job_name_from_dir(root, &dir) {
+            Ok(valid_name) => match_collector.push(valid_name),
+            Err(e) => {
                 hit_error = true;
-                eprintln!("Invalid job name: {}", name.to_string_lossy());
+                eprintln!("{e}");
             }
-        } else {
-            hit_error = true;
-            let glob_err = runfile.unwrap_err(); // We know it's an error.
-            eprintln!("GlobError: {}", glob_err);
         }
     }
+
     if hit_error {
         Err(anyhow!("Failed to retrieve job names"))
     } else {
@@ -79,134 +177,2655 @@ fn get_job_names<P: AsRef<Path>>(root: P) -> Result<Vec<String>> {
     }
 }
 
+/// Whether `dir` holds a job: it has a `run.*` file, or its info file gives an inline `run`, a
+/// script, a runner or an `exec` command instead.
+fn is_job_dir(dir: &Path) -> Result<bool> {
+    let has_runfile = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .any(|entry| entry.file_name().to_string_lossy().starts_with("run."));
+    if has_runfile {
+        return Ok(true);
+    }
+    let info = parse_resolved_info(dir)?;
+    Ok(info.run.is_some() || info.script.is_some() || info.runner.is_some() || info.exec.is_some())
+}
+
+/// Parse a single job directory on its own, without scanning a root, the same way discovery
+/// would.  The job is named after the directory, and its runner must be found.
+fn inspect_job_dir(dir: &Path) -> Result<JobSpec> {
+    let dir = fs::canonicalize(dir)
+        .map_err(|e| anyhow!(format!("Cannot read {}: {}", dir.display(), e)))?;
+    if !dir.is_dir() || !is_job_dir(&dir)? {
+        return Err(anyhow!(format!(
+            "{} is not a job directory: it has no run.* file, and no run, script, runner or exec in {}",
+            dir.display(),
+            INFO_FILE
+        )));
+    }
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| anyhow!(format!("{} has no name", dir.display())))?;
+    let mut spec = parse_job_dir(&name, &dir, None)?;
+    locate_runner(
+        &dir,
+        &name,
+        spec.inline_run.as_ref(),
+        spec.script.as_deref(),
+        spec.runner.as_deref(),
+        spec.exec.as_deref(),
+    )?;
+    spec.dir = dir;
+    Ok(spec)
+}
+
+/// Every directory below the root that could hold a job, sorted.
+///
+/// Only direct children are searched unless `--recursive`, which skips hidden directories like
+/// `.git` and stops at `--max-discovery-depth`.  Symlinked directories are only followed with
+/// `--follow-symlinks`, and even then each directory is visited once, so a loop can't recurse.
+fn candidate_dirs(root: &Path, config: &Config) -> Result<Vec<PathBuf>> {
+    let max_depth = if config.recursive {
+        config.max_discovery_depth.unwrap_or(usize::MAX)
+    } else {
+        1
+    };
+    let mut visited = HashSet::new();
+    visited.insert(root.to_path_buf());
+    let mut found = Vec::new();
+    let mut pending = vec![(root.to_path_buf(), 0)];
+    while let Some((dir, depth)) = pending.pop() {
+        if depth >= max_depth {
+            continue;
+        }
+        let mut entries = fs::read_dir(&dir)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(fs::DirEntry::file_name);
+        for entry in entries {
+            let path = entry.path();
+            if !path.is_dir()
+                || (config.recursive && entry.file_name().to_string_lossy().starts_with('.'))
+            {
+                continue;
+            }
+            if entry.file_type()?.is_symlink() && !config.follow_symlinks {
+                debug!("Not following symlinked directory: {}", path.display());
+                continue;
+            }
+            if !visited.insert(fs::canonicalize(&path)?) {
+                debug!("Skipping directory visited already: {}", path.display());
+                continue;
+            }
+            found.push(path.clone());
+            pending.push((path, depth + 1));
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// The primary root, followed by the extra roots listed in `extra_roots`, the value of
+/// `DEVMAKER_PATH`, like `PATH`.
+fn job_roots(root: &Path, extra_roots: &OsStr) -> Vec<PathBuf> {
+    let mut roots = vec![root.to_path_buf()];
+    for extra in env::split_paths(extra_roots) {
+        if extra.as_os_str().is_empty() {
+            continue;
+        }
+        match fs::canonicalize(&extra) {
+            Ok(extra) if extra.is_dir() => {
+                if !roots.contains(&extra) {
+                    roots.push(extra);
+                }
+            }
+            _ => warn!(
+                "Ignoring {} entry that isn't a directory: {}",
+                DEVMAKER_PATH,
+                extra.display()
+            ),
+        }
+    }
+    roots
+}
+
+/// Discover the jobs of `root` and the `DEVMAKER_PATH` roots, each paired with the root it was
+/// found in.
+fn discover_jobs(root: &Path, config: &Config) -> Result<Vec<(String, PathBuf)>> {
+    let extra_roots = env::var_os(DEVMAKER_PATH).unwrap_or_default();
+    discover_jobs_in(job_roots(root, &extra_roots), config)
+}
+
+/// Discover the jobs of every root.  When roots share a job name, the earliest root wins, so
+/// the primary root always does.
+fn discover_jobs_in(roots: Vec<PathBuf>, config: &Config) -> Result<Vec<(String, PathBuf)>> {
+    let mut jobs: Vec<(String, PathBuf)> = Vec::new();
+    for job_root in roots {
+        for name in get_job_names(&job_root, config)? {
+            match jobs.iter().find(|(known, _)| *known == name) {
+                Some((_, found_in)) => debug!(
+                    "Job '{}' in {} is shadowed by the one in {}",
+                    name,
+                    job_root.display(),
+                    found_in.display()
+                ),
+                None => jobs.push((name, job_root.clone())),
+            }
+        }
+    }
+    Ok(jobs)
+}
+
+/// Parse a discovered job.  A job from a `DEVMAKER_PATH` root keeps its directory as an
+/// absolute path, which joining onto the primary root leaves as it is.
+fn parse_discovered_job(
+    name: &str,
+    job_root: &Path,
+    root: &Path,
+    profile: Option<&str>,
+    jobs_files: &mut JobsFiles,
+) -> Result<JobSpec> {
+    let mut spec = match jobs_files.get(job_root)? {
+        Some(jobs) => parse_combined_job(name, job_root, jobs, profile)?,
+        None => parse_job_dir(name, &job_root.join(name), profile)?,
+    };
+    if job_root != root {
+        spec.dir = job_root.join(&spec.dir);
+    }
+    Ok(spec)
+}
+
 fn parse_info_file<P: AsRef<Path>>(root: P) -> Result<InfoSpec> {
     let info_path = root.as_ref().join(INFO_FILE);
     if !info_path.exists() {
         // no file, fall back to default settings
         return Ok(InfoSpec::default());
-    };
+    }
     debug!("Parsing info file: {}", info_path.display());
     let file = File::open(info_path)?;
     let reader = BufReader::new(file);
     Ok(serde_json::from_reader(reader)?)
 }
 
-fn parse_job_files<P: AsRef<Path>>(name: &str, root: P) -> Result<JobSpec> {
-    debug!("Parsing job files: {}", name);
-    let script_dir = root.as_ref().join(name);
-    let has_deps_script = script_dir.join(DEPS_SCRIPT).is_file();
-    let info_spec = parse_info_file(&script_dir)?;
-    Ok(JobSpec::new(
-        name.to_owned(),
-        info_spec.env.unwrap_or_default(),
-        info_spec.depends.unwrap_or_default(),
-        info_spec.ask.unwrap_or_default(),
-        has_deps_script,
-    ))
+/// The combined jobs file at the root, if there is one.
+fn find_jobs_file(root: &Path) -> Result<Option<PathBuf>> {
+    let mut present = JOBS_FILES
+        .iter()
+        .map(|file| root.join(file))
+        .filter(|path| path.is_file());
+    match (present.next(), present.next()) {
+        (Some(_), Some(_)) => Err(anyhow!(format!(
+            "Only one of {} may be present in {}",
+            JOBS_FILES.join(" and "),
+            root.display()
+        ))),
+        (found, _) => Ok(found),
+    }
 }
 
-fn report_jobs(jobs: &[ReadyJob]) {
-    for (position, job) in jobs.iter().enumerate() {
-        println!("{}", job.report(position));
-    }
+/// Parse a combined jobs file, which maps each job name to what would be its info file.
+fn parse_jobs_file(path: &Path) -> Result<BTreeMap<String, InfoSpec>> {
+    debug!("Parsing jobs file: {}", path.display());
+    let contents = fs::read_to_string(path)?;
+    let jobs = if path.extension().is_some_and(|ext| ext == "toml") {
+        toml::from_str(&contents)?
+    } else {
+        serde_json::from_str(&contents)?
+    };
+    Ok(jobs)
 }
 
-fn run_all_jobs<P: AsRef<Path>>(root: P, config: &Config) -> Result<()> {
-    info!(
-        "Retrieving job names from root: {}",
-        root.as_ref().display()
-    );
-    let names: Vec<String> = get_job_names(root.as_ref())?;
+/// The combined jobs files parsed so far, by root, so each is read once however many jobs it
+/// defines.  A root without one maps to `None`.
+#[derive(Default)]
+pub(crate) struct JobsFiles(HashMap<PathBuf, Option<BTreeMap<String, InfoSpec>>>);
 
-    info!("Parsing job files");
-    let specs: Vec<JobSpec> = names
-        .into_iter()
-        .map(|name| parse_job_files(&name, root.as_ref()))
-        .collect::<Result<Vec<JobSpec>, Error>>()?;
+impl JobsFiles {
+    /// The jobs of `root`'s combined jobs file, or `None` if its jobs have their own directories.
+    fn get(&mut self, root: &Path) -> Result<Option<&BTreeMap<String, InfoSpec>>> {
+        if !self.0.contains_key(root) {
+            let jobs = find_jobs_file(root)?
+                .map(|path| parse_jobs_file(&path))
+                .transpose()?;
+            self.0.insert(root.to_owned(), jobs);
+        }
+        Ok(self.0[root].as_ref())
+    }
+}
 
-    info!("Querying ask variables");
-    let asked_vars: EnvMap = query(&specs, config)?;
+/// Build a job from its own directory: its info file, with the profile's override applied,
+/// and optional scripts.
+fn parse_job_dir(name: &str, dir: &Path, profile: Option<&str>) -> Result<JobSpec> {
+    debug!("Parsing job files: {name}");
+    let has_deps_script = find_job_script(dir, name, DEPS_SCRIPT)?;
+    let info_spec = parse_resolved_info(dir)?.apply_profile(profile)?;
+    let mut spec = JobSpec::new(name.to_owned(), info_spec, has_deps_script);
+    spec.has_undo_script = find_job_script(dir, name, UNDO_SCRIPT)?;
+    Ok(spec)
+}
 
-    info!("Populating asked variables");
-    let respecs: Vec<ReadyJob> = specs
-        .into_iter()
-        .map(|spec| fill_asked(spec, &asked_vars))
-        .collect::<Result<Vec<ReadyJob>, Error>>()?;
+/// Build a job from its entry in the root's parsed combined jobs file, whose other `jobs` it
+/// can extend.  Paths in it are relative to the root.
+fn parse_combined_job(
+    name: &str,
+    root: &Path,
+    jobs: &BTreeMap<String, InfoSpec>,
+    profile: Option<&str>,
+) -> Result<JobSpec> {
+    let info_spec = jobs
+        .get(name)
+        .cloned()
+        .ok_or_else(|| anyhow!(format!("Cannot locate job: {}", name)))?;
+    let info_spec = resolve_info(info_spec, root, Some(jobs), &mut vec![name.to_owned()])?
+        .apply_profile(profile)?;
+    let mut spec = JobSpec::new(name.to_owned(), info_spec, false);
+    spec.dir = PathBuf::new();
+    Ok(spec)
+}
 
-    info!("Scheduling jobs");
-    let queue: Vec<ReadyJob> = schedule_specs(&respecs)?;
+/// Parse the info file in `dir`, with its `ask_include` and `extends` resolved.
+fn parse_resolved_info(dir: &Path) -> Result<InfoSpec> {
+    let info_path = dir.join(INFO_FILE);
+    let start = fs::canonicalize(&info_path).unwrap_or(info_path);
+    resolve_info(
+        parse_info_file(dir)?,
+        dir,
+        None,
+        &mut vec![start.display().to_string()],
+    )
+}
 
-    if config.dry_run {
-        report_jobs(&queue);
-        return Ok(());
+/// Resolve a spec's `ask_include`, then its `extends` chain, relative to `base_dir`.
+///
+/// `siblings` are the jobs of a combined jobs file, which `extends` can name.  `chain` holds
+/// the specs extended so far, to catch circular `extends`.
+fn resolve_info(
+    mut info: InfoSpec,
+    base_dir: &Path,
+    siblings: Option<&BTreeMap<String, InfoSpec>>,
+    chain: &mut Vec<String>,
+) -> Result<InfoSpec> {
+    if let Some(include) = info.ask_include.take() {
+        let included = load_ask_include(base_dir, &include, &mut Vec::new())?;
+        info.ask.get_or_insert_with(Vec::new).extend(included);
+    }
+    let Some(extends) = info.extends.take() else {
+        return Ok(info);
     };
-    if let Some(jobname) = &config.single_job {
-        queue
-            .iter()
-            .find(|job| job.name() == jobname)
-            .ok_or(anyhow!(format!("Cannot locate job: {}", jobname)))?
-            .run(&root)
+    let (base, key, dir) = if let Some(sibling) = siblings.and_then(|jobs| jobs.get(&extends)) {
+        (sibling.clone(), extends, base_dir.to_owned())
     } else {
-        queue.iter().try_for_each(|job| job.run(&root))
+        let mut path = fs::canonicalize(base_dir.join(&extends))
+            .map_err(|e| anyhow!(format!("Cannot read extends {}: {}", extends, e)))?;
+        if path.is_dir() {
+            path = path.join(INFO_FILE);
+        }
+        debug!("Parsing extended spec: {}", path.display());
+        let reader = BufReader::new(
+            File::open(&path)
+                .map_err(|e| anyhow!(format!("Cannot read extends {}: {}", extends, e)))?,
+        );
+        let base: InfoSpec = serde_json::from_reader(reader)?;
+        let dir = path.parent().unwrap_or(base_dir).to_owned();
+        (base, path.display().to_string(), dir)
+    };
+    let circular = chain.contains(&key);
+    chain.push(key);
+    if circular {
+        return Err(anyhow!(format!("Circular extends: {}", chain.join(" -> "))));
     }
+    let base = resolve_info(base, &dir, siblings, chain)?;
+    info.extend_from(base);
+    Ok(info)
 }
 
-fn schedule_specs(jobs: &[ReadyJob]) -> Result<Vec<ReadyJob>> {
-    let required_count = jobs.len();
-    let mut scheduled = Vec::with_capacity(required_count);
+/// Load the ask entries from an `ask_include` file, following any nested includes.
+fn load_ask_include(
+    base_dir: &Path,
+    include: &str,
+    visited: &mut Vec<PathBuf>,
+) -> Result<Vec<AskEntry>> {
+    let path = fs::canonicalize(base_dir.join(include))
+        .map_err(|e| anyhow!(format!("Cannot read ask_include {}: {}", include, e)))?;
+    if visited.contains(&path) {
+        visited.push(path);
+        let chain: Vec<String> = visited.iter().map(|p| p.display().to_string()).collect();
+        return Err(anyhow!(format!(
+            "Circular ask_include: {}",
+            chain.join(" -> ")
+        )));
+    }
+    debug!("Parsing ask include: {}", path.display());
+    let reader = BufReader::new(File::open(&path)?);
+    let entries = match serde_json::from_reader(reader)? {
+        AskInclude::List(entries) => entries,
+        AskInclude::Nested { ask, ask_include } => {
+            let mut entries = ask.unwrap_or_default();
+            if let Some(nested) = ask_include {
+                visited.push(path.clone());
+                let parent = path.parent().unwrap_or(base_dir);
+                entries.extend(load_ask_include(parent, &nested, visited)?);
+                visited.pop();
+            }
+            entries
+        }
+    };
+    Ok(entries)
+}
 
-    let mut scheduled_names: HashSet<&String> = HashSet::with_capacity(required_count);
+#[inline]
+fn is_glob(dep: &str) -> bool {
+    dep.contains(&['*', '?', '['][..])
+}
 
-    macro_rules! schedule {
-        ($x:expr) => {
-            debug!("Schedule: {}", $x.name());
-            scheduled_names.insert(&$x.name());
-            scheduled.push($x.clone());
-        };
+/// The tag a `tag:<label>` dependency refers to.
+#[inline]
+fn dep_tag(dep: &str) -> Option<&str> {
+    dep.strip_prefix("tag:")
+}
+
+/// A job listing itself in `depends` would otherwise only show up as an unschedulable job.
+///
+/// A conditional dependency also has to name a single job, so it can be dropped on its own.
+fn check_self_depends(spec: &JobSpec) -> Result<()> {
+    if spec.depends.contains(&spec.name) {
+        return Err(anyhow!(format!("Job '{}' depends on itself", spec.name)));
+    }
+    if let Some(dep) = spec
+        .conditional_depends
+        .iter()
+        .find(|dep| is_glob(&dep.job) || dep_tag(&dep.job).is_some())
+    {
+        return Err(anyhow!(format!(
+            "Job '{}' has a conditional dependency on '{}', which must name a single job",
+            spec.name, dep.job
+        )));
     }
+    Ok(())
+}
 
-    for job in jobs {
-        if job.depends().is_empty() {
-            schedule!(job);
+/// Drop the conditional dependencies whose variable doesn't have the required value, now that
+/// variables are resolved.  Jobs left unneeded by a `skip_unneeded` dependency are dropped too,
+/// then the rest is rescheduled, as fewer dependencies may allow a different order.
+fn apply_conditional_depends(
+    root: &Path,
+    mut specs: Vec<JobSpec>,
+    answers: &EnvMap,
+    config: &Config,
+) -> Result<Vec<JobSpec>> {
+    if specs.iter().all(|spec| spec.conditional_depends.is_empty()) {
+        return Ok(specs);
+    }
+    let mut unneeded = Vec::new();
+    for spec in &mut specs {
+        for dep in &spec.conditional_depends {
+            let name = secure_name_check(dep.when_var.as_str(), &config.secure_suffix).0;
+            if answers.get(&name) == Some(&dep.equals) {
+                continue;
+            }
+            info!(
+                "Dropping dependency of {} on {}: {} is not {:?}",
+                spec.name, dep.job, name, dep.equals
+            );
+            spec.depends.retain(|other| other != &dep.job);
+            if dep.skip_unneeded {
+                unneeded.push(dep.job.clone());
+            }
         }
     }
+    // Dropping one job may leave another unneeded, so go until nothing changes.
+    while let Some(index) = specs.iter().position(|spec| {
+        unneeded.contains(&spec.name)
+            && !specs.iter().any(|other| other.depends.contains(&spec.name))
+    }) {
+        info!("Skipping job {}: no longer needed", specs[index].name);
+        specs.remove(index);
+    }
+    schedule_specs(&specs, &load_root_settings(root)?.order)
+}
 
-    while scheduled.len() < required_count {
-        let sched_count = scheduled.len();
-
-        for job in jobs {
-            if scheduled_names.contains(&job.name()) {
+/// Expand glob patterns and tags in each job's `depends` into the matching job names.
+fn expand_depends(specs: &mut [JobSpec], config: &Config) -> Result<()> {
+    let mut jobs: Vec<(String, Vec<String>)> = specs
+        .iter()
+        .map(|spec| (spec.name.clone(), spec.tags.clone()))
+        .collect();
+    jobs.sort();
+    for spec in specs.iter_mut() {
+        let mut expanded = Vec::with_capacity(spec.depends.len());
+        for dep in std::mem::take(&mut spec.depends) {
+            let others = jobs.iter().filter(|(name, _)| *name != spec.name);
+            let matches: Vec<&String> = if let Some(tag) = dep_tag(&dep) {
+                others
+                    .filter(|(_, tags)| tags.iter().any(|other| other == tag))
+                    .map(|(name, _)| name)
+                    .collect()
+            } else if is_glob(&dep) {
+                let pattern = glob::Pattern::new(&dep)?;
+                others
+                    .filter(|(name, _)| pattern.matches(name))
+                    .map(|(name, _)| name)
+                    .collect()
+            } else {
+                if !expanded.contains(&dep) {
+                    expanded.push(dep);
+                }
                 continue;
+            };
+            if matches.is_empty() {
+                let message = format!(
+                    "Job '{}' depends on '{}', which matches no jobs",
+                    spec.name, dep
+                );
+                if config.allow_empty_depends {
+                    warn!("{message}");
+                } else {
+                    return Err(anyhow!(message));
+                }
+            }
+            for name in matches {
+                if !expanded.contains(name) {
+                    expanded.push(name.clone());
+                }
+            }
+        }
+        spec.depends = expanded;
+    }
+    Ok(())
+}
+
+/// Jobs named in `env_from` must run first, so make sure they're dependencies.
+fn add_env_from_depends(specs: &mut [JobSpec]) -> Result<()> {
+    let names: HashSet<String> = specs.iter().map(|spec| spec.name.clone()).collect();
+    for spec in specs.iter_mut() {
+        for base in &spec.env_from {
+            if !names.contains(base) {
+                return Err(anyhow!(format!(
+                    "Job '{}' takes env from unknown job '{}'",
+                    spec.name, base
+                )));
+            }
+            if !spec.depends.contains(base) {
+                info!(
+                    "Job '{}' takes env from '{}', adding it as a dependency",
+                    spec.name, base
+                );
+                spec.depends.push(base.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Merge `env_from` envs, in schedule order so inherited env is itself already merged.
+fn merge_env_from(queue: &mut [ReadyJob]) {
+    for i in 0..queue.len() {
+        let (done, rest) = queue.split_at_mut(i);
+        let job = &mut rest[0];
+        let bases: Vec<ReadyJob> = job
+            .env_from()
+            .iter()
+            .filter_map(|base| done.iter().find(|d| d.name() == base))
+            .cloned()
+            .collect();
+        job.inherit_env(&bases);
+    }
+}
+
+/// Warn about env keys and ask vars named like the env devmaker sets itself, which would be
+/// overridden.  With `--strict-env`, they're an error.
+fn check_reserved_env(specs: &[JobSpec], config: &Config) -> Result<()> {
+    let collisions = reserved_env_collisions(specs, &config.secure_suffix);
+    for collision in &collisions {
+        warn!("{collision}");
+    }
+    if !collisions.is_empty() && config.strict_env {
+        Err(anyhow!(format!(
+            "Found {} env key(s) using names devmaker reserves",
+            collisions.len()
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Describe each env key and ask var named like the env devmaker sets itself.  Keys are compared
+/// as they reach the job, so `script-dir` is caught too.
+fn reserved_env_collisions(specs: &[JobSpec], secure_suffix: &str) -> Vec<String> {
+    let mut collisions = Vec::new();
+    for spec in specs {
+        let asked = spec
+            .ask_for_vars
+            .iter()
+            .map(|var| secure_name_check(var.name.as_str(), secure_suffix).0);
+        let mut keys: Vec<String> = spec
+            .provided_env
+            .keys()
+            .chain(spec.env_from_vars.keys())
+            .chain(&spec.capture_output_var)
+            .cloned()
+            .chain(asked)
+            .map(|key| encode_key(&key))
+            .filter(|key| {
+                RESERVED_ENV.contains(&key.as_str()) || key.starts_with(RESERVED_ENV_PREFIX)
+            })
+            .collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            collisions.push(format!(
+                "Job '{}' sets {}, which devmaker reserves and will override",
+                spec.name, key
+            ));
+        }
+    }
+    collisions
+}
+
+/// Warn about env keys set to different values by jobs where one depends on the other.
+///
+/// With `--strict-env`, any such conflict is an error.
+fn check_env_conflicts(queue: &[ReadyJob], config: &Config) -> Result<()> {
+    let conflicts = env_conflicts(queue);
+    for conflict in &conflicts {
+        warn!("{conflict}");
+    }
+    if !conflicts.is_empty() && config.strict_env {
+        Err(anyhow!(format!(
+            "Found {} conflicting env value(s) between dependent jobs",
+            conflicts.len()
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Describe each env key set to different values by jobs where one depends on the other.
+fn env_conflicts(queue: &[ReadyJob]) -> Vec<String> {
+    // The queue is in schedule order, so each job's dependencies are already known.
+    let mut ancestors: HashMap<&String, BTreeSet<&String>> = HashMap::new();
+    let mut conflicts = Vec::new();
+    for job in queue {
+        let mut own = BTreeSet::new();
+        for dep in job.depends() {
+            own.insert(dep);
+            if let Some(inherited) = ancestors.get(dep) {
+                own.extend(inherited.iter().copied());
             }
+        }
+        for ancestor in own
+            .iter()
+            .filter_map(|name| queue.iter().find(|other| other.name() == *name))
+        {
+            let mut keys: Vec<&String> = job
+                .provided_keys()
+                .intersection(ancestor.provided_keys())
+                .filter(|key| job.env().get(*key) != ancestor.env().get(*key))
+                .collect();
+            keys.sort();
+            for key in keys {
+                conflicts.push(format!(
+                    "Jobs '{}' and '{}' set {} to different values",
+                    ancestor.name(),
+                    job.name(),
+                    key
+                ));
+            }
+        }
+        ancestors.insert(job.name(), own);
+    }
+    conflicts
+}
+
+/// Split off disabled jobs, erroring if any remaining job still depends on one.
+fn split_disabled(specs: Vec<JobSpec>) -> Result<(Vec<JobSpec>, Vec<JobSpec>)> {
+    let (disabled, enabled): (Vec<JobSpec>, Vec<JobSpec>) =
+        specs.into_iter().partition(|spec| spec.disabled);
+    for spec in &enabled {
+        if let Some(dep) = spec
+            .depends
+            .iter()
+            .find(|dep| disabled.iter().any(|d| &&d.name == dep))
+        {
+            return Err(anyhow!(format!(
+                "Job '{}' depends on disabled job '{}'",
+                spec.name, dep
+            )));
+        }
+    }
+    Ok((enabled, disabled))
+}
+
+fn report_jobs<P: AsRef<Path>>(
+    root: P,
+    jobs: &[ReadyJob],
+    disabled: &[JobSpec],
+    config: &Config,
+) -> Result<()> {
+    let context = RunContext::new(jobs.iter().map(|job| job.name().clone()).collect(), config);
+    for (position, job) in jobs.iter().enumerate() {
+        let condition = if config.eval_conditions {
+            Some(job.condition_met(&root, &context)?)
+        } else {
+            None
+        };
+        println!("{}", job.report(&root, position, condition, &context));
+    }
+    for spec in disabled {
+        println!("Disabled job: {}", spec.name);
+    }
+    Ok(())
+}
+
+fn run_deps_if_met(job: &ReadyJob, root: &Path, context: &RunContext) -> Result<()> {
+    // A simulated failure is the job's, wherever in it the real one would have been.
+    if context.simulate_failures.is_some() {
+        return Ok(());
+    }
+    let result = job.condition_met(root, context).and_then(|met| {
+        if met {
+            job.run_deps(root, context)
+        } else {
+            Ok(())
+        }
+    });
+    context.track_job(job, result)
+}
 
+/// Run every job's `deps.sh` ahead of the main runners, returning the failures `--on-failure`
+/// carried on past.  The runners of the jobs that failed, and of their dependents, are skipped.
+///
+/// Sequentially, these run in schedule order, and the dependents of a failed job don't run
+/// theirs either.  In parallel, all of them start at once, and every failure is reported before
+/// giving up.
+fn run_deps_phase(
+    queue: &[ReadyJob],
+    root: &Path,
+    context: &RunContext,
+    config: &Config,
+) -> Result<Vec<String>> {
+    info!("Running deps scripts");
+    let mut failures = Vec::new();
+    if !config.parallel_deps {
+        let mut not_run: HashSet<&String> = HashSet::new();
+        for job in queue {
             if job
                 .depends()
                 .iter()
-                .all(|name| scheduled_names.contains(&name))
+                .any(|dep| not_run.contains(dep) || context.failed(dep))
             {
-                schedule!(job);
+                not_run.insert(job.name());
+                continue;
+            }
+            if let Err(e) = run_deps_if_met(job, root, context) {
+                if !keep_going(config.on_failure, job.name(), &e) {
+                    return Err(e);
+                }
+                failures.push(e.to_string());
             }
         }
+        return Ok(failures);
+    }
+    let results: Vec<Result<()>> = thread::scope(|scope| {
+        // Spawn everything before joining anything, or the scripts would run one at a time.
+        #[allow(clippy::needless_collect)]
+        let handles: Vec<_> = queue
+            .iter()
+            .filter(|job| *job.has_deps_script())
+            .map(|job| scope.spawn(move || run_deps_if_met(job, root, context)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("deps.sh thread panicked")))
+            })
+            .collect()
+    });
+    let mut stopping = false;
+    for (job, result) in queue
+        .iter()
+        .filter(|job| *job.has_deps_script())
+        .zip(results)
+    {
+        if let Err(e) = result {
+            stopping = stopping || !keep_going(config.on_failure, job.name(), &e);
+            failures.push(e.to_string());
+        }
+    }
+    if stopping {
+        Err(anyhow!(failures.join("\n")))
+    } else {
+        Ok(failures)
+    }
+}
+
+/// Discover and parse every job, returning the enabled jobs scheduled in order,
+/// along with the disabled jobs.
+fn load_specs<P: AsRef<Path>>(root: P, config: &Config) -> Result<(Vec<JobSpec>, Vec<JobSpec>)> {
+    info!(
+        "Retrieving job names from root: {}",
+        root.as_ref().display()
+    );
+    let jobs = discover_jobs(root.as_ref(), config)?;
+
+    info!("Parsing job files");
+    let mut jobs_files = JobsFiles::default();
+    let mut specs: Vec<JobSpec> = jobs
+        .iter()
+        .map(|(name, job_root)| {
+            parse_discovered_job(
+                name,
+                job_root,
+                root.as_ref(),
+                config.profile.as_deref(),
+                &mut jobs_files,
+            )
+        })
+        .collect::<Result<Vec<JobSpec>, Error>>()?;
+    specs.iter().try_for_each(check_self_depends)?;
+    add_env_from_depends(&mut specs)?;
+    let (mut specs, disabled) = split_disabled(specs)?;
+    for spec in &disabled {
+        info!("Skipping disabled job: {}", spec.name);
+    }
+    check_reserved_env(&specs, config)?;
+    expand_depends(&mut specs, config)?;
+
+    let settings = load_root_settings(root.as_ref())?;
+    for var in specs.iter().flat_map(|spec| &spec.ask_for_vars) {
+        check_ask_keys(var, &settings.resolvers)?;
+    }
+    let known: Vec<&String> = specs.iter().chain(&disabled).map(|s| &s.name).collect();
+    for name in settings.unknown_ordered(&known) {
+        warn!("Job '{name}' in the order of {SETTINGS_FILE} does not exist");
+    }
+
+    info!("Scheduling jobs");
+    Ok((schedule_specs(&specs, &settings.order)?, disabled))
+}
+
+/// Validate that the jobs parse and schedule, without asking for or running anything.
+fn check_jobs<P: AsRef<Path>>(root: P, config: &Config) -> Result<()> {
+    let (scheduled, disabled) = load_specs(root, config)?;
+    println!(
+        "check: ok, {} jobs scheduled, {} disabled",
+        scheduled.len(),
+        disabled.len()
+    );
+    Ok(())
+}
+
+/// Print the jobs for `--list`.
+fn list_jobs<P: AsRef<Path>>(root: P, config: &Config) -> Result<()> {
+    let (scheduled, disabled) = load_specs(root, config)?;
+    for line in list_lines(&scheduled, &disabled) {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// Each job in schedule order, then the disabled ones, with their descriptions.
+fn list_lines(scheduled: &[JobSpec], disabled: &[JobSpec]) -> Vec<String> {
+    let width = scheduled
+        .iter()
+        .chain(disabled)
+        .map(|spec| spec.name.len())
+        .max()
+        .unwrap_or(0);
+    scheduled
+        .iter()
+        .map(|spec| (spec, ""))
+        .chain(disabled.iter().map(|spec| (spec, "(disabled) ")))
+        .map(|(spec, status)| {
+            let line = format!(
+                "{:width$}  {}{}",
+                spec.name,
+                status,
+                spec.description.as_deref().unwrap_or_default(),
+                width = width
+            );
+            line.trim_end().to_owned()
+        })
+        .collect()
+}
+
+fn print_tree<P: AsRef<Path>>(root: P, config: &Config) -> Result<()> {
+    let (scheduled, _) = load_specs(root, config)?;
+    println!("{}", render_tree(&scheduled));
+    Ok(())
+}
+
+fn print_schedule_explanation<P: AsRef<Path>>(root: P, config: &Config) -> Result<()> {
+    let (scheduled, _) = load_specs(&root, config)?;
+    let settings = load_root_settings(root.as_ref())?;
+    for line in explain_schedule(&scheduled, &settings.order) {
+        println!("{line}");
+    }
+    println!(
+        "Each job waits for the jobs it's after.  Among jobs ready at the same time, higher \
+         priority goes first, then the order in {SETTINGS_FILE}, then the name."
+    );
+    Ok(())
+}
 
-        // Compare the count at the beginning to the current.
-        // If the count doesn't change, we've hit an unresolvable cycle.
-        if scheduled.len() == sched_count {
-            return Err(cycle_error(&scheduled_names, jobs));
+fn print_levels<P: AsRef<Path>>(root: P, config: &Config) -> Result<()> {
+    let (scheduled, _) = load_specs(root, config)?;
+    println!("{}", serde_json::to_string(&dependency_levels(&scheduled))?);
+    Ok(())
+}
+
+fn run_single_job(job: &ReadyJob, root: &Path, context: &RunContext) -> Result<()> {
+    if job.condition_met(root, context)? {
+        job.run(root, context)
+    } else {
+        info!("Skipping job {}: condition not met", job.name());
+        Ok(())
+    }
+}
+
+/// Run a single job over and over, waiting for Enter between runs, until interrupted.
+///
+/// Failures are reported but don't stop the loop, since the point is to fix and retry.
+fn repeat_job(job: &ReadyJob, root: &Path, context: &RunContext) -> Result<()> {
+    loop {
+        if let Err(e) = run_single_job(job, root, context) {
+            eprintln!("error: {e}");
+        }
+        println!("Press Enter to run {} again, or Ctrl-C to stop", job.name());
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 {
+            // stdin closed, nobody is around to press Enter anymore.
+            return Ok(());
         }
     }
+}
 
+/// Ask which jobs to run, and keep only those and the jobs they depend on.
+///
+/// The menu can't check dependencies as they're selected, so it's shown again with them
+/// checked until the selection includes everything it depends on.
+fn select_jobs(mut scheduled: Vec<JobSpec>) -> Result<Vec<JobSpec>> {
+    let names: Vec<String> = scheduled.iter().map(|spec| spec.name.clone()).collect();
+    let mut checked = vec![false; names.len()];
+    let selected = loop {
+        let chosen = Checkboxes::new()
+            .with_prompt("Jobs to run (space to select, enter to confirm)")
+            .items(&names)
+            .defaults(&checked)
+            .interact()?;
+        let closure = dependency_closure(&scheduled, chosen.iter().map(|&index| &names[index]));
+        let added: Vec<&str> = names
+            .iter()
+            .enumerate()
+            .filter(|(index, name)| closure.contains(*name) && !chosen.contains(index))
+            .map(|(_, name)| name.as_str())
+            .collect();
+        if added.is_empty() {
+            break closure;
+        }
+        println!(
+            "Checked the jobs the selection depends on: {}",
+            added.join(", ")
+        );
+        checked = names.iter().map(|name| closure.contains(name)).collect();
+    };
+    if selected.is_empty() {
+        return Err(anyhow!("No jobs selected"));
+    }
+    scheduled.retain(|spec| selected.contains(&spec.name));
     Ok(scheduled)
 }
 
-fn inner_main() -> Result<()> {
-    let config: Config = Opt::from_args().try_into()?;
-    run_all_jobs(&config.root_dir, &config)
+/// The named jobs, along with everything they depend on, directly or not.
+fn dependency_closure<'a, I: Iterator<Item = &'a String>>(
+    specs: &[JobSpec],
+    names: I,
+) -> HashSet<String> {
+    let mut closure = HashSet::new();
+    let mut pending: Vec<String> = names.cloned().collect();
+    while let Some(name) = pending.pop() {
+        if let Some(spec) = specs.iter().find(|spec| spec.name == name) {
+            if closure.insert(name) {
+                pending.extend(spec.depends.iter().cloned());
+            }
+        }
+    }
+    closure
 }
 
-fn main() {
-    env_logger::init();
-    if let Err(e) = inner_main() {
-        eprintln!("error: {}", e);
-        process::exit(1);
+fn run_all_jobs<P: AsRef<Path>>(root: P, config: &Config) -> Result<()> {
+    // Taken before asking anything, so a second run fails before it wastes anyone's time.
+    let _lock = if config.dry_run || config.no_lock {
+        None
+    } else {
+        Some(RunLock::acquire(config.state_dir()?, root.as_ref())?)
+    };
+    let (mut scheduled, disabled) = load_specs(&root, config)?;
+    if config.interactive_select {
+        scheduled = select_jobs(scheduled)?;
+    }
+
+    // Checked before asking anything, since an invalid pattern would fail the run anyway.
+    let patterns = load_secure_patterns(root.as_ref())?;
+    info!("Querying ask variables");
+    let asked_vars: EnvMap = query(&scheduled, config)?;
+    let scheduled = apply_conditional_depends(root.as_ref(), scheduled, &asked_vars, config)?;
+    if let Some(path) = &config.env_file_out {
+        write_env_file(path, &scheduled, &asked_vars, config)?;
+    }
+
+    info!("Populating asked variables");
+    let mut queue: Vec<ReadyJob> = scheduled
+        .into_iter()
+        .map(|spec| fill_asked(spec, &asked_vars, &config.secure_suffix, &patterns))
+        .collect::<Result<Vec<ReadyJob>, Error>>()?;
+    merge_env_from(&mut queue);
+    check_env_conflicts(&queue, config)?;
+    let queue = slice_queue(queue, config)?;
+    for name in config.simulate_failures.iter().flatten() {
+        if !queue.iter().any(|job| job.name() == name) {
+            return Err(unknown_job(name, queue.iter().map(ReadyJob::name)));
+        }
+    }
+
+    if config.dry_run {
+        return report_jobs(&root, &queue, &disabled, config);
+    }
+    if let Some(jobname) = &config.single_job {
+        let job = queue
+            .iter()
+            .find(|job| job.name() == jobname)
+            .ok_or_else(|| unknown_job(jobname, queue.iter().map(ReadyJob::name)))?;
+        let context = RunContext::new(vec![job.name().clone()], config);
+        if config.repeat {
+            repeat_job(job, root.as_ref(), &context)
+        } else {
+            run_single_job(job, root.as_ref(), &context)
+        }
+    } else {
+        let context = RunContext::new(queue.iter().map(|job| job.name().clone()).collect(), config);
+        let undoable = if config.rollback {
+            queue.clone()
+        } else {
+            Vec::new()
+        };
+        let mut result = run_queue(queue, root.as_ref(), &context, config);
+        if config.rollback && result.is_err() {
+            rollback(&undoable, root.as_ref(), &context);
+        }
+        if let Some(path) = &config.metrics_file {
+            // Written even when the run failed, since that's what the metrics are for.
+            let written = write_metrics(path, &context.results());
+            result = result.and(written);
+        }
+        if let Some(path) = &config.report_file {
+            let written = write_report(path, &context);
+            result = result.and(written);
+        }
+        context.notify(config, result.is_ok());
+        if config.oneline_summary {
+            println!("{}", context.summary());
+            return result.map_err(|e| Error::new(Summarized(e)));
+        }
+        result
+    }
+}
+
+/// Run every job in the queue, skipping those the last run completed if continuing from it.
+fn run_queue(
+    queue: Vec<ReadyJob>,
+    root: &Path,
+    context: &RunContext,
+    config: &Config,
+) -> Result<()> {
+    // Only running deps.sh doesn't complete a job, so a later run mustn't skip it.  A pipeline
+    // leaves out whatever it likes, so it doesn't say where the full run got to.
+    let mut state =
+        if config.simulate_failures.is_some() || config.only_deps || config.pipeline.is_some() {
+            RunState::unrecorded(root)
+        } else {
+            RunState::start(config.state_dir()?, root, config.continue_from_last)?
+        };
+    let (done, queue): (Vec<ReadyJob>, Vec<ReadyJob>) = queue
+        .into_iter()
+        .partition(|job| state.completed(job.name()) && !job.always());
+    for job in done {
+        info!("Skipping job {}: completed by the last run", job.name());
+        context.mark_already_done(job.name());
+    }
+    let failures = if config.deps_first {
+        run_deps_phase(&queue, root, context, config)?
+    } else {
+        Vec::new()
+    };
+    if config.jobs > 1 {
+        run_concurrently(&queue, root, context, &mut state, config, failures)
+    } else {
+        run_in_order(&queue, root, context, &mut state, config, failures)
+    }
+}
+
+/// Run the queue one job at a time, stopping at the first failure.
+///
+/// `failures` are those of the deps phase the run carried on past, whose jobs don't run.
+fn run_in_order(
+    queue: &[ReadyJob],
+    root: &Path,
+    context: &RunContext,
+    state: &mut RunState,
+    config: &Config,
+    mut failures: Vec<String>,
+) -> Result<()> {
+    let progress = progress_bar(queue.len(), config);
+    let mut not_run: HashSet<&String> = HashSet::new();
+    let mut out_of_time = Vec::new();
+    for job in queue {
+        if context.failed(job.name()) {
+            progress.inc(1);
+            continue;
+        }
+        if job
+            .depends()
+            .iter()
+            .any(|dep| not_run.contains(dep) || context.failed(dep))
+        {
+            not_run.insert(job.name());
+            progress.inc(1);
+            continue;
+        }
+        if context.out_of_time() {
+            out_of_time.push(job);
+            progress.inc(1);
+            continue;
+        }
+        progress.set_message(job.name().clone());
+        // Jobs write straight to the terminal, so keep the bar out of their way.
+        let ran = progress.suspend(|| {
+            run_job_if_met(job, root, context, config.deps_first).or_else(|e| {
+                if keep_going(config.on_failure, job.name(), &e) {
+                    failures.push(e.to_string());
+                    Ok(false)
+                } else {
+                    Err(e)
+                }
+            })
+        })?;
+        if ran {
+            state.record(job.name())?;
+            context.mark_completed(job.name());
+            info!("{}", context.completion_line(job.name()));
+        }
+        progress.inc(1);
+    }
+    progress.finish_and_clear();
+    let not_run = queue.iter().filter(|job| not_run.contains(job.name()));
+    failure_report(failures, not_run, &out_of_time)
+}
+
+/// Run up to `--jobs` jobs at once, starting each as soon as its dependencies have completed.
+///
+/// Among the ready jobs, those scheduled earliest start first.  When a job fails and the run
+/// carries on, its dependents are skipped; otherwise no more jobs start, but those running
+/// finish.  Every failure is reported at the end, after the deps phase's `failures`.
+fn run_concurrently(
+    queue: &[ReadyJob],
+    root: &Path,
+    context: &RunContext,
+    state: &mut RunState,
+    config: &Config,
+    mut failures: Vec<String>,
+) -> Result<()> {
+    let position: HashMap<&String, usize> = queue
+        .iter()
+        .enumerate()
+        .map(|(index, job)| (job.name(), index))
+        .collect();
+    // Dependencies outside the queue have already run, so only count those inside it.
+    let mut waiting_on: Vec<usize> = queue
+        .iter()
+        .map(|job| {
+            job.depends()
+                .iter()
+                .filter(|dep| position.contains_key(dep))
+                .count()
+        })
+        .collect();
+    // Jobs whose deps.sh failed count as started, so they and their dependents never do.
+    let mut started: Vec<bool> = queue.iter().map(|job| context.failed(job.name())).collect();
+    let mut ready: BTreeSet<usize> = (0..queue.len())
+        .filter(|&index| waiting_on[index] == 0 && !started[index])
+        .collect();
+    let mut stopping = false;
+    let mut ran_out = false;
+
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| -> Result<()> {
+        let mut running = 0;
+        loop {
+            if !stopping && context.out_of_time() {
+                stopping = true;
+                ran_out = true;
+                ready.clear();
+            }
+            while running < config.jobs {
+                let Some(index) = ready.pop_first() else {
+                    break;
+                };
+                started[index] = true;
+                running += 1;
+                let job = &queue[index];
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    let result = run_job_if_met(job, root, context, config.deps_first);
+                    // The receiver outlives every worker, so this can't fail.
+                    let _ = sender.send((index, result));
+                });
+            }
+            if running == 0 {
+                return Ok(());
+            }
+            let (index, result) = receiver.recv()?;
+            running -= 1;
+            let job = &queue[index];
+            match result {
+                Ok(ran) => {
+                    if ran {
+                        state.record(job.name())?;
+                        context.mark_completed(job.name());
+                        info!("{}", context.completion_line(job.name()));
+                    }
+                    for (dependent, other) in queue.iter().enumerate() {
+                        if !stopping && other.depends().contains(job.name()) {
+                            waiting_on[dependent] -= 1;
+                            if waiting_on[dependent] == 0 {
+                                ready.insert(dependent);
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    if !stopping && !keep_going(config.on_failure, job.name(), &e) {
+                        stopping = true;
+                        ready.clear();
+                    }
+                    failures.push(e.to_string());
+                }
+            }
+        }
+    })?;
+
+    // Tell the jobs held up by a failure, directly or not, from those that only missed out
+    // because time ran out.  The queue is in schedule order, so dependencies come first.
+    let mut blocked: HashSet<&String> = HashSet::new();
+    let mut not_run = Vec::new();
+    let mut out_of_time = Vec::new();
+    for (job, _) in queue.iter().zip(&started).filter(|(_, &started)| !started) {
+        let held_up = job
+            .depends()
+            .iter()
+            .any(|dep| blocked.contains(dep) || context.failed(dep));
+        if ran_out && !held_up {
+            out_of_time.push(job);
+        } else {
+            blocked.insert(job.name());
+            not_run.push(job);
+        }
+    }
+    failure_report(failures, not_run.into_iter(), &out_of_time)
+}
+
+/// Run the `undo.sh` of each job that succeeded in this run, in reverse schedule order, to
+/// unwind a failed run.  An undo that fails is reported, and the rest still run.
+fn rollback(queue: &[ReadyJob], root: &Path, context: &RunContext) {
+    let completed: Vec<&ReadyJob> = queue
+        .iter()
+        .rev()
+        .filter(|job| context.ran_successfully(job.name()))
+        .collect();
+    if completed.is_empty() {
+        return;
+    }
+    eprintln!("Rolling back {} completed job(s)", completed.len());
+    for job in completed {
+        if !job.has_undo_script() {
+            debug!("Nothing to undo for job {}", job.name());
+            continue;
+        }
+        info!("Undoing job {}", job.name());
+        if let Err(e) = job.run_undo(root, context) {
+            eprintln!("error: Cannot undo job '{}': {}", job.name(), e);
+        }
+    }
+}
+
+/// Whether to carry on with the remaining jobs after `job` failed, per `--on-failure`.
+fn keep_going(policy: FailurePolicy, job: &str, error: &Error) -> bool {
+    match policy {
+        FailurePolicy::Abort => false,
+        FailurePolicy::Continue => true,
+        FailurePolicy::Ask => {
+            eprintln!("error: {error}");
+            Confirmation::new()
+                .with_text(&format!(
+                    "Job '{job}' failed.  Continue with the remaining jobs?"
+                ))
+                .default(false)
+                .interact()
+                .unwrap_or(false)
+        }
+    }
+}
+
+/// Combine the failures of a run that carried on past them, the jobs that didn't run as a
+/// result, and those that didn't start before `--max-runtime` ran out, into one error.
+fn failure_report<'a, I: Iterator<Item = &'a ReadyJob>>(
+    mut failures: Vec<String>,
+    not_run: I,
+    out_of_time: &[&ReadyJob],
+) -> Result<()> {
+    let not_run: Vec<&str> = not_run.map(|job| job.name().as_str()).collect();
+    if !not_run.is_empty() {
+        failures.push(format!(
+            "Not run because of an earlier failure: {}",
+            not_run.join(", ")
+        ));
+    }
+    if !out_of_time.is_empty() {
+        let names: Vec<&str> = out_of_time.iter().map(|job| job.name().as_str()).collect();
+        failures.push(format!(
+            "Not run because the run went over --max-runtime: {}",
+            names.join(", ")
+        ));
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(failures.join("\n")))
+    }
+}
+
+/// Run a job unless its `when` condition says otherwise, returning whether it ran.
+fn run_job_if_met(
+    job: &ReadyJob,
+    root: &Path,
+    context: &RunContext,
+    deps_first: bool,
+) -> Result<bool> {
+    if context.tolerated(job.name()) {
+        debug!("Skipping job {}: its deps.sh failed", job.name());
+        return Ok(false);
+    }
+    if let Some(failing) = &context.simulate_failures {
+        context.record_duration(job.name(), Duration::ZERO);
+        return context.track_job(job, simulate_job(job, failing));
+    }
+    let started = Instant::now();
+    let result = job.condition_met(root, context).and_then(|met| {
+        if !met {
+            info!("Skipping job {}: condition not met", job.name());
+            return Ok(false);
+        }
+        if deps_first {
+            job.run_main(root, context)?;
+        } else {
+            job.run(root, context)?;
+        }
+        Ok(true)
+    });
+    if !matches!(result, Ok(false)) {
+        context.record_duration(job.name(), started.elapsed());
+    }
+    context.track_job(job, result)
+}
+
+/// Pretend to run a job for `--simulate-failures`, failing it if it's one of `failing`.
+fn simulate_job(job: &ReadyJob, failing: &[String]) -> Result<bool> {
+    if failing.contains(job.name()) {
+        return Err(anyhow!(format!(
+            "Job '{}' failed with exit code 1 (simulated)",
+            job.name()
+        )));
+    }
+    info!("Simulated job {}", job.name());
+    Ok(true)
+}
+
+/// A bar tracking completed jobs, hidden when stdout isn't a terminal or with `--no-progress`.
+#[allow(clippy::literal_string_with_formatting_args)] // indicatif's template syntax
+fn progress_bar(total: usize, config: &Config) -> ProgressBar {
+    if config.no_progress || !Term::stdout().is_term() {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(total as u64);
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template("{pos}/{len} [{bar:30}] {msg}")
+            .unwrap_or_else(|_| ProgressStyle::default_bar())
+            .progress_chars("=> "),
+    );
+    bar
+}
+
+/// Cut the scheduled queue down to the `--start-from`..=`--stop-after` range.
+fn slice_queue(mut queue: Vec<ReadyJob>, config: &Config) -> Result<Vec<ReadyJob>> {
+    if let Some(pipeline) = &config.pipeline {
+        return pipeline_queue(queue, pipeline);
+    }
+    let position = |name: &String| {
+        queue
+            .iter()
+            .position(|job| job.name() == name)
+            .ok_or_else(|| unknown_job(name, queue.iter().map(ReadyJob::name)))
+    };
+    let start = config.start_from.as_ref().map(position).transpose()?;
+    let stop = config.stop_after.as_ref().map(position).transpose()?;
+    if let (Some(start), Some(stop)) = (start, stop) {
+        if stop < start {
+            return Err(anyhow!(format!(
+                "Job '{}' is scheduled before job '{}'",
+                queue[stop].name(),
+                queue[start].name()
+            )));
+        }
+    }
+    if let Some(stop) = stop {
+        queue.truncate(stop + 1);
+    }
+    if let Some(start) = start {
+        queue.drain(..start);
+    }
+    Ok(queue)
+}
+
+/// Take the `--pipeline` jobs out of the queue, in the order given.
+fn pipeline_queue(mut queue: Vec<ReadyJob>, pipeline: &[String]) -> Result<Vec<ReadyJob>> {
+    let mut picked = Vec::with_capacity(pipeline.len());
+    for name in pipeline {
+        if picked.iter().any(|job: &ReadyJob| job.name() == name) {
+            return Err(anyhow!(format!(
+                "Job '{}' is in the pipeline more than once",
+                name
+            )));
+        }
+        let index = queue
+            .iter()
+            .position(|job| job.name() == name)
+            .ok_or_else(|| unknown_job(name, queue.iter().map(ReadyJob::name)))?;
+        picked.push(queue.remove(index));
+    }
+    Ok(picked)
+}
+
+/// Schedule jobs so that every job runs after its dependencies.
+///
+/// Among the jobs that are ready at any point, higher priorities go first.  Ties are broken
+/// by position in `order`, then alphabetically so the order is deterministic.
+fn schedule_specs(jobs: &[JobSpec], order: &[String]) -> Result<Vec<JobSpec>> {
+    let rank = |job: &JobSpec| {
+        order
+            .iter()
+            .position(|name| name == &job.name)
+            .unwrap_or(usize::MAX)
+    };
+    let required_count = jobs.len();
+    let mut scheduled = Vec::with_capacity(required_count);
+
+    let mut scheduled_names: HashSet<&String> = HashSet::with_capacity(required_count);
+
+    while scheduled.len() < required_count {
+        let next = jobs
+            .iter()
+            .filter(|job| !scheduled_names.contains(&job.name))
+            .filter(|job| {
+                job.depends
+                    .iter()
+                    .all(|name| scheduled_names.contains(name))
+            })
+            .min_by(|a, b| {
+                b.priority
+                    .cmp(&a.priority)
+                    .then_with(|| rank(a).cmp(&rank(b)))
+                    .then_with(|| a.name.cmp(&b.name))
+            });
+
+        // If nothing is ready but jobs remain, we've hit an unresolvable cycle.
+        let job = next.ok_or_else(|| cycle_error(&scheduled_names, jobs))?;
+        debug!("Schedule: {}", job.name);
+        scheduled_names.insert(&job.name);
+        scheduled.push(job.clone());
+    }
+
+    Ok(scheduled)
+}
+
+fn inner_main() -> Result<()> {
+    let opt = Opt::from_args();
+    // The schema and a single job directory don't depend on any job root, so don't require one.
+    match &opt.command {
+        Some(Command::Schema) => {
+            println!("{}", info_schema()?);
+            return Ok(());
+        }
+        Some(Command::Inspect { job_dir }) => {
+            let spec = inspect_job_dir(job_dir)?;
+            println!("{}", serde_json::to_string_pretty(&spec)?);
+            return Ok(());
+        }
+        _ => {}
+    }
+    let config: Config = opt.try_into()?;
+    if config.trace_exec && !log_enabled!(log::Level::Info) {
+        eprintln!("warning: --trace-exec logs at info level, which is off; set RUST_LOG=info");
+    }
+    match config.command {
+        Some(Command::Doctor { .. }) => run_doctor(&config),
+        Some(Command::Show {
+            ref job, with_env, ..
+        }) => run_show(&config, job, with_env),
+        Some(Command::Schema | Command::Inspect { .. }) => {
+            unreachable!("handled before loading the config")
+        }
+        None if config.check => check_jobs(&config.root_dir, &config),
+        None if config.check_vars => check_vars(&config),
+        None if config.list => list_jobs(&config.root_dir, &config),
+        None if config.tree => print_tree(&config.root_dir, &config),
+        None if config.print_levels_json => print_levels(&config.root_dir, &config),
+        None if config.explain_schedule => print_schedule_explanation(&config.root_dir, &config),
+        None if config.reset_state => reset_state(config.state_dir()?, &config.root_dir),
+        None if config.keyring_set.is_some() => {
+            store_in_keyring(&config, config.keyring_set.as_deref().unwrap_or_default())
+        }
+        None => {
+            install_forwarding()?;
+            run_all_jobs(&config.root_dir, &config)
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+    if let Err(e) = inner_main() {
+        match e.downcast_ref::<Summarized>() {
+            Some(Summarized(e)) => debug!("Run failed: {e}"),
+            None => eprintln!("error: {e}"),
+        }
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::PermissionsExt;
+
+    use tempdir::TempDir;
+
+    use crate::common::{capture_logs, SECURE_SUFFIX};
+    use crate::settings::SecurePatterns;
+    use crate::signals::running_jobs;
+
+    /// A config for an empty root, which lives as long as the `TempDir`.
+    fn config(flags: &[&str]) -> (TempDir, Config) {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let config = Config::for_test(root.path(), flags);
+        (root, config)
+    }
+
+    /// `Config::for_test`, keeping run state and locks in `state` rather than the data dir.
+    fn config_in(state: &TempDir, root: &Path, flags: &[&str]) -> Config {
+        Config {
+            state_dir: Some(state.path().to_owned()),
+            ..Config::for_test(root, flags)
+        }
+    }
+
+    /// A root holding a job directory with each given `info.json`.
+    fn root_with(jobs: &[(&str, &str)]) -> TempDir {
+        let root = TempDir::new("devmaker-test").unwrap();
+        for (name, info) in jobs {
+            let dir = root.path().join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(INFO_FILE), info).unwrap();
+        }
+        root
+    }
+
+    fn spec(name: &str, info: &str) -> JobSpec {
+        JobSpec::new(name.to_owned(), serde_json::from_str(info).unwrap(), false)
+    }
+
+    #[test]
+    fn reserved_env_keys_are_caught_however_theyre_spelled() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let lenient = Config::for_test(root.path(), &[]);
+        let strict = Config::for_test(root.path(), &["--strict-env"]);
+        for key in &["SCRIPT_DIR", "script-dir"] {
+            let specs = [spec(
+                "build",
+                &format!(r#"{{"env": {{"{key}": "/elsewhere"}}}}"#),
+            )];
+            assert!(check_reserved_env(&specs, &lenient).is_ok());
+            assert_eq!(
+                check_reserved_env(&specs, &strict).unwrap_err().to_string(),
+                "Found 1 env key(s) using names devmaker reserves"
+            );
+        }
+    }
+
+    #[test]
+    fn unreserved_env_keys_pass_strict_env() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let strict = Config::for_test(root.path(), &["--strict-env"]);
+        let specs = [spec("build", r#"{"env": {"SCRIPT_DIRS": "/elsewhere"}}"#)];
+        assert!(check_reserved_env(&specs, &strict).is_ok());
+    }
+
+    fn names(specs: &[JobSpec]) -> Vec<&str> {
+        specs.iter().map(|spec| spec.name.as_str()).collect()
+    }
+
+    #[test]
+    fn priority_orders_the_ready_jobs() {
+        let specs = [
+            spec("low", r#"{"priority": -1}"#),
+            spec("plain", "{}"),
+            spec("high", r#"{"priority": 5}"#),
+            spec("also-plain", "{}"),
+        ];
+        let order = names(&specs)
+            .iter()
+            .map(|&name| name.to_owned())
+            .collect::<Vec<_>>();
+        let scheduled = schedule_specs(&specs, &order).unwrap();
+        assert_eq!(names(&scheduled), ["high", "plain", "also-plain", "low"]);
+    }
+
+    #[test]
+    fn dependencies_outrank_priority() {
+        let specs = [
+            spec("base", r#"{"priority": -10}"#),
+            spec("urgent", r#"{"priority": 10, "depends": ["base"]}"#),
+            spec("other", "{}"),
+        ];
+        let scheduled = schedule_specs(&specs, &[]).unwrap();
+        assert_eq!(names(&scheduled), ["other", "base", "urgent"]);
+    }
+
+    #[test]
+    fn the_settings_order_breaks_ties_but_not_dependencies() {
+        let root = root_with(&[
+            ("alpha", r#"{"run": "true"}"#),
+            ("beta", r#"{"run": "true"}"#),
+            ("gamma", r#"{"run": "true", "depends": ["zeta"]}"#),
+            ("zeta", r#"{"run": "true"}"#),
+        ]);
+        fs::write(
+            root.path().join(SETTINGS_FILE),
+            "order = [\"gamma\", \"beta\", \"missing\"]\n",
+        )
+        .unwrap();
+        let config = Config::for_test(root.path(), &[]);
+        let mut scheduled = Vec::new();
+        let logged = capture_logs(|| {
+            scheduled = load_specs(root.path(), &config).unwrap().0;
+        });
+        assert_eq!(names(&scheduled), ["beta", "alpha", "zeta", "gamma"]);
+        assert!(logged.contains(&format!(
+            "WARN: Job 'missing' in the order of {SETTINGS_FILE} does not exist"
+        )));
+    }
+
+    #[test]
+    fn disabled_jobs_are_split_off() {
+        let specs = vec![spec("on", "{}"), spec("off", r#"{"disabled": true}"#)];
+        let (enabled, disabled) = split_disabled(specs).unwrap();
+        assert_eq!(names(&enabled), ["on"]);
+        assert_eq!(names(&disabled), ["off"]);
+    }
+
+    #[test]
+    fn depending_on_a_disabled_job_is_an_error() {
+        let specs = vec![
+            spec("off", r#"{"disabled": true}"#),
+            spec("on", r#"{"depends": ["off"]}"#),
+        ];
+        let error = split_disabled(specs).unwrap_err();
+        assert_eq!(error.to_string(), "Job 'on' depends on disabled job 'off'");
+    }
+
+    #[test]
+    fn glob_depends_expand_to_the_other_matching_jobs() {
+        let (_root, config) = config(&[]);
+        let mut specs = [
+            spec("db-main", r#"{"depends": ["db-*"]}"#),
+            spec("db-replica", "{}"),
+            spec("app", r#"{"depends": ["db-?ain", "db-*"]}"#),
+        ];
+        expand_depends(&mut specs, &config).unwrap();
+        assert_eq!(specs[0].depends, ["db-replica"]);
+        assert_eq!(specs[2].depends, ["db-main", "db-replica"]);
+    }
+
+    #[test]
+    fn glob_depends_matching_nothing_are_an_error_unless_allowed() {
+        let specs = || [spec("app", r#"{"depends": ["cache-*"]}"#)];
+        let (_root, strict) = config(&[]);
+        let error = expand_depends(&mut specs(), &strict).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Job 'app' depends on 'cache-*', which matches no jobs"
+        );
+
+        let (_root, lenient) = config(&["--allow-empty-depends"]);
+        let mut specs = specs();
+        expand_depends(&mut specs, &lenient).unwrap();
+        assert!(specs[0].depends.is_empty());
+    }
+
+    #[test]
+    fn check_fails_on_a_dependency_cycle() {
+        let root = root_with(&[
+            ("a", r#"{"run": "true", "depends": ["b"]}"#),
+            ("b", r#"{"run": "true", "depends": ["a"]}"#),
+            ("c", r#"{"run": "true"}"#),
+        ]);
+        let config = Config::for_test(root.path(), &["--check"]);
+        assert!(config.check);
+        let error = check_jobs(&config.root_dir, &config).unwrap_err();
+        assert_eq!(error.to_string(), "Unschedulable jobs: a, b");
+    }
+
+    #[test]
+    fn check_passes_a_schedulable_root() {
+        let root = root_with(&[
+            ("a", r#"{"run": "true"}"#),
+            ("b", r#"{"run": "true", "depends": ["a"]}"#),
+        ]);
+        let config = Config::for_test(root.path(), &["--check"]);
+        check_jobs(&config.root_dir, &config).unwrap();
+    }
+
+    fn ask_names(info: &InfoSpec) -> Vec<String> {
+        info.ask
+            .iter()
+            .flatten()
+            .map(|entry| match entry {
+                AskEntry::Name(name) => name.clone(),
+                AskEntry::Detailed(var) => var.name.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ask_include_follows_nested_includes() {
+        let root = root_with(&[(
+            "app",
+            r#"{"ask": ["OWN"], "ask_include": "../common.json"}"#,
+        )]);
+        fs::write(
+            root.path().join("common.json"),
+            r#"{"ask": ["SHARED"], "ask_include": "more.json"}"#,
+        )
+        .unwrap();
+        fs::write(root.path().join("more.json"), r#"["MORE"]"#).unwrap();
+        let info = parse_resolved_info(&root.path().join("app")).unwrap();
+        assert_eq!(ask_names(&info), ["OWN", "SHARED", "MORE"]);
+    }
+
+    #[test]
+    fn circular_ask_includes_are_an_error() {
+        let root = root_with(&[("app", r#"{"ask_include": "../one.json"}"#)]);
+        fs::write(
+            root.path().join("one.json"),
+            r#"{"ask": [], "ask_include": "two.json"}"#,
+        )
+        .unwrap();
+        fs::write(
+            root.path().join("two.json"),
+            r#"{"ask": [], "ask_include": "one.json"}"#,
+        )
+        .unwrap();
+        let error = parse_resolved_info(&root.path().join("app")).unwrap_err();
+        assert!(error.to_string().starts_with("Circular ask_include: "));
+        assert!(error.to_string().ends_with("/one.json"));
+    }
+
+    #[test]
+    fn extends_inherits_a_base_and_overrides_it() {
+        let root = root_with(&[
+            (
+                "base",
+                r#"{"run": "make", "depends": ["db", "cache"], "tags": ["web"],
+                    "env": {"PORT": "80", "HOST": "localhost"}}"#,
+            ),
+            (
+                "app",
+                r#"{"extends": "../base", "depends": ["cache", "queue"],
+                    "env": {"port": "8080"}}"#,
+            ),
+        ]);
+        let info = parse_resolved_info(&root.path().join("app")).unwrap();
+        let app = JobSpec::new("app".to_owned(), info, false);
+        assert_eq!(app.inline_run.unwrap().command, "make");
+        assert_eq!(app.depends, ["db", "cache", "queue"]);
+        assert_eq!(app.tags, ["web"]);
+        assert_eq!(app.provided_env["PORT"], "8080");
+        assert_eq!(app.provided_env["HOST"], "localhost");
+        assert_eq!(app.provided_env.len(), 2);
+    }
+
+    #[test]
+    fn circular_extends_are_an_error() {
+        let root = root_with(&[
+            ("app", r#"{"extends": "../base"}"#),
+            ("base", r#"{"extends": "../app"}"#),
+        ]);
+        let error = parse_resolved_info(&root.path().join("app"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Circular extends: "), "{}", error);
+        assert_eq!(error.matches("/app/info.json").count(), 2, "{error}");
+    }
+
+    fn ready(name: &str, info: &str, env: &[(&str, &str)]) -> ReadyJob {
+        let env = env
+            .iter()
+            .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+            .collect();
+        ReadyJob::new(spec(name, info), env, HashSet::new(), HashSet::new())
+    }
+
+    #[test]
+    fn env_from_merges_in_listed_order_under_the_jobs_own_env() {
+        let mut queue = [
+            ready(
+                "first",
+                "{}",
+                &[("A", "first"), ("B", "first"), ("C", "first")],
+            ),
+            ready("second", r#"{"env_from": ["first"]}"#, &[("B", "second")]),
+            ready(
+                "app",
+                r#"{"env_from": ["second", "first"]}"#,
+                &[("C", "app")],
+            ),
+        ];
+        merge_env_from(&mut queue);
+        assert_eq!(queue[1].env()["A"], "first");
+        let env = queue[2].env();
+        assert_eq!(env["A"], "first");
+        assert_eq!(env["B"], "first");
+        assert_eq!(env["C"], "app");
+    }
+
+    #[test]
+    fn env_from_adds_missing_dependencies_and_rejects_unknown_jobs() {
+        let mut specs = [spec("base", "{}"), spec("app", r#"{"env_from": ["base"]}"#)];
+        add_env_from_depends(&mut specs).unwrap();
+        assert_eq!(specs[1].depends, ["base"]);
+
+        let mut specs = [spec("app", r#"{"env_from": ["missing"]}"#)];
+        let error = add_env_from_depends(&mut specs).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Job 'app' takes env from unknown job 'missing'"
+        );
+    }
+
+    #[test]
+    fn recursive_discovery_names_nested_jobs_by_their_path() {
+        let root = root_with(&[
+            ("top", r#"{"run": "true"}"#),
+            ("languages/rust", r#"{"run": "true"}"#),
+            ("languages/go/tools", r#"{"run": "true"}"#),
+            (".git/hooks", r#"{"run": "true"}"#),
+        ]);
+        let flat = Config::for_test(root.path(), &[]);
+        assert_eq!(get_job_names(root.path(), &flat).unwrap(), ["top"]);
+
+        let recursive = Config::for_test(root.path(), &["--recursive"]);
+        assert_eq!(
+            get_job_names(root.path(), &recursive).unwrap(),
+            ["languages/go/tools", "languages/rust", "top"]
+        );
+
+        let shallow = Config::for_test(root.path(), &["--recursive", "--max-discovery-depth", "2"]);
+        assert_eq!(
+            get_job_names(root.path(), &shallow).unwrap(),
+            ["languages/rust", "top"]
+        );
+    }
+
+    fn queue(names: &[&str]) -> Vec<ReadyJob> {
+        names.iter().map(|name| ready(name, "{}", &[])).collect()
+    }
+
+    #[test]
+    fn slice_includes_both_boundary_jobs() {
+        let all = ["a", "b", "c", "d"];
+        let slice = |flags: &[&str]| {
+            let (_root, config) = config(flags);
+            let sliced = slice_queue(queue(&all), &config).unwrap();
+            sliced
+                .iter()
+                .map(|job| job.name().clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(slice(&[]), all);
+        assert_eq!(slice(&["--start-from", "b"]), ["b", "c", "d"]);
+        assert_eq!(slice(&["--stop-after", "c"]), ["a", "b", "c"]);
+        assert_eq!(slice(&["--start-from", "b", "--stop-after", "b"]), ["b"]);
+        assert_eq!(slice(&["--start-from", "a", "--stop-after", "d"]), all);
+    }
+
+    #[test]
+    fn slice_rejects_unknown_and_reversed_boundaries() {
+        let (_root, reversed) = config(&["--start-from", "c", "--stop-after", "a"]);
+        let error = slice_queue(queue(&["a", "b", "c"]), &reversed).unwrap_err();
+        assert_eq!(error.to_string(), "Job 'a' is scheduled before job 'c'");
+
+        let (_root, unknown) = config(&["--stop-after", "bb"]);
+        let error = slice_queue(queue(&["a", "b", "c"]), &unknown).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cannot locate job 'bb'. Did you mean 'b'?"
+        );
+    }
+
+    #[test]
+    fn pipelines_run_exactly_the_listed_jobs_in_their_order() {
+        let (_root, config) = config(&["--pipeline", "c,a"]);
+        let picked = slice_queue(queue(&["a", "b", "c"]), &config).unwrap();
+        let names: Vec<_> = picked.iter().map(ReadyJob::name).collect();
+        assert_eq!(names, ["c", "a"]);
+
+        let error =
+            pipeline_queue(queue(&["a", "b"]), &["a".to_owned(), "a".to_owned()]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Job 'a' is in the pipeline more than once"
+        );
+
+        let error = pipeline_queue(queue(&["a", "bee"]), &["be".to_owned()]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cannot locate job 'be'. Did you mean 'bee'?"
+        );
+    }
+
+    fn filled(name: &str, info: &str) -> ReadyJob {
+        fill_asked(
+            spec(name, info),
+            &HashMap::new(),
+            SECURE_SUFFIX,
+            &SecurePatterns::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn env_conflicts_are_found_between_transitively_dependent_jobs() {
+        let queue = [
+            filled("base", r#"{"env": {"MODE": "dev", "PORT": "80"}}"#),
+            filled("other", r#"{"env": {"MODE": "test"}}"#),
+            filled("mid", r#"{"depends": ["base"]}"#),
+            filled(
+                "app",
+                r#"{"depends": ["mid"], "env": {"MODE": "prod", "PORT": "80"}}"#,
+            ),
+        ];
+        let (_root, lenient) = config(&[]);
+        check_env_conflicts(&queue, &lenient).unwrap();
+
+        let (_root, strict) = config(&["--strict-env"]);
+        assert_eq!(
+            check_env_conflicts(&queue, &strict)
+                .unwrap_err()
+                .to_string(),
+            "Found 1 conflicting env value(s) between dependent jobs"
+        );
+        check_env_conflicts(&queue[..3], &strict).unwrap();
+    }
+
+    #[test]
+    fn manifest_lists_jobs_in_its_own_order() {
+        let root = root_with(&[
+            ("web", r#"{"run": "true"}"#),
+            ("db", r#"{"run": "true"}"#),
+            ("unlisted", r#"{"run": "true"}"#),
+            ("docs", "{}"),
+        ]);
+        let manifest = "# Databases first\ndb/\n\nweb\n";
+        fs::write(root.path().join(JOBS_MANIFEST), manifest).unwrap();
+        let config = Config::for_test(root.path(), &[]);
+        assert_eq!(get_job_names(root.path(), &config).unwrap(), ["db", "web"]);
+    }
+
+    #[test]
+    fn manifest_entries_must_be_runnable_jobs_listed_once() {
+        let root = root_with(&[("web", r#"{"run": "true"}"#), ("docs", "{}")]);
+        let config = Config::for_test(root.path(), &[]);
+        let error = |manifest: &str| {
+            fs::write(root.path().join(JOBS_MANIFEST), manifest).unwrap();
+            get_job_names(root.path(), &config).unwrap_err().to_string()
+        };
+        assert_eq!(
+            error("web\nmissing\n"),
+            "Job 'missing' is listed in devmaker.jobs, but doesn't exist"
+        );
+        assert_eq!(
+            error("web\nweb/\n"),
+            "Job 'web' is listed twice in devmaker.jobs"
+        );
+        assert_eq!(error("docs\n"), "No runner found for job 'docs'");
+    }
+
+    #[test]
+    fn self_dependencies_are_reported_as_such() {
+        let root = root_with(&[
+            ("foo", r#"{"run": "true", "depends": ["bar", "foo"]}"#),
+            ("bar", r#"{"run": "true"}"#),
+        ]);
+        let config = Config::for_test(root.path(), &[]);
+        let error = load_specs(root.path(), &config).map(|_| ()).unwrap_err();
+        assert_eq!(error.to_string(), "Job 'foo' depends on itself");
+
+        let conditional = spec(
+            "foo",
+            r#"{"depends": [{"job": "foo", "when_var": "MODE", "equals": "full"}]}"#,
+        );
+        assert_eq!(
+            check_self_depends(&conditional).unwrap_err().to_string(),
+            "Job 'foo' depends on itself"
+        );
+    }
+
+    #[test]
+    fn conditional_dependencies_follow_their_variable() {
+        let (root, config) = config(&[]);
+        let specs = |skip_unneeded: bool| {
+            let train = serde_json::json!({
+                "ask": ["USE_GPU"],
+                "depends": [{
+                    "job": "z-gpu",
+                    "when_var": "USE_GPU",
+                    "equals": "1",
+                    "skip_unneeded": skip_unneeded,
+                }],
+            });
+            let specs = [
+                spec("a-train", &train.to_string()),
+                spec("m-other", "{}"),
+                spec("z-gpu", "{}"),
+            ];
+            schedule_specs(&specs, &[]).unwrap()
+        };
+        let answers = |value: &str| HashMap::from([("USE_GPU".to_owned(), value.to_owned())]);
+        let scheduled = |skip_unneeded, value| {
+            apply_conditional_depends(root.path(), specs(skip_unneeded), &answers(value), &config)
+                .unwrap()
+        };
+
+        let gpu = scheduled(true, "1");
+        assert_eq!(names(&gpu), ["m-other", "z-gpu", "a-train"]);
+        assert_eq!(gpu[2].depends, ["z-gpu"]);
+
+        let no_gpu = scheduled(false, "0");
+        assert_eq!(names(&no_gpu), ["a-train", "m-other", "z-gpu"]);
+        assert!(no_gpu[0].depends.is_empty());
+
+        assert_eq!(names(&scheduled(true, "0")), ["a-train", "m-other"]);
+    }
+
+    #[test]
+    fn conditional_dependencies_must_name_a_single_job() {
+        let glob = spec(
+            "app",
+            r#"{"depends": [{"job": "db-*", "when_var": "MODE", "equals": "full"}]}"#,
+        );
+        assert_eq!(
+            check_self_depends(&glob).unwrap_err().to_string(),
+            "Job 'app' has a conditional dependency on 'db-*', which must name a single job"
+        );
+    }
+
+    #[test]
+    fn combined_jobs_file_defines_every_job() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let jobs = r#"
+            [db]
+            run = "createdb app"
+            env = { PORT = "5432" }
+
+            [app]
+            script = "scripts/app.sh"
+            depends = ["db"]
+        "#;
+        fs::write(root.path().join("jobs.toml"), jobs).unwrap();
+        fs::create_dir(root.path().join("scripts")).unwrap();
+        fs::write(root.path().join("scripts/app.sh"), "#!/bin/sh\n").unwrap();
+        // Directories are ignored once there's a jobs file.
+        fs::create_dir(root.path().join("stray")).unwrap();
+        fs::write(root.path().join("stray/run.sh"), "#!/bin/sh\n").unwrap();
+
+        let config = Config::for_test(root.path(), &[]);
+        let (specs, _) = load_specs(root.path(), &config).unwrap();
+        assert_eq!(names(&specs), ["db", "app"]);
+        assert_eq!(specs[0].provided_env["PORT"], "5432");
+        assert_eq!(
+            specs[0].inline_run.as_ref().unwrap().command,
+            "createdb app"
+        );
+        assert_eq!(specs[1].script.as_deref(), Some("scripts/app.sh"));
+        assert_eq!(specs[1].depends, ["db"]);
+        assert!(specs.iter().all(|spec| spec.dir == Path::new("")));
+    }
+
+    #[test]
+    fn combined_jobs_file_is_parsed_once_per_root() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let jobs_file = root.path().join("jobs.json");
+        fs::write(
+            &jobs_file,
+            r#"{"base": {"env": {"PORT": "5432"}}, "db": {"extends": "base"}}"#,
+        )
+        .unwrap();
+        let mut jobs_files = JobsFiles::default();
+        let base = parse_discovered_job("base", root.path(), root.path(), None, &mut jobs_files);
+        assert_eq!(base.unwrap().provided_env["PORT"], "5432");
+
+        fs::write(&jobs_file, "not json").unwrap();
+        let db = parse_discovered_job("db", root.path(), root.path(), None, &mut jobs_files);
+        assert_eq!(db.unwrap().provided_env["PORT"], "5432");
+        let mut fresh = JobsFiles::default();
+        assert!(parse_discovered_job("db", root.path(), root.path(), None, &mut fresh).is_err());
+    }
+
+    #[test]
+    fn only_one_combined_jobs_file_may_be_present() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::write(root.path().join("jobs.json"), r#"{"db": {"run": "true"}}"#).unwrap();
+        let config = Config::for_test(root.path(), &[]);
+        assert_eq!(get_job_names(root.path(), &config).unwrap(), ["db"]);
+
+        fs::write(root.path().join("jobs.toml"), "[db]\nrun = \"true\"\n").unwrap();
+        let error = get_job_names(root.path(), &config).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Only one of jobs.json and jobs.toml may be present in "));
+    }
+
+    #[test]
+    fn continuing_skips_the_jobs_the_failed_run_completed() {
+        let _jobs = running_jobs();
+        let state = TempDir::new("devmaker-state").unwrap();
+        let root = root_with(&[
+            ("a", r#"{"run": "echo a >> ../ran", "cwd": "script_dir"}"#),
+            (
+                "b",
+                r#"{"run": "test -f ok && echo b >> ../ran", "cwd": "script_dir",
+                    "depends": ["a"]}"#,
+            ),
+            (
+                "c",
+                r#"{"run": "echo c >> ../ran", "cwd": "script_dir", "depends": ["b"]}"#,
+            ),
+        ]);
+        let ran = || fs::read_to_string(root.path().join("ran")).unwrap();
+        let config = config_in(&state, root.path(), &[]);
+        run_all_jobs(root.path(), &config).unwrap_err();
+        assert_eq!(ran(), "a\n");
+
+        fs::write(root.path().join("b/ok"), "").unwrap();
+        let resuming = config_in(&state, root.path(), &["--continue-from-last"]);
+        run_all_jobs(root.path(), &resuming).unwrap();
+        assert_eq!(ran(), "a\nb\nc\n");
+
+        reset_state(state.path(), root.path()).unwrap();
+        run_all_jobs(root.path(), &resuming).unwrap();
+        assert_eq!(ran(), "a\nb\nc\na\nb\nc\n");
+    }
+
+    #[test]
+    fn always_jobs_run_again_when_continuing() {
+        let _jobs = running_jobs();
+        let state = TempDir::new("devmaker-state").unwrap();
+        let root = root_with(&[
+            (
+                "reload",
+                r#"{"run": "echo reload >> ../ran", "cwd": "script_dir", "always": true}"#,
+            ),
+            (
+                "setup",
+                r#"{"run": "echo setup >> ../ran", "cwd": "script_dir"}"#,
+            ),
+            (
+                "finish",
+                r#"{"run": "test -f ok && echo finish >> ../ran", "cwd": "script_dir",
+                    "depends": ["reload", "setup"]}"#,
+            ),
+        ]);
+        let ran = || fs::read_to_string(root.path().join("ran")).unwrap();
+        let config = config_in(&state, root.path(), &[]);
+        run_all_jobs(root.path(), &config).unwrap_err();
+        assert_eq!(ran(), "reload\nsetup\n");
+
+        fs::write(root.path().join("finish/ok"), "").unwrap();
+        let resuming = config_in(&state, root.path(), &["--continue-from-last"]);
+        run_all_jobs(root.path(), &resuming).unwrap();
+        assert_eq!(ran(), "reload\nsetup\nreload\nfinish\n");
+    }
+
+    #[test]
+    fn rollback_undoes_the_completed_jobs_in_reverse() {
+        let _jobs = running_jobs();
+        let state = TempDir::new("devmaker-state").unwrap();
+        let root = root_with(&[
+            ("a", r#"{"run": "true", "env": {"STEP": "a"}}"#),
+            (
+                "b",
+                r#"{"run": "true", "env": {"STEP": "b"}, "depends": ["a"]}"#,
+            ),
+            ("plain", r#"{"run": "true", "depends": ["b"]}"#),
+            ("broken", r#"{"run": "exit 1", "depends": ["plain"]}"#),
+            ("never", r#"{"run": "true", "depends": ["broken"]}"#),
+        ]);
+        for (job, undo) in &[
+            ("a", "echo \"$STEP\" >> \"$SCRIPT_DIR/../undone\"\n"),
+            ("b", "echo \"$STEP\" >> \"$SCRIPT_DIR/../undone\"\nexit 1\n"),
+            ("never", "echo never >> \"$SCRIPT_DIR/../undone\"\n"),
+        ] {
+            let path = root.path().join(job).join(UNDO_SCRIPT);
+            fs::write(&path, format!("#!/bin/sh\n{undo}")).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let config = config_in(&state, root.path(), &["--rollback"]);
+        let error = run_all_jobs(root.path(), &config).unwrap_err();
+        assert_eq!(error.to_string(), "Job 'broken' failed with exit code 1");
+        let undone = fs::read_to_string(root.path().join("undone")).unwrap();
+        assert_eq!(undone, "b\na\n");
+    }
+
+    #[test]
+    fn oneline_summary_failures_are_marked_as_summarized() {
+        let _jobs = running_jobs();
+        let state = TempDir::new("devmaker-state").unwrap();
+        let root = root_with(&[("broken", r#"{"run": "exit 1"}"#)]);
+        let config = config_in(&state, root.path(), &["--oneline-summary"]);
+        let error = run_all_jobs(root.path(), &config).unwrap_err();
+        let summarized = error.downcast_ref::<Summarized>().unwrap();
+        assert_eq!(
+            summarized.0.to_string(),
+            "Job 'broken' failed with exit code 1"
+        );
+        // The lock went with the error, rather than being left for the next run.
+        RunLock::acquire(state.path(), root.path()).unwrap();
+    }
+
+    /// The jobs in `root`, in the given order, ready to run.
+    fn ready_queue(root: &Path, names: &[&str]) -> Vec<ReadyJob> {
+        names
+            .iter()
+            .map(|name| {
+                fill_asked(
+                    parse_job_dir(name, &root.join(name), None).unwrap(),
+                    &HashMap::new(),
+                    SECURE_SUFFIX,
+                    &SecurePatterns::default(),
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+
+    /// Each of a pair of jobs waits for the other to start, which only works when they run at
+    /// the same time.
+    fn rendezvous(other: &str) -> String {
+        let run = format!(
+            "touch started; \
+             for i in $(seq 50); do test -f ../{other}/started && exit 0; sleep 0.1; done; \
+             exit 1"
+        );
+        serde_json::json!({ "cwd": "script_dir", "run": run }).to_string()
+    }
+
+    #[test]
+    fn concurrent_runs_start_ready_jobs_together_and_carry_on_past_failures() {
+        let _jobs = running_jobs();
+        let root = root_with(&[
+            ("a", &rendezvous("b")),
+            ("b", &rendezvous("a")),
+            ("broken", r#"{"run": "exit 1"}"#),
+            ("c", r#"{"run": "true", "depends": ["a", "b"]}"#),
+            ("after-broken", r#"{"run": "true", "depends": ["broken"]}"#),
+        ]);
+        let config = Config::for_test(root.path(), &["-j", "2", "--on-failure", "continue"]);
+        let queue = ready_queue(root.path(), &["a", "b", "broken", "c", "after-broken"]);
+        let context = RunContext::new(
+            queue.iter().map(|job| job.name().clone()).collect(),
+            &config,
+        );
+        let mut state = RunState::unrecorded(root.path());
+
+        let error = run_concurrently(
+            &queue,
+            root.path(),
+            &context,
+            &mut state,
+            &config,
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Job 'broken' failed with exit code 1\nNot run because of an earlier failure: after-broken"
+        );
+        for job in &["a", "b", "c"] {
+            assert!(context.ran_successfully(job), "{} didn't run", job);
+        }
+        assert!(context.failed("broken"));
+        assert!(state.completed("c"));
+        assert!(!state.completed("broken"));
+    }
+
+    #[test]
+    fn failure_policy_decides_whether_the_run_carries_on() {
+        let _jobs = running_jobs();
+        let root = root_with(&[
+            ("broken", r#"{"run": "exit 1"}"#),
+            ("after-broken", r#"{"run": "true", "depends": ["broken"]}"#),
+            ("other", r#"{"run": "true"}"#),
+        ]);
+        let queue = ready_queue(root.path(), &["broken", "after-broken", "other"]);
+        let run = |policy: &str| {
+            let config = Config::for_test(root.path(), &["--on-failure", policy]);
+            let context = RunContext::new(Vec::new(), &config);
+            let mut state = RunState::unrecorded(root.path());
+            let result = run_in_order(
+                &queue,
+                root.path(),
+                &context,
+                &mut state,
+                &config,
+                Vec::new(),
+            );
+            (result.unwrap_err().to_string(), state.completed("other"))
+        };
+
+        let (error, ran_other) = run("abort");
+        assert_eq!(error, "Job 'broken' failed with exit code 1");
+        assert!(!ran_other);
+
+        let (error, ran_other) = run("continue");
+        assert_eq!(
+            error,
+            "Job 'broken' failed with exit code 1\nNot run because of an earlier failure: after-broken"
+        );
+        assert!(ran_other);
+    }
+
+    #[test]
+    fn failed_deps_scripts_skip_their_jobs_and_dependents_when_continuing() {
+        let _jobs = running_jobs();
+        let root = root_with(&[
+            ("a", r#"{"run": "echo a >> ../ran", "cwd": "script_dir"}"#),
+            (
+                "b",
+                r#"{"run": "echo b >> ../ran", "cwd": "script_dir", "depends": ["a"]}"#,
+            ),
+            ("c", r#"{"run": "echo c >> ../ran", "cwd": "script_dir"}"#),
+        ]);
+        for (job, deps) in &[
+            ("a", "exit 1\n"),
+            ("b", "echo b-deps >> \"$SCRIPT_DIR/../ran\"\n"),
+            ("c", "echo c-deps >> \"$SCRIPT_DIR/../ran\"\n"),
+        ] {
+            let path = root.path().join(job).join(DEPS_SCRIPT);
+            fs::write(&path, format!("#!/bin/sh\n{deps}")).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        for jobs in &["1", "2"] {
+            let state = TempDir::new("devmaker-state").unwrap();
+            let config = config_in(
+                &state,
+                root.path(),
+                &["--deps-first", "--on-failure", "continue", "--jobs", jobs],
+            );
+            let queue = ready_queue(root.path(), &["a", "b", "c"]);
+            let context = RunContext::new(
+                queue.iter().map(|job| job.name().clone()).collect(),
+                &config,
+            );
+            let error = run_queue(queue, root.path(), &context, &config).unwrap_err();
+            let error = error.to_string();
+            assert!(
+                error.ends_with("\nNot run because of an earlier failure: b"),
+                "{}",
+                error
+            );
+            let ran = fs::read_to_string(root.path().join("ran")).unwrap();
+            assert_eq!(ran, "c-deps\nc\n");
+            assert_eq!(context.summary(), "devmaker: 1 ok, 1 failed (a), 1 skipped");
+            fs::remove_file(root.path().join("ran")).unwrap();
+        }
+    }
+
+    #[test]
+    fn allowed_failures_dont_stop_the_run_or_its_dependents() {
+        let _jobs = running_jobs();
+        let root = root_with(&[
+            ("nicety", r#"{"run": "exit 3", "allow_failure": true}"#),
+            ("after", r#"{"run": "true", "depends": ["nicety"]}"#),
+            ("other", r#"{"run": "true"}"#),
+        ]);
+        let queue = ready_queue(root.path(), &["nicety", "after", "other"]);
+        let config = Config::for_test(root.path(), &["--on-failure", "abort"]);
+        let context = RunContext::new(
+            queue.iter().map(|job| job.name().clone()).collect(),
+            &config,
+        );
+        let mut state = RunState::unrecorded(root.path());
+
+        run_in_order(
+            &queue,
+            root.path(),
+            &context,
+            &mut state,
+            &config,
+            Vec::new(),
+        )
+        .unwrap();
+        assert!(state.completed("after"));
+        assert!(state.completed("other"));
+        assert!(!state.completed("nicety"));
+        assert!(context.tolerated("nicety"));
+        assert_eq!(context.summary(), "devmaker: 2 ok, 1 tolerated (nicety)");
+    }
+
+    #[test]
+    fn jobs_past_the_max_runtime_are_not_started() {
+        let _jobs = running_jobs();
+        let root = root_with(&[
+            ("slow", r#"{"run": "sleep 1.2"}"#),
+            ("later", r#"{"run": "true"}"#),
+            ("last", r#"{"run": "true"}"#),
+        ]);
+        let queue = ready_queue(root.path(), &["slow", "later", "last"]);
+        let config = Config::for_test(root.path(), &["--max-runtime", "1"]);
+        let context = RunContext::new(Vec::new(), &config);
+        let mut state = RunState::unrecorded(root.path());
+
+        let error = run_in_order(
+            &queue,
+            root.path(),
+            &context,
+            &mut state,
+            &config,
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Not run because the run went over --max-runtime: later, last"
+        );
+        assert!(state.completed("slow"));
+        assert!(!state.completed("later"));
+
+        let config = Config::for_test(root.path(), &["--max-runtime", "0", "-j", "2"]);
+        let context = RunContext::new(Vec::new(), &config);
+        let mut state = RunState::unrecorded(root.path());
+        let queue = ready_queue(root.path(), &["later", "last"]);
+        let error = run_concurrently(
+            &queue,
+            root.path(),
+            &context,
+            &mut state,
+            &config,
+            Vec::new(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Not run because the run went over --max-runtime: later, last"
+        );
+    }
+
+    #[test]
+    fn simulated_failures_fail_jobs_without_running_anything() {
+        let _jobs = running_jobs();
+        let state = TempDir::new("devmaker-state").unwrap();
+        let root = root_with(&[
+            ("broken", r#"{"run": "touch ran", "cwd": "script_dir"}"#),
+            (
+                "after-broken",
+                r#"{"run": "touch ran", "cwd": "script_dir", "depends": ["broken"]}"#,
+            ),
+            ("other", r#"{"run": "touch ran", "cwd": "script_dir"}"#),
+        ]);
+        let config = Config::for_test(
+            root.path(),
+            &[
+                "--simulate-failures",
+                "broken",
+                "--on-failure",
+                "continue",
+                "--oneline-summary",
+            ],
+        );
+        let queue = ready_queue(root.path(), &["broken", "after-broken", "other"]);
+        let context = RunContext::new(
+            queue.iter().map(|job| job.name().clone()).collect(),
+            &config,
+        );
+        let error = run_queue(queue, root.path(), &context, &config).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Job 'broken' failed with exit code 1 (simulated)\nNot run because of an earlier \
+             failure: after-broken"
+        );
+        assert_eq!(
+            context.summary(),
+            "devmaker: 1 ok, 1 failed (broken), 1 skipped"
+        );
+        for job in &["broken", "after-broken", "other"] {
+            assert!(!root.path().join(job).join("ran").exists());
+        }
+
+        let config = config_in(&state, root.path(), &["--simulate-failures", "brokn"]);
+        let error = run_all_jobs(root.path(), &config).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cannot locate job 'brokn'. Did you mean 'broken'?"
+        );
+    }
+
+    #[test]
+    fn inspecting_a_job_dir_parses_it_like_discovery() {
+        let root = root_with(&[
+            ("db", r#"{"description": "Postgres", "depends": ["base"]}"#),
+            ("inline", r#"{"run": "true"}"#),
+            ("notes", r#"{"description": "Nothing to run"}"#),
+            ("broken", r#"{"depends": "#),
+        ]);
+        fs::write(root.path().join("db/run.sh"), "").unwrap();
+        fs::write(root.path().join("broken/run.sh"), "").unwrap();
+
+        let db = inspect_job_dir(&root.path().join("db")).unwrap();
+        assert_eq!(db.name, "db");
+        assert_eq!(db.description.as_deref(), Some("Postgres"));
+        assert_eq!(db.depends, ["base"]);
+        assert_eq!(db.dir, fs::canonicalize(root.path().join("db")).unwrap());
+        assert!(inspect_job_dir(&root.path().join("inline")).is_ok());
+
+        let notes = fs::canonicalize(root.path().join("notes")).unwrap();
+        assert_eq!(
+            inspect_job_dir(&notes).unwrap_err().to_string(),
+            format!(
+                "{} is not a job directory: it has no run.* file, and no run, script, runner or \
+                 exec in info.json",
+                notes.display()
+            )
+        );
+        let error = inspect_job_dir(&root.path().join("broken")).unwrap_err();
+        assert!(error.to_string().contains("EOF while parsing"), "{}", error);
+    }
+
+    #[test]
+    fn discovery_stops_at_the_max_depth() {
+        let root = root_with(&[
+            ("one", r#"{"run": "true"}"#),
+            ("one/two", r#"{"run": "true"}"#),
+            ("one/two/three", r#"{"run": "true"}"#),
+            ("one/two/three/four", r#"{"run": "true"}"#),
+        ]);
+        let found = |depth: &str| {
+            let config = Config::for_test(
+                root.path(),
+                &["--recursive", "--max-discovery-depth", depth],
+            );
+            get_job_names(root.path(), &config).unwrap()
+        };
+        assert_eq!(found("1"), ["one"]);
+        assert_eq!(found("3"), ["one", "one/two", "one/two/three"]);
+    }
+
+    #[test]
+    fn symlinked_directories_are_followed_once_and_only_when_asked() {
+        let root = root_with(&[("jobs/real", r#"{"run": "true"}"#)]);
+        std::os::unix::fs::symlink(root.path().join("jobs"), root.path().join("jobs/loop"))
+            .unwrap();
+        std::os::unix::fs::symlink(root.path().join("jobs/real"), root.path().join("alias"))
+            .unwrap();
+
+        let config = Config::for_test(root.path(), &["--recursive"]);
+        assert_eq!(get_job_names(root.path(), &config).unwrap(), ["jobs/real"]);
+
+        // The loop leads back to a directory visited already, and the job is only found once,
+        // by the first path reaching it.
+        let following = Config::for_test(root.path(), &["--recursive", "--follow-symlinks"]);
+        assert_eq!(get_job_names(root.path(), &following).unwrap(), ["alias"]);
+    }
+
+    #[test]
+    fn devmaker_path_roots_follow_the_primary_one() {
+        let primary = root_with(&[
+            ("shared", r#"{"run": "true"}"#),
+            ("local", r#"{"run": "true"}"#),
+        ]);
+        let extra = root_with(&[
+            ("shared", r#"{"run": "false"}"#),
+            ("plugin", r#"{"run": "true"}"#),
+        ]);
+        let (primary, extra) = (
+            fs::canonicalize(primary.path()).unwrap(),
+            fs::canonicalize(extra.path()).unwrap(),
+        );
+        let path = env::join_paths([
+            extra.as_path(),
+            Path::new(""),
+            Path::new("/nonexistent"),
+            primary.as_path(),
+        ])
+        .unwrap();
+        let roots = job_roots(&primary, &path);
+        assert_eq!(roots, [primary.clone(), extra.clone()]);
+
+        let config = Config::for_test(&primary, &[]);
+        let jobs = discover_jobs_in(roots, &config).unwrap();
+        let expected = [
+            ("local".to_owned(), primary.clone()),
+            ("shared".to_owned(), primary.clone()),
+            ("plugin".to_owned(), extra.clone()),
+        ];
+        assert_eq!(jobs, expected);
+
+        let mut jobs_files = JobsFiles::default();
+        let plugin =
+            parse_discovered_job("plugin", &extra, &primary, None, &mut jobs_files).unwrap();
+        assert_eq!(plugin.dir, extra.join("plugin"));
+        let shared =
+            parse_discovered_job("shared", &primary, &primary, None, &mut jobs_files).unwrap();
+        assert_eq!(shared.dir, Path::new("shared"));
+        assert_eq!(shared.inline_run.unwrap().command, "true");
+    }
+
+    #[test]
+    fn tag_depends_expand_to_every_other_tagged_job_in_schedule() {
+        let (_root, config) = config(&[]);
+        let mut specs = [
+            spec("vim", r#"{"tags": ["editors"]}"#),
+            spec("emacs", r#"{"tags": ["editors", "slow"]}"#),
+            spec(
+                "plugins",
+                r#"{"tags": ["editors"], "depends": ["tag:editors"]}"#,
+            ),
+            spec("dotfiles", r#"{"depends": ["tag:editors", "vim"]}"#),
+        ];
+        expand_depends(&mut specs, &config).unwrap();
+        assert_eq!(specs[2].depends, ["emacs", "vim"]);
+        assert_eq!(specs[3].depends, ["emacs", "plugins", "vim"]);
+
+        let scheduled = schedule_specs(&specs, &[]).unwrap();
+        assert_eq!(names(&scheduled), ["emacs", "vim", "plugins", "dotfiles"]);
+    }
+
+    #[test]
+    fn tag_depends_matching_nothing_are_an_error_unless_allowed() {
+        let specs = || [spec("app", r#"{"depends": ["tag:databases"]}"#)];
+        let (_root, strict) = config(&[]);
+        assert_eq!(
+            expand_depends(&mut specs(), &strict)
+                .unwrap_err()
+                .to_string(),
+            "Job 'app' depends on 'tag:databases', which matches no jobs"
+        );
+
+        let (_root, lenient) = config(&["--allow-empty-depends"]);
+        let mut specs = specs();
+        expand_depends(&mut specs, &lenient).unwrap();
+        assert!(specs[0].depends.is_empty());
+    }
+
+    #[test]
+    fn selected_jobs_pull_in_everything_they_depend_on() {
+        let specs = [
+            spec("base", "{}"),
+            spec("db", r#"{"depends": ["base"]}"#),
+            spec("cache", "{}"),
+            spec("app", r#"{"depends": ["db", "cache"]}"#),
+            spec("docs", "{}"),
+        ];
+        let chosen = ["app".to_owned()];
+        let mut closure: Vec<String> = dependency_closure(&specs, chosen.iter())
+            .into_iter()
+            .collect();
+        closure.sort();
+        assert_eq!(closure, ["app", "base", "cache", "db"]);
+
+        let chosen = ["db".to_owned(), "base".to_owned(), "docs".to_owned()];
+        let mut closure: Vec<String> = dependency_closure(&specs, chosen.iter())
+            .into_iter()
+            .collect();
+        closure.sort();
+        assert_eq!(closure, ["base", "db", "docs"]);
+        assert!(dependency_closure(&specs, [].iter()).is_empty());
+    }
+
+    #[test]
+    fn list_shows_descriptions_beside_aligned_names() {
+        let scheduled = [
+            spec("db", r#"{"description": "Install and start Postgres"}"#),
+            spec("dotfiles", "{}"),
+        ];
+        let disabled = [spec("old", r#"{"description": "Replaced by db"}"#)];
+        assert_eq!(
+            list_lines(&scheduled, &disabled),
+            [
+                "db        Install and start Postgres",
+                "dotfiles",
+                "old       (disabled) Replaced by db",
+            ]
+        );
     }
 }
@@ -0,0 +1,120 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+
+/// Write the results of the run for `node_exporter`'s textfile collector.
+///
+/// The metrics go to a temp file beside `path`, which is then renamed over it, so the
+/// collector never reads a partial file.
+pub(crate) fn write_metrics(path: &Path, results: &[(String, Duration, bool)]) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!(format!("Not a file path: {}", path.display())))?;
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+    let finished = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    debug!("Writing metrics to {}", path.display());
+    fs::write(&tmp_path, render_metrics(results, finished))?;
+    fs::rename(&tmp_path, path)
+        .map_err(|e| anyhow!(format!("Cannot write metrics to {}: {}", path.display(), e)))
+}
+
+/// Render the metrics in Prometheus' text format.  `finished` is the time since the epoch.
+fn render_metrics(results: &[(String, Duration, bool)], finished: Duration) -> String {
+    let mut metrics = String::new();
+    metrics.push_str("# HELP devmaker_job_duration_seconds How long each job took to run.\n");
+    metrics.push_str("# TYPE devmaker_job_duration_seconds gauge\n");
+    for (job, took, _) in results {
+        let _ = writeln!(
+            metrics,
+            "devmaker_job_duration_seconds{{job=\"{}\"}} {:.3}",
+            escape_label(job),
+            took.as_secs_f64()
+        );
+    }
+    metrics.push_str("# HELP devmaker_job_success Whether each job succeeded, 1 or 0.\n");
+    metrics.push_str("# TYPE devmaker_job_success gauge\n");
+    for (job, _, succeeded) in results {
+        let _ = writeln!(
+            metrics,
+            "devmaker_job_success{{job=\"{}\"}} {}",
+            escape_label(job),
+            u8::from(*succeeded)
+        );
+    }
+    metrics.push_str(
+        "# HELP devmaker_last_run_timestamp When the last run finished, as a Unix time.\n",
+    );
+    metrics.push_str("# TYPE devmaker_last_run_timestamp gauge\n");
+    let _ = writeln!(
+        metrics,
+        "devmaker_last_run_timestamp {}",
+        finished.as_secs()
+    );
+    metrics
+}
+
+/// Escape a label value, as Prometheus' text format requires.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    fn results() -> Vec<(String, Duration, bool)> {
+        vec![
+            ("foo".to_owned(), Duration::from_millis(12_300), true),
+            ("bar".to_owned(), Duration::from_millis(1500), false),
+        ]
+    }
+
+    #[test]
+    fn metrics_are_rendered_one_sample_per_line() {
+        let rendered = render_metrics(&results(), Duration::from_secs(1_700_000_000));
+        let samples: Vec<&str> = rendered
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect();
+        assert_eq!(
+            samples,
+            [
+                r#"devmaker_job_duration_seconds{job="foo"} 12.300"#,
+                r#"devmaker_job_duration_seconds{job="bar"} 1.500"#,
+                r#"devmaker_job_success{job="foo"} 1"#,
+                r#"devmaker_job_success{job="bar"} 0"#,
+                "devmaker_last_run_timestamp 1700000000",
+            ]
+        );
+        assert!(rendered.contains("# TYPE devmaker_job_success gauge\n"));
+        assert!(rendered.ends_with('\n'));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape_label("a\\b\"c\nd"), r#"a\\b\"c\nd"#);
+        let results = [("we\"ird".to_owned(), Duration::from_secs(1), true)];
+        let rendered = render_metrics(&results, Duration::from_secs(0));
+        assert!(rendered.contains(r#"devmaker_job_success{job="we\"ird"} 1"#));
+    }
+
+    #[test]
+    fn metrics_replace_the_file_without_leaving_a_temp_file() {
+        let dir = TempDir::new("devmaker-test").unwrap();
+        let path = dir.path().join("devmaker.prom");
+        fs::write(&path, "stale").unwrap();
+        write_metrics(&path, &results()).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        assert!(written.starts_with("# HELP devmaker_job_duration_seconds"));
+        let files: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(files.len(), 1);
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::jobs::RunContext;
+
+#[derive(Serialize)]
+struct RunReport<'a> {
+    jobs: Vec<JobReport<'a>>,
+}
+
+#[derive(Serialize)]
+struct JobReport<'a> {
+    name: &'a str,
+    /// `ok`, `failed`, `tolerated` or `skipped`.
+    status: &'static str,
+    /// The env the job's runner was given, with secure values redacted.  Left out if the
+    /// runner never started.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    env: Option<BTreeMap<String, String>>,
+}
+
+/// Write a JSON report of the run, listing each scheduled job with its outcome and the
+/// variables it was given, so it's on record which jobs saw which secrets.
+pub(crate) fn write_report(path: &Path, context: &RunContext) -> Result<()> {
+    let report = RunReport {
+        jobs: context
+            .scheduled
+            .iter()
+            .map(|job| JobReport {
+                name: job,
+                status: context.status(job),
+                env: context.env_given(job),
+            })
+            .collect(),
+    };
+    debug!("Writing run report to {}", path.display());
+    fs::write(path, serde_json::to_string_pretty(&report)?)
+        .map_err(|e| anyhow!(format!("Cannot write report to {}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    use crate::common::EnvMap;
+    use crate::config::Config;
+    use crate::jobs::JobSpec;
+    use crate::settings::load_secure_patterns;
+    use crate::signals::running_jobs;
+    use crate::vars::fill_asked;
+
+    #[test]
+    fn reports_list_each_jobs_status_and_redacted_env() {
+        let _jobs = running_jobs();
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::write(
+            root.path().join("devmaker.toml"),
+            "secure_patterns = [\".*TOKEN.*\"]\n",
+        )
+        .unwrap();
+        fs::create_dir(root.path().join("deploy")).unwrap();
+        let info = serde_json::from_str(
+            r#"{"run": "true", "env": {"DEPLOY_TOKEN": "hunter2", "REGION": "eu-west-1"}}"#,
+        )
+        .unwrap();
+        let spec = JobSpec::new("deploy".to_owned(), info, false);
+        let patterns = load_secure_patterns(root.path()).unwrap();
+        let job = fill_asked(spec, &EnvMap::new(), "_SECURE", &patterns).unwrap();
+
+        let config = Config::for_test(root.path(), &[]);
+        let scheduled = ["deploy", "broken", "lint"]
+            .iter()
+            .map(|&name| name.to_owned());
+        let context = RunContext::new(scheduled.collect(), &config);
+        job.run(root.path(), &context).unwrap();
+        context.mark_completed("deploy");
+        let _: Result<()> = context.track("broken", Err(anyhow!("exit code 1")));
+
+        let path = root.path().join("report.json");
+        write_report(&path, &context).unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let jobs = report["jobs"].as_array().unwrap();
+        let statuses: Vec<_> = jobs
+            .iter()
+            .map(|job| {
+                (
+                    job["name"].as_str().unwrap(),
+                    job["status"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            statuses,
+            [("deploy", "ok"), ("broken", "failed"), ("lint", "skipped")]
+        );
+        assert_eq!(jobs[0]["env"]["DEPLOY_TOKEN"], "***");
+        assert_eq!(jobs[0]["env"]["REGION"], "eu-west-1");
+        assert!(jobs[1].get("env").is_none());
+        assert!(jobs[2].get("env").is_none());
+
+        let missing = root.path().join("no/such/dir/report.json");
+        let error = write_report(&missing, &context).unwrap_err().to_string();
+        assert!(error.starts_with("Cannot write report to "), "{}", error);
+    }
+}
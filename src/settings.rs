@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::common::SETTINGS_FILE;
+
+/// Settings for a whole job root, from the `devmaker.toml` at its top.
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct RootSettings {
+    /// Job names, breaking ties between jobs that are ready at the same time.  Jobs not listed
+    /// come after those that are.
+    #[serde(default)]
+    pub order: Vec<String>,
+    /// Regexes for env keys whose values are secure, like `.*TOKEN.*`, on top of those asked
+    /// for with the secure suffix.  Each must match the whole key.
+    #[serde(default)]
+    pub secure_patterns: Vec<String>,
+    /// Commands variables can be resolved with, by name, like
+    /// `vault = "vault kv get -field={field} {path}"`.  The `{placeholders}` are filled from the
+    /// variable's `ask` entry.
+    #[serde(default)]
+    pub resolvers: HashMap<String, String>,
+}
+
+/// The compiled `secure_patterns` of a root.
+#[derive(Debug, Default)]
+pub(crate) struct SecurePatterns(Vec<Regex>);
+
+impl SecurePatterns {
+    /// Whether an env key's value should be treated as secure, without a secure suffix.
+    pub fn is_secure(&self, key: &str) -> bool {
+        self.0.iter().any(|pattern| pattern.is_match(key))
+    }
+}
+
+impl RootSettings {
+    /// The jobs named in `order` that aren't among `known`.
+    pub fn unknown_ordered<'a>(&'a self, known: &[&String]) -> Vec<&'a String> {
+        self.order
+            .iter()
+            .filter(|name| !known.contains(name))
+            .collect()
+    }
+
+    pub fn secure_patterns(&self) -> Result<SecurePatterns> {
+        self.secure_patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(&format!("^(?:{pattern})$"))
+                    .map_err(|e| anyhow!(format!("Invalid secure pattern {:?}: {}", pattern, e)))
+            })
+            .collect::<Result<_>>()
+            .map(SecurePatterns)
+    }
+}
+
+/// Read and compile the root's `secure_patterns`.
+pub(crate) fn load_secure_patterns(root: &Path) -> Result<SecurePatterns> {
+    load_root_settings(root)?.secure_patterns()
+}
+
+/// Read the root's settings file, or the defaults if it has none.
+pub(crate) fn load_root_settings(root: &Path) -> Result<RootSettings> {
+    let path = root.join(SETTINGS_FILE);
+    if !path.is_file() {
+        return Ok(RootSettings::default());
+    }
+    debug!("Reading root settings: {}", path.display());
+    toml::from_str(&fs::read_to_string(&path)?)
+        .map_err(|e| anyhow!(format!("Cannot parse {}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    #[test]
+    fn secure_patterns_match_whole_keys() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::write(
+            root.path().join(SETTINGS_FILE),
+            "secure_patterns = [\".*TOKEN.*\", \"DB_PASS\"]\n",
+        )
+        .unwrap();
+        let patterns = load_secure_patterns(root.path()).unwrap();
+        assert!(patterns.is_secure("GITHUB_TOKEN"));
+        assert!(patterns.is_secure("TOKEN_FILE"));
+        assert!(patterns.is_secure("DB_PASS"));
+        assert!(!patterns.is_secure("DB_PASSWORD"));
+        assert!(!patterns.is_secure("github_token"));
+
+        let empty = TempDir::new("devmaker-test").unwrap();
+        assert!(!load_secure_patterns(empty.path())
+            .unwrap()
+            .is_secure("GITHUB_TOKEN"));
+    }
+
+    #[test]
+    fn invalid_secure_patterns_are_an_error() {
+        let settings = RootSettings {
+            secure_patterns: vec!["TOKEN(".to_owned()],
+            ..RootSettings::default()
+        };
+        let error = settings.secure_patterns().map(|_| ()).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Invalid secure pattern \"TOKEN(\": "));
+
+        let root = TempDir::new("devmaker-test").unwrap();
+        let path = root.path().join(SETTINGS_FILE);
+        fs::write(&path, "secure_patterns = \".*TOKEN.*\"\n").unwrap();
+        let error = load_root_settings(root.path()).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with(&format!("Cannot parse {}: ", path.display())));
+    }
+}
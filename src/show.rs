@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Error, Result};
+use serde::Serialize;
+
+use crate::common::{unknown_job, REDACTED};
+use crate::config::Config;
+use crate::info::{AskVar, ConditionalDep};
+use crate::jobs::{locate_runner, InlineRun, JobSpec, ReadyJob, RunContext, Runner};
+use crate::settings::load_secure_patterns;
+use crate::vars::{fill_asked, query};
+use crate::{load_specs, merge_env_from};
+
+#[derive(Serialize)]
+#[allow(clippy::struct_excessive_bools)] // Shows the JobSpec switches as they are.
+struct JobDetails<'a> {
+    name: &'a str,
+    description: Option<&'a str>,
+    depends: &'a [String],
+    conditional_depends: &'a [ConditionalDep],
+    tags: &'a [String],
+    /// Including the variables `from_var` env refers to.
+    ask_for_vars: Vec<AskVar>,
+    has_deps_script: bool,
+    has_undo_script: bool,
+    /// The `run.*` file, `script` or `runner`, or `None` for an inline `run` or `exec` command.
+    runner: Option<PathBuf>,
+    inline_run: Option<&'a InlineRun>,
+    exec: Option<&'a [String]>,
+    priority: i32,
+    disabled: bool,
+    always: bool,
+    allow_failure: bool,
+    capture_output_var: Option<&'a str>,
+    login_shell: bool,
+    when: Option<&'a InlineRun>,
+    verify_path: Option<&'a str>,
+    env_from: &'a [String],
+    /// In seconds.
+    timeout: Option<u64>,
+    cwd: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    env: Option<BTreeMap<String, String>>,
+}
+
+/// Print the fully resolved spec of a single job as JSON.
+///
+/// With `with_env`, the job's variables are resolved, and its env is included with secure
+/// values redacted.
+pub(crate) fn run_show(config: &Config, job: &str, with_env: bool) -> Result<()> {
+    let root = &config.root_dir;
+    let (scheduled, disabled) = load_specs(root, config)?;
+    let spec = scheduled
+        .iter()
+        .chain(&disabled)
+        .find(|spec| spec.name == job)
+        .ok_or_else(|| {
+            unknown_job(
+                job,
+                scheduled.iter().chain(&disabled).map(|spec| &spec.name),
+            )
+        })?;
+
+    let job_dir = root.join(&spec.dir);
+    let runner = match locate_runner(
+        &job_dir,
+        &spec.name,
+        spec.inline_run.as_ref(),
+        spec.script.as_deref(),
+        spec.runner.as_deref(),
+        spec.exec.as_deref(),
+    )? {
+        Runner::Script(path) => Some(path),
+        Runner::Inline(_) | Runner::Exec(_) => None,
+    };
+    let env = if with_env {
+        Some(resolve_env(config, spec, &scheduled)?)
+    } else {
+        None
+    };
+
+    let details = JobDetails {
+        name: &spec.name,
+        description: spec.description.as_deref(),
+        depends: &spec.depends,
+        conditional_depends: &spec.conditional_depends,
+        tags: &spec.tags,
+        ask_for_vars: spec.get_ask_vars(&config.secure_suffix),
+        has_deps_script: spec.has_deps_script,
+        has_undo_script: spec.has_undo_script,
+        runner,
+        inline_run: spec.inline_run.as_ref(),
+        exec: spec.exec.as_deref(),
+        priority: spec.priority,
+        disabled: spec.disabled,
+        always: spec.always,
+        allow_failure: spec.allow_failure,
+        capture_output_var: spec.capture_output_var.as_deref(),
+        login_shell: spec.login_shell,
+        when: spec.when.as_ref(),
+        verify_path: spec.verify_path.as_deref(),
+        env_from: &spec.env_from,
+        timeout: spec.timeout.map(|timeout| timeout.as_secs()),
+        cwd: spec.cwd.as_deref(),
+        env,
+    };
+    println!("{}", serde_json::to_string_pretty(&details)?);
+    Ok(())
+}
+
+/// Resolve the job's env, asking only for the variables of the job and those it takes env from.
+fn resolve_env(
+    config: &Config,
+    spec: &JobSpec,
+    scheduled: &[JobSpec],
+) -> Result<BTreeMap<String, String>> {
+    let mut needed: HashSet<&String> = HashSet::new();
+    needed.insert(&spec.name);
+    let mut pending: Vec<&String> = spec.env_from.iter().collect();
+    while let Some(name) = pending.pop() {
+        if needed.insert(name) {
+            if let Some(base) = scheduled.iter().find(|s| &s.name == name) {
+                pending.extend(&base.env_from);
+            }
+        }
+    }
+    // Disabled jobs aren't scheduled, but can still be shown on their own.
+    let specs: Vec<JobSpec> = if scheduled.iter().any(|s| s.name == spec.name) {
+        scheduled
+            .iter()
+            .filter(|s| needed.contains(&s.name))
+            .cloned()
+            .collect()
+    } else {
+        vec![spec.clone()]
+    };
+
+    let patterns = load_secure_patterns(&config.root_dir)?;
+    let answers = query(&specs, config)?;
+    let mut queue: Vec<ReadyJob> = specs
+        .into_iter()
+        .map(|spec| fill_asked(spec, &answers, &config.secure_suffix, &patterns))
+        .collect::<Result<Vec<ReadyJob>, Error>>()?;
+    merge_env_from(&mut queue);
+    let job = queue
+        .iter()
+        .find(|job| job.name() == &spec.name)
+        .ok_or_else(|| anyhow!(format!("Cannot locate job: {}", spec.name)))?;
+
+    let context = RunContext::new(vec![spec.name.clone()], config);
+    let env = job.create_proc_env(&config.root_dir, &context)?;
+    Ok(env
+        .into_iter()
+        .map(|(key, value)| {
+            if job.secure_vars().contains(&key) {
+                (key, REDACTED.to_owned())
+            } else {
+                (key, value)
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,257 @@
+use std::convert::TryFrom;
+use std::os::unix::process::CommandExt;
+use std::process::{self, Child, Command, ExitStatus};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+#[cfg(test)]
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+
+/// Process groups of the jobs currently running.  More than one with `--parallel-deps`.
+static ACTIVE_GROUPS: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+
+/// The last termination signal we received, or 0.
+static INTERRUPTED: AtomicI32 = AtomicI32::new(0);
+
+/// Held for reading by tests while they run jobs, and for writing by tests that interrupt
+/// them, since an interruption reaches every job in the process.
+#[cfg(test)]
+static RUNNING_JOBS: RwLock<()> = RwLock::new(());
+
+/// How often to check on a job that has a time limit.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a job may run, and how to stop it once it's over.
+pub(crate) struct TimeLimit {
+    pub after: Duration,
+    /// Sent to the job's process group first, to give it a chance to clean up.
+    pub signal: i32,
+    /// How long to wait after `signal` before sending SIGKILL.
+    pub grace: Duration,
+}
+
+/// Forward SIGINT, SIGTERM and SIGHUP to the process groups of running jobs.
+///
+/// While a job runs, we wait for it to exit so its temp dir is cleaned up, and
+/// `run_in_own_group` reports the interruption.  With no job running, we exit right away, which
+/// releases the run lock too.
+pub(crate) fn install_forwarding() -> Result<()> {
+    let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP])?;
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            if !forward(signal) {
+                process::exit(128 + signal);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Record the signal and send it on to every running job's group, returning whether there were
+/// any.
+fn forward(signal: i32) -> bool {
+    INTERRUPTED.store(signal, Ordering::SeqCst);
+    let groups = lock_groups();
+    for group in groups.iter() {
+        debug!("Forwarding signal {signal} to process group {group}");
+        unsafe { libc::kill(-group, signal) };
+    }
+    !groups.is_empty()
+}
+
+/// The signal that interrupted us, if any.
+pub(crate) fn interrupted() -> Option<i32> {
+    match INTERRUPTED.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+/// Run `command` to completion in a new process group, so a signal reaches all of its children.
+///
+/// With `foreground`, if we're in the terminal's foreground, the job gets the foreground for as
+/// long as it runs, so it can still read from the terminal and receives Ctrl-C directly.  Only
+/// one job can have it, so that's for jobs run one at a time.
+///
+/// Returns `None` if the job went over its time limit and was stopped.
+pub(crate) fn run_in_own_group(
+    command: &mut Command,
+    limit: Option<&TimeLimit>,
+    foreground: bool,
+) -> Result<Option<ExitStatus>> {
+    let take_terminal = foreground && owns_terminal();
+    command.process_group(0);
+    if take_terminal {
+        // The child claims the terminal too, in case it reads from it before we get to.
+        unsafe {
+            command.pre_exec(|| {
+                claim_terminal(libc::getpgrp());
+                Ok(())
+            });
+        }
+    }
+    let mut child = command.spawn()?;
+    let group = i32::try_from(child.id())?;
+    if take_terminal {
+        claim_terminal(group);
+    }
+    lock_groups().push(group);
+    let status = match limit {
+        Some(limit) => wait_with_limit(&mut child, group, limit),
+        None => child.wait().map(Some).map_err(Into::into),
+    };
+    lock_groups().retain(|active| *active != group);
+    if take_terminal {
+        claim_terminal(unsafe { libc::getpgrp() });
+    }
+    status
+}
+
+/// Wait for the child, stopping its group if it runs past the limit: first with the limit's
+/// signal, then with SIGKILL once the grace period is up.
+fn wait_with_limit(child: &mut Child, group: i32, limit: &TimeLimit) -> Result<Option<ExitStatus>> {
+    if let Some(status) = wait_until(child, Instant::now() + limit.after)? {
+        return Ok(Some(status));
+    }
+    debug!(
+        "Time limit reached, sending signal {} to process group {}",
+        limit.signal, group
+    );
+    unsafe { libc::kill(-group, limit.signal) };
+    if wait_until(child, Instant::now() + limit.grace)?.is_none() {
+        debug!("Grace period over, killing process group {group}");
+        unsafe { libc::kill(-group, libc::SIGKILL) };
+        child.wait()?;
+    }
+    Ok(None)
+}
+
+/// Wait for the child to exit, giving up at the deadline.
+fn wait_until(child: &mut Child, deadline: Instant) -> Result<Option<ExitStatus>> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Keep tests that interrupt jobs from running until the caller is done running its own.
+#[cfg(test)]
+pub(crate) fn running_jobs() -> RwLockReadGuard<'static, ()> {
+    RUNNING_JOBS.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Wait for the tests running jobs to finish, and keep others from starting, for a test that
+/// interrupts jobs or releases the run lock on the way out.
+#[cfg(test)]
+pub(crate) fn no_running_jobs() -> RwLockWriteGuard<'static, ()> {
+    RUNNING_JOBS.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn lock_groups() -> MutexGuard<'static, Vec<i32>> {
+    ACTIVE_GROUPS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn owns_terminal() -> bool {
+    unsafe {
+        libc::isatty(libc::STDIN_FILENO) == 1
+            && libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp()
+    }
+}
+
+/// Make `group` the terminal's foreground process group.
+///
+/// Doing so from the background raises SIGTTOU, so that's ignored for the duration.
+fn claim_terminal(group: i32) {
+    unsafe {
+        let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+        libc::tcsetpgrp(libc::STDIN_FILENO, group);
+        libc::signal(libc::SIGTTOU, previous);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn interruptions_are_forwarded_to_running_jobs() {
+        let _alone = no_running_jobs();
+        let interrupter = thread::spawn(|| {
+            while lock_groups().is_empty() {
+                thread::sleep(POLL_INTERVAL);
+            }
+            assert!(forward(SIGTERM));
+        });
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("sleep 30 & wait");
+        let status = run_in_own_group(&mut command, None, false).unwrap();
+        interrupter.join().unwrap();
+
+        assert_eq!(status.and_then(|status| status.signal()), Some(SIGTERM));
+        assert_eq!(interrupted(), Some(SIGTERM));
+        assert!(lock_groups().is_empty());
+        INTERRUPTED.store(0, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn nothing_to_forward_to_without_running_jobs() {
+        let _alone = no_running_jobs();
+        assert!(!forward(SIGHUP));
+        INTERRUPTED.store(0, Ordering::SeqCst);
+    }
+
+    fn limit(grace: Duration) -> TimeLimit {
+        TimeLimit {
+            after: Duration::from_millis(100),
+            signal: SIGTERM,
+            grace,
+        }
+    }
+
+    #[test]
+    fn jobs_over_time_get_to_clean_up_during_the_grace_period() {
+        let _jobs = running_jobs();
+        let dir = tempdir::TempDir::new("devmaker-test").unwrap();
+        let marker = dir.path().join("cleaned");
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(format!(
+                "trap 'touch {}; exit 0' TERM; while :; do sleep 0.05; done",
+                marker.display()
+            ))
+            // The shell reports its `sleep` being terminated.
+            .stderr(process::Stdio::null());
+        let started = Instant::now();
+        let status = run_in_own_group(&mut command, Some(&limit(Duration::from_secs(10))), false);
+        assert!(status.unwrap().is_none());
+        assert!(marker.is_file());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn jobs_ignoring_the_signal_are_killed_after_the_grace_period() {
+        let _jobs = running_jobs();
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg("trap '' TERM; while :; do sleep 0.05; done");
+        let started = Instant::now();
+        let limit = limit(Duration::from_millis(200));
+        let status = run_in_own_group(&mut command, Some(&limit), false);
+        assert!(status.unwrap().is_none());
+        assert!(started.elapsed() >= Duration::from_millis(300));
+    }
+}
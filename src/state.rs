@@ -0,0 +1,299 @@
+use std::convert::TryFrom;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, prelude::*, SeekFrom};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::ptr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Default)]
+struct SavedState {
+    root: PathBuf,
+    completed: Vec<String>,
+}
+
+/// The jobs completed by the latest run of a root, saved as they finish.
+///
+/// `--continue-from-last` picks this up to skip them; any other run starts it afresh.
+pub(crate) struct RunState {
+    /// `None` for a run that isn't recorded.
+    path: Option<PathBuf>,
+    saved: SavedState,
+}
+
+impl RunState {
+    /// Start recording a run of `root` in `state_dir`, keeping what the last run completed if
+    /// `resume` is set.
+    pub fn start(state_dir: &Path, root: &Path, resume: bool) -> Result<Self> {
+        let path = state_path(state_dir, root);
+        let saved = if resume && path.is_file() {
+            debug!("Reading run state: {}", path.display());
+            serde_json::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| anyhow!(format!("Cannot read run state {}: {}", path.display(), e)))?
+        } else {
+            SavedState {
+                root: root.to_owned(),
+                completed: Vec::new(),
+            }
+        };
+        let state = Self {
+            path: Some(path),
+            saved,
+        };
+        state.save()?;
+        Ok(state)
+    }
+
+    /// Track a run of `root` without recording it, leaving the last real run's state alone.
+    pub fn unrecorded(root: &Path) -> Self {
+        Self {
+            path: None,
+            saved: SavedState {
+                root: root.to_owned(),
+                completed: Vec::new(),
+            },
+        }
+    }
+
+    /// Whether the job was completed by the run being continued.
+    pub fn completed(&self, job: &str) -> bool {
+        self.saved.completed.iter().any(|done| done == job)
+    }
+
+    pub fn record(&mut self, job: &str) -> Result<()> {
+        if !self.completed(job) {
+            self.saved.completed.push(job.to_owned());
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&self.saved)?)?;
+        Ok(())
+    }
+}
+
+/// Who holds a run lock, as written to the lock file.
+#[derive(Deserialize, Serialize)]
+struct LockOwner {
+    pid: u32,
+    /// Seconds since the epoch.
+    since: u64,
+}
+
+/// Held for the length of a run, so two runs of the same root can't overlap.  Released when
+/// dropped.
+///
+/// It's an `flock` on a lock file next to the root's state file, so the system releases it
+/// however the process exits, and the file itself is never removed.  The file records who took
+/// the lock, and is emptied on release, so an owner found in it by the next run is one that
+/// crashed.
+pub(crate) struct RunLock {
+    path: PathBuf,
+    file: File,
+}
+
+impl RunLock {
+    pub fn acquire(state_dir: &Path, root: &Path) -> Result<Self> {
+        let path = state_path(state_dir, root).with_extension("lock");
+        fs::create_dir_all(state_dir)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| anyhow!(format!("Cannot open run lock {}: {}", path.display(), e)))?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::WouldBlock {
+                return Err(anyhow!(format!("Cannot lock {}: {}", path.display(), e)));
+            }
+            // The owner may not have written itself in yet.
+            let held = read_owner(&mut file).map_or_else(
+                || format!("lock held on {}", path.display()),
+                |owner| {
+                    format!(
+                        "lock held since {} by PID {}",
+                        format_time(owner.since),
+                        owner.pid
+                    )
+                },
+            );
+            return Err(anyhow!(format!(
+                "Another devmaker run is in progress ({held}).  If it's stuck, stop it or pass \
+                 --no-lock"
+            )));
+        }
+        if let Some(crashed) = read_owner(&mut file) {
+            warn!(
+                "Taking over run lock left behind by PID {}: {}",
+                crashed.pid,
+                path.display()
+            );
+        }
+        let owner = LockOwner {
+            pid: process::id(),
+            since: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        };
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(serde_json::to_string(&owner)?.as_bytes())?;
+        debug!("Took run lock: {}", path.display());
+        Ok(Self { path, file })
+    }
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        debug!("Releasing run lock: {}", self.path.display());
+        // Emptied while it's still ours; closing the file then releases the lock.
+        if let Err(e) = self.file.set_len(0) {
+            warn!("Cannot clear run lock {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+fn read_owner(file: &mut File) -> Option<LockOwner> {
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Format seconds since the epoch as local time, like `2024-05-01 13:45:00`.
+fn format_time(secs: u64) -> String {
+    let Ok(time) = libc::time_t::try_from(secs) else {
+        return secs.to_string();
+    };
+    let mut buf = [0_u8; 32];
+    let written = unsafe {
+        let mut tm = std::mem::zeroed::<libc::tm>();
+        if libc::localtime_r(ptr::addr_of!(time), ptr::addr_of_mut!(tm)).is_null() {
+            return secs.to_string();
+        }
+        libc::strftime(
+            buf.as_mut_ptr().cast(),
+            buf.len(),
+            b"%Y-%m-%d %H:%M:%S\0".as_ptr().cast(),
+            ptr::addr_of!(tm),
+        )
+    };
+    String::from_utf8_lossy(&buf[..written]).into_owned()
+}
+
+/// Forget what earlier runs of `root` completed.
+pub(crate) fn reset_state(state_dir: &Path, root: &Path) -> Result<()> {
+    let path = state_path(state_dir, root);
+    if path.is_file() {
+        fs::remove_file(&path)?;
+        println!("Cleared run state for {}", root.display());
+    } else {
+        println!("No run state for {}", root.display());
+    }
+    Ok(())
+}
+
+/// The state file for a root in `state_dir`.
+///
+/// It's named after the root's path, with `%` and `/` escaped so different roots can't collide.
+fn state_path(state_dir: &Path, root: &Path) -> PathBuf {
+    let name = root
+        .to_string_lossy()
+        .replace('%', "%25")
+        .replace('/', "%2F");
+    state_dir.join(format!("{name}.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    use crate::common::capture_logs;
+
+    #[test]
+    fn runs_are_recorded_in_the_state_dir() {
+        let state = TempDir::new("devmaker-state").unwrap();
+        let root = TempDir::new("devmaker-test").unwrap();
+        let mut run = RunState::start(state.path(), root.path(), false).unwrap();
+        run.record("a").unwrap();
+        assert!(state_path(state.path(), root.path()).is_file());
+
+        let resumed = RunState::start(state.path(), root.path(), true).unwrap();
+        assert!(resumed.completed("a"));
+        reset_state(state.path(), root.path()).unwrap();
+        let resumed = RunState::start(state.path(), root.path(), true).unwrap();
+        assert!(!resumed.completed("a"));
+    }
+
+    #[test]
+    fn the_lock_is_released_when_dropped() {
+        let state = TempDir::new("devmaker-state").unwrap();
+        let root = TempDir::new("devmaker-test").unwrap();
+        let lock = RunLock::acquire(state.path(), root.path()).unwrap();
+        let path = lock.path.clone();
+        drop(lock);
+        // The file stays, empty, for the next run to lock.
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        let logged = capture_logs(|| drop(RunLock::acquire(state.path(), root.path()).unwrap()));
+        assert!(!logged.iter().any(|line| line.starts_with("WARN")));
+    }
+
+    #[test]
+    fn a_running_owner_keeps_the_lock() {
+        let state = TempDir::new("devmaker-state").unwrap();
+        let root = TempDir::new("devmaker-test").unwrap();
+        let lock = RunLock::acquire(state.path(), root.path()).unwrap();
+        let error = RunLock::acquire(state.path(), root.path())
+            .map(|_| ())
+            .unwrap_err();
+        let error = error.to_string();
+        assert!(error.starts_with("Another devmaker run is in progress (lock held since "));
+        assert!(
+            error.ends_with(&format!(
+                " by PID {}).  If it's stuck, stop it or pass --no-lock",
+                process::id()
+            )),
+            "{}",
+            error
+        );
+        drop(lock);
+        RunLock::acquire(state.path(), root.path()).unwrap();
+    }
+
+    #[test]
+    fn locks_left_by_a_crash_are_taken_over() {
+        let state = TempDir::new("devmaker-state").unwrap();
+        let root = TempDir::new("devmaker-test").unwrap();
+        let path = state_path(state.path(), root.path()).with_extension("lock");
+        // Not a PID any process can have.
+        let crashed = LockOwner {
+            pid: u32::MAX,
+            since: 0,
+        };
+        fs::write(&path, serde_json::to_string(&crashed).unwrap()).unwrap();
+        let mut lock = None;
+        let logged =
+            capture_logs(|| lock = Some(RunLock::acquire(state.path(), root.path()).unwrap()));
+        assert!(logged.contains(&format!(
+            "WARN: Taking over run lock left behind by PID {}: {}",
+            u32::MAX,
+            path.display()
+        )));
+        let owner = read_owner(&mut File::open(&path).unwrap()).unwrap();
+        assert_eq!(owner.pid, process::id());
+        drop(lock);
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+use crate::jobs::JobSpec;
+
+/// Render the dependency graph as an indented tree, like `cargo tree`.
+///
+/// Jobs nothing depends on are the roots, with each job's dependencies beneath it.  A job
+/// already shown elsewhere is marked with `(*)` instead of being expanded again.
+pub(crate) fn render_tree(specs: &[JobSpec]) -> String {
+    let depended_on: HashSet<&String> = specs.iter().flat_map(|spec| &spec.depends).collect();
+    let mut roots: Vec<&JobSpec> = specs
+        .iter()
+        .filter(|spec| !depended_on.contains(&spec.name))
+        .collect();
+    roots.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut shown = HashSet::new();
+    let mut lines = Vec::new();
+    for root in roots {
+        render_node(specs, &root.name, "", "", &mut shown, &mut lines);
+    }
+    lines.join("\n")
+}
+
+/// Group the scheduled jobs into levels, where each job's dependencies are all in earlier
+/// levels, so every job in a level can run in parallel.
+///
+/// Jobs keep their schedule order within a level.
+pub(crate) fn dependency_levels(specs: &[JobSpec]) -> Vec<Vec<&str>> {
+    let mut level_of: HashMap<&String, usize> = HashMap::new();
+    let mut levels: Vec<Vec<&str>> = Vec::new();
+    // Scheduled jobs come after their dependencies, so theirs are already known.
+    for spec in specs {
+        let level = spec
+            .depends
+            .iter()
+            .filter_map(|dep| level_of.get(dep))
+            .map(|level| level + 1)
+            .max()
+            .unwrap_or(0);
+        level_of.insert(&spec.name, level);
+        if levels.len() <= level {
+            levels.resize_with(level + 1, Vec::new);
+        }
+        levels[level].push(&spec.name);
+    }
+    levels
+}
+
+/// Explain where each scheduled job was placed, in schedule order: its level from
+/// `dependency_levels`, the jobs it had to wait for, and the hints that order it among the jobs
+/// ready at the same time, like `app (wave 1, after: db; priority 0)`.
+pub(crate) fn explain_schedule(specs: &[JobSpec], order: &[String]) -> Vec<String> {
+    let wave_of: HashMap<&str, usize> = dependency_levels(specs)
+        .into_iter()
+        .enumerate()
+        .flat_map(|(wave, names)| names.into_iter().map(move |name| (name, wave)))
+        .collect();
+    specs
+        .iter()
+        .enumerate()
+        .map(|(position, spec)| {
+            let mut depends: Vec<&str> = spec.depends.iter().map(String::as_str).collect();
+            depends.sort_unstable();
+            let after = if depends.is_empty() {
+                "no deps".to_owned()
+            } else {
+                format!("after: {}", depends.join(", "))
+            };
+            let mut line = format!(
+                "{}. {} (wave {}, {}; priority {}",
+                position + 1,
+                spec.name,
+                wave_of.get(spec.name.as_str()).copied().unwrap_or_default(),
+                after,
+                spec.priority
+            );
+            if let Some(rank) = order.iter().position(|name| name == &spec.name) {
+                let _ = write!(line, "; order {}", rank + 1);
+            }
+            line.push(')');
+            line
+        })
+        .collect()
+}
+
+fn render_node<'a>(
+    specs: &'a [JobSpec],
+    name: &'a String,
+    branch: &str,
+    indent: &str,
+    shown: &mut HashSet<&'a String>,
+    lines: &mut Vec<String>,
+) {
+    let spec = specs.iter().find(|spec| &spec.name == name);
+    let expandable = spec.is_some_and(|spec| !spec.depends.is_empty());
+    if !shown.insert(name) && expandable {
+        lines.push(format!("{indent}{branch}{name} (*)"));
+        return;
+    }
+    lines.push(format!("{indent}{branch}{name}"));
+
+    let mut depends: Vec<&String> = spec
+        .map(|spec| spec.depends.iter().collect())
+        .unwrap_or_default();
+    depends.sort();
+    let child_indent = match branch {
+        "" => indent.to_owned(),
+        "└── " => format!("{indent}    "),
+        _ => format!("{indent}│   "),
+    };
+    let last = depends.len().saturating_sub(1);
+    for (position, dep) in depends.into_iter().enumerate() {
+        let child_branch = if position == last {
+            "└── "
+        } else {
+            "├── "
+        };
+        render_node(specs, dep, child_branch, &child_indent, shown, lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(name: &str, depends: &[&str]) -> JobSpec {
+        let info = serde_json::json!({ "depends": depends });
+        JobSpec::new(
+            name.to_owned(),
+            serde_json::from_value(info).unwrap(),
+            false,
+        )
+    }
+
+    #[test]
+    fn chain_renders_one_level_deeper_each_step() {
+        let specs = [spec("a", &[]), spec("b", &["a"]), spec("c", &["b"])];
+        assert_eq!(render_tree(&specs), "c\n└── b\n    └── a");
+    }
+
+    #[test]
+    fn diamond_expands_a_shared_dependency_once() {
+        let specs = [
+            spec("core", &[]),
+            spec("base", &["core"]),
+            spec("left", &["base"]),
+            spec("right", &["base"]),
+            spec("app", &["right", "left"]),
+        ];
+        let expected = [
+            "app",
+            "├── left",
+            "│   └── base",
+            "│       └── core",
+            "└── right",
+            "    └── base (*)",
+        ];
+        assert_eq!(render_tree(&specs), expected.join("\n"));
+    }
+
+    #[test]
+    fn diamond_levels_put_each_job_after_its_deepest_dependency() {
+        let specs = [
+            spec("core", &[]),
+            spec("left", &["core"]),
+            spec("right", &["core"]),
+            spec("tools", &[]),
+            spec("app", &["left", "right", "core"]),
+        ];
+        let levels = dependency_levels(&specs);
+        assert_eq!(
+            levels,
+            vec![vec!["core", "tools"], vec!["left", "right"], vec!["app"]]
+        );
+        assert!(dependency_levels(&[]).is_empty());
+    }
+
+    #[test]
+    fn schedule_explanation_names_each_jobs_dependencies() {
+        let mut urgent = spec("urgent", &[]);
+        urgent.priority = 5;
+        let specs = [
+            urgent,
+            spec("db", &[]),
+            spec("cache", &[]),
+            spec("app", &["db", "cache"]),
+            spec("docs", &["app"]),
+        ];
+        let order = ["db".to_owned()];
+        assert_eq!(
+            explain_schedule(&specs, &order),
+            [
+                "1. urgent (wave 0, no deps; priority 5)",
+                "2. db (wave 0, no deps; priority 0; order 1)",
+                "3. cache (wave 0, no deps; priority 0)",
+                "4. app (wave 1, after: cache, db; priority 0)",
+                "5. docs (wave 2, after: app; priority 0)",
+            ]
+        );
+    }
+}
@@ -1,60 +1,458 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::RawFd;
+use std::path::Path;
+use std::process::{self, Stdio};
+use std::result::Result as StdResult;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
 use dialoguer::{Input, PasswordInput};
+use regex::{Captures, Regex};
 
-use crate::common::{secure_name_check, EnvMap};
-use crate::config::Config;
+use crate::common::{secure_name_check, EnvMap, DEFAULT_SHELL, REDACTED, SETTINGS_FILE};
+use crate::config::{try_parse_var_string, Config};
+use crate::info::{AskVar, VarSource};
 use crate::jobs::{JobSpec, ReadyJob};
+use crate::keyring::check_keyring;
+use crate::load_specs;
+use crate::settings::{load_root_settings, load_secure_patterns, SecurePatterns};
 
 #[inline]
-fn encode_key(key: &str) -> String {
-    key.to_uppercase().replace("-", "_").replace(" ", "_")
+pub(crate) fn encode_key(key: &str) -> String {
+    key.to_uppercase().replace(['-', ' '], "_")
 }
 
-fn query_single_var(name: &str, config: &Config) -> Result<(String, String)> {
-    let (runnable_name, is_secure) = secure_name_check(name);
+/// Merge two env maps, with `overlay`'s values winning.
+///
+/// Every key is normalized with `encode_key` first, so `my-key` in one map and `MY_KEY` in
+/// the other are the same variable.  Whatever combines env maps goes through here, layering
+/// from lowest to highest precedence.
+pub(crate) fn merge_env<V>(
+    base: HashMap<String, V>,
+    overlay: HashMap<String, V>,
+) -> HashMap<String, V> {
+    let mut merged = HashMap::with_capacity(base.len() + overlay.len());
+    for (key, value) in base.into_iter().chain(overlay) {
+        merged.insert(encode_key(&key), value);
+    }
+    merged
+}
 
-    debug!("Querying var: {}", runnable_name);
+/// Resolve a variable, also returning the source it came from.
+///
+/// The source is `None` for fixed values, `--force-empty-vars`, and `default_empty`.  A value
+/// of the wrong length is an error, unless it was just entered, in which case it's asked again.
+fn query_single_var(var: &AskVar, config: &Config) -> Result<(String, String, Option<VarSource>)> {
+    checked_length(var, || resolve_single_var(var, config))
+}
 
-    let value = try_empty_var(&runnable_name, config)
-        .or_else(|| try_var_from_cmd(&runnable_name, config))
-        .or_else(|| try_var_from_env(&runnable_name, config))
-        .or_else(|| try_var_from_askfile(&runnable_name, config))
-        .or_else(|| try_ask_user_for_var(&runnable_name, config, is_secure))
-        .ok_or(anyhow!(format!("Cound not resolve var: {}", runnable_name)))?;
+/// Resolve with `resolve` until the value has the right length, if it was entered at a prompt.
+fn checked_length<F>(var: &AskVar, mut resolve: F) -> Result<(String, String, Option<VarSource>)>
+where
+    F: FnMut() -> Result<(String, String, Option<VarSource>)>,
+{
+    loop {
+        let (name, value, source) = resolve()?;
+        match var.check_length(&value) {
+            Ok(()) => return Ok((name, value, source)),
+            Err(e) if source == Some(VarSource::Prompt) => eprintln!("{e}"),
+            Err(e) => return Err(e),
+        }
+    }
+}
 
-    Ok((runnable_name, value))
+fn resolve_single_var(
+    var: &AskVar,
+    config: &Config,
+) -> Result<(String, String, Option<VarSource>)> {
+    resolve_with_prompt(var, config, try_ask_user_for_var)
 }
 
-fn try_ask_user_for_var(name: &str, config: &Config, secure: bool) -> Option<String> {
+/// Resolve a variable, asking with `prompt` when it comes to that, like `try_ask_user_for_var`.
+fn resolve_with_prompt<F>(
+    var: &AskVar,
+    config: &Config,
+    prompt: F,
+) -> Result<(String, String, Option<VarSource>)>
+where
+    F: FnOnce(&AskVar, &str, &Config, bool) -> Result<Option<String>>,
+{
+    let (runnable_name, is_secure) = secure_name_check(var.name.as_str(), &config.secure_suffix);
+
+    debug!("Querying var: {runnable_name}");
+    if let Some(value) = &var.value {
+        debug!("Using fixed value for var: {runnable_name}");
+        let value = transform_var(var, &runnable_name, value.clone())?;
+        return Ok((runnable_name, value, None));
+    }
+
+    let reask = config.reask.contains(&runnable_name);
+    if reask && !var.allows(VarSource::Prompt) {
+        return Err(anyhow!(format!(
+            "Cannot re-ask var {}: its sources don't include prompt",
+            runnable_name
+        )));
+    }
+    let resolved = if reask {
+        debug!("Re-asking var: {runnable_name}");
+        None
+    } else {
+        try_empty_var(&runnable_name, config)
+            .map(|value| (value, None))
+            .or_else(|| {
+                try_source(var, VarSource::Command, || {
+                    try_var_from_cmd(&runnable_name, config)
+                })
+            })
+            .or_else(|| {
+                try_source(var, VarSource::Env, || {
+                    try_var_from_env(&runnable_name, config)
+                })
+            })
+            .or_else(|| {
+                try_source(var, VarSource::Askfile, || {
+                    try_var_from_askfile(&runnable_name, config)
+                })
+            })
+            .or_else(|| {
+                try_source(var, VarSource::Keyring, || {
+                    try_var_from_keyring(var, config)
+                })
+            })
+    };
+    let resolved = match resolved {
+        None if !reask && var.allows(VarSource::Resolver) => {
+            try_var_from_resolver(var, &runnable_name, config)?
+                .map(|value| (value, Some(VarSource::Resolver)))
+        }
+        resolved => resolved,
+    };
+    let resolved = match resolved {
+        Some((saved, Some(VarSource::Askfile)))
+            if config.only_changed_vars && var.allows(VarSource::Prompt) =>
+        {
+            Some(prompt_to_change(&runnable_name, saved, is_secure)?)
+        }
+        Some(resolved) => Some(resolved),
+        None if var.allows(VarSource::Prompt) => prompt(var, &runnable_name, config, is_secure)?
+            .map(|value| (value, Some(VarSource::Prompt))),
+        None => None,
+    };
+    let (value, source) = resolved
+        .or_else(|| try_default_empty(&runnable_name, var).map(|value| (value, None)))
+        .ok_or_else(|| anyhow!(format!("Cound not resolve var: {}", runnable_name)))?;
+    // Given without the suffix, so the check on parsing `--with-vars` couldn't tell.
+    if is_secure && source == Some(VarSource::Command) && config.forbid_cli_secrets {
+        return Err(anyhow!(format!(
+            "Secure variable {} can't be given with --with-vars",
+            var.name
+        )));
+    }
+    let value = transform_var(var, &runnable_name, value)?;
+
+    Ok((runnable_name, value, source))
+}
+
+/// Apply the var's transforms, if any.  The value is left out of errors, as it may be secure.
+fn transform_var(var: &AskVar, name: &str, value: String) -> Result<String> {
+    match &var.transform {
+        Some(transforms) => transforms
+            .apply(value)
+            .map_err(|e| anyhow!(format!("Cannot transform var {}: {}", name, e))),
+        None => Ok(value),
+    }
+}
+
+fn try_ask_user_for_var(
+    var: &AskVar,
+    name: &str,
+    config: &Config,
+    secure: bool,
+) -> Result<Option<String>> {
     if !config.interactive {
-        return None;
+        return Ok(None);
+    }
+
+    debug!("Interactive query: {name}");
+
+    config.prompt_timeout.map_or_else(
+        || Ok(prompt_for_var(name, secure).ok()),
+        |timeout| prompt_with_timeout(var, name, secure, timeout, libc::STDIN_FILENO),
+    )
+}
+
+/// Prompt on `input` without dialoguer, which can't stop waiting once it has started.
+fn prompt_with_timeout(
+    var: &AskVar,
+    name: &str,
+    secure: bool,
+    timeout: Duration,
+    input: RawFd,
+) -> Result<Option<String>> {
+    eprint!("{}: ", prompt_text(name, secure));
+    let answer = {
+        let _echo = if secure { EchoOff::new(input) } else { None };
+        read_line_within(input, Instant::now() + timeout)
     };
+    // Echo was off, so the user's Enter didn't end the line, and on timeout nothing did.
+    if secure || matches!(answer, Ok(None)) {
+        eprintln!();
+    }
+    match answer {
+        Ok(Some(value)) => Ok(Some(value)),
+        Ok(None) if var.default_empty => {
+            warn!("Timed out waiting for input for {name}, using default");
+            Ok(None)
+        }
+        Ok(None) => Err(anyhow!(format!("Timed out waiting for input for {}", name))),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Read a line from `input`, or `None` if none is finished by `deadline`.  A partial line left
+/// on a terminal is discarded, so it isn't read by whatever runs next.
+fn read_line_within(input: RawFd, deadline: Instant) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let mut poll = libc::pollfd {
+            fd: input,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = i32::try_from(left.as_millis()).unwrap_or(i32::MAX);
+        match unsafe { libc::poll(std::ptr::addr_of_mut!(poll), 1, millis) } {
+            0 => {
+                unsafe {
+                    if libc::isatty(input) == 1 {
+                        libc::tcflush(input, libc::TCIFLUSH);
+                    }
+                }
+                return Ok(None);
+            }
+            ready if ready < 0 => {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        // A byte at a time, so nothing past the line is taken from whoever reads next.
+        let mut byte = 0_u8;
+        match unsafe { libc::read(input, std::ptr::addr_of_mut!(byte).cast(), 1) } {
+            0 if line.is_empty() => return Err(io::ErrorKind::UnexpectedEof.into()),
+            0 => break,
+            read if read < 0 => {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+            _ if byte == b'\n' => break,
+            _ => line.push(byte),
+        }
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Turns off echo on a terminal for a secure prompt, and restores it when dropped, so a prompt
+/// that times out or fails doesn't leave the terminal silent.
+struct EchoOff {
+    input: RawFd,
+    saved: libc::termios,
+}
+
+impl EchoOff {
+    fn new(input: RawFd) -> Option<Self> {
+        unsafe {
+            if libc::isatty(input) != 1 {
+                return None;
+            }
+            let mut saved: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(input, std::ptr::addr_of_mut!(saved)) != 0 {
+                return None;
+            }
+            let mut quiet = saved;
+            quiet.c_lflag &= !libc::ECHO;
+            libc::tcsetattr(input, libc::TCSANOW, std::ptr::addr_of!(quiet));
+            Some(Self { input, saved })
+        }
+    }
+}
 
-    debug!("Interactive query: {}", name);
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.input, libc::TCSANOW, std::ptr::addr_of!(self.saved)) };
+    }
+}
 
+fn prompt_text(name: &str, secure: bool) -> String {
     let message = "Please enter the value for the variable";
+    if secure {
+        format!("<Secure> {message} [{name}]")
+    } else {
+        format!("{message}, [{name}]")
+    }
+}
 
+fn prompt_for_var(name: &str, secure: bool) -> io::Result<String> {
+    let prompt = prompt_text(name, secure);
     if secure {
-        let prompt = format!("<Secure> {} [{}]", message, name);
         PasswordInput::new()
             .with_prompt(&prompt)
             .allow_empty_password(true)
             .interact()
     } else {
-        let prompt = format!("{}, [{}]", message, name);
         Input::new()
             .with_prompt(&prompt)
             .allow_empty(true)
             .interact()
     }
-    .ok()
+}
+
+/// Prompt for a variable's value and store it in the keyring entry its `ask` entry names.
+pub(crate) fn store_in_keyring(config: &Config, name: &str) -> Result<()> {
+    check_keyring()?;
+    let (scheduled, disabled) = load_specs(&config.root_dir, config)?;
+    let suffix = config.secure_suffix.as_str();
+    let var = scheduled
+        .iter()
+        .chain(&disabled)
+        .flat_map(|spec| spec.get_ask_vars(suffix))
+        .find(|var| var.name == name || secure_name_check(var.name.as_str(), suffix).0 == name)
+        .ok_or_else(|| anyhow!(format!("No job asks for variable {}", name)))?;
+    let entry = var
+        .keyring
+        .as_ref()
+        .ok_or_else(|| anyhow!(format!("Variable {} has no keyring entry", var.name)))?;
+    let value = PasswordInput::new()
+        .with_prompt(&format!(
+            "<Secure> Value to store in the keyring [{}]",
+            var.name
+        ))
+        .with_confirmation("Repeat the value", "The values don't match")
+        .interact()?;
+    config.keyring.set(entry, &value)?;
+    println!(
+        "Stored {} in the keyring (service {}, account {})",
+        var.name, entry.service, entry.account
+    );
+    Ok(())
+}
+
+/// Offer to change a value from the askfile, keeping it if nothing is entered.
+///
+/// Secure values are never shown, only asked for again.
+fn prompt_to_change(
+    name: &str,
+    saved: String,
+    secure: bool,
+) -> Result<(String, Option<VarSource>)> {
+    let entered = prompt_to_edit(name, &saved, secure)?;
+    Ok(
+        entered.map_or((saved, Some(VarSource::Askfile)), |entered| {
+            (entered, Some(VarSource::Prompt))
+        }),
+    )
+}
+
+/// Prompt with the current value filled in, or hidden if secure, returning a changed value.
+fn prompt_to_edit(name: &str, current: &str, secure: bool) -> Result<Option<String>> {
+    let message = "Enter a new value for the variable, or nothing to keep it";
+    let entered = if secure {
+        PasswordInput::new()
+            .with_prompt(&format!("<Secure> {message} [{name} = {REDACTED}]"))
+            .allow_empty_password(true)
+            .interact()?
+    } else {
+        Input::new()
+            .with_prompt(&format!("{message}, [{name}]"))
+            .default(current.to_owned())
+            .interact()?
+    };
+    Ok(edited_value(entered, current))
+}
+
+/// What was entered at an edit prompt, unless it was nothing or the current value.
+fn edited_value(entered: String, current: &str) -> Option<String> {
+    if entered.is_empty() || entered == current {
+        None
+    } else {
+        Some(entered)
+    }
+}
+
+/// Offer to edit each resolved variable before the run, for `--review-vars`.
+///
+/// `edit` prompts like `prompt_to_edit`.  Fixed values are part of the job, so they aren't
+/// offered.  An edited value must still have the right length.
+fn review_vars<F>(
+    vars: &[AskVar],
+    env: &mut EnvMap,
+    sources: &mut [(String, Option<VarSource>)],
+    suffix: &str,
+    patterns: &SecurePatterns,
+    mut edit: F,
+) -> Result<()>
+where
+    F: FnMut(&str, &str, bool) -> Result<Option<String>>,
+{
+    for (var, (name, source)) in vars.iter().zip(sources.iter_mut()) {
+        if var.value.is_some() {
+            continue;
+        }
+        let is_secure = secure_name_check(var.name.as_str(), suffix).1 || patterns.is_secure(name);
+        let current = env.get(name.as_str()).cloned().unwrap_or_default();
+        while let Some(entered) = edit(name, &current, is_secure)? {
+            match var.check_length(&entered) {
+                Ok(()) => {
+                    env.insert(name.clone(), entered);
+                    *source = Some(VarSource::Prompt);
+                    break;
+                }
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Try a source, unless the variable's `sources` rule it out.
+fn try_source<F: FnOnce() -> Option<String>>(
+    var: &AskVar,
+    source: VarSource,
+    try_var: F,
+) -> Option<(String, Option<VarSource>)> {
+    if var.allows(source) {
+        try_var().map(|value| (value, Some(source)))
+    } else {
+        debug!("Source {:?} not allowed for var: {}", source, var.name);
+        None
+    }
 }
 
 fn try_empty_var(name: &str, config: &Config) -> Option<String> {
     if config.empty_vars {
-        debug!("No-fill: {}", name);
+        debug!("No-fill: {name}");
+        Some(String::default())
+    } else {
+        None
+    }
+}
+
+fn try_default_empty(name: &str, var: &AskVar) -> Option<String> {
+    if var.default_empty {
+        debug!("Defaulting to empty: {name}");
         Some(String::default())
     } else {
         None
@@ -62,18 +460,138 @@ fn try_empty_var(name: &str, config: &Config) -> Option<String> {
 }
 
 fn try_var_from_askfile(name: &str, config: &Config) -> Option<String> {
-    debug!("Trying askfile for var: {}", name);
+    debug!("Trying askfile for var: {name}");
     config.get_file_var(name)
 }
 
+/// A keyring that can't be read is only warned about, so the variable can still be prompted for.
+fn try_var_from_keyring(var: &AskVar, config: &Config) -> Option<String> {
+    let entry = var.keyring.as_ref()?;
+    debug!("Trying keyring for var: {}", var.name);
+    config.keyring.get(entry).unwrap_or_else(|e| {
+        warn!("Cannot read {} from the keyring: {}", var.name, e);
+        None
+    })
+}
+
+/// Run the var's resolver command, with its placeholders filled in, and take its stdout.
+///
+/// Unlike the other sources, a resolver that fails is an error rather than being passed over,
+/// since the variable was meant to come from it.
+fn try_var_from_resolver(var: &AskVar, name: &str, config: &Config) -> Result<Option<String>> {
+    let Some(resolver) = &var.resolver else {
+        return Ok(None);
+    };
+    let settings = load_root_settings(&config.root_dir)?;
+    let template = settings.resolvers.get(resolver).ok_or_else(|| {
+        anyhow!(format!(
+            "Var {} uses resolver '{}', which isn't among the resolvers in {}",
+            name, resolver, SETTINGS_FILE
+        ))
+    })?;
+    let args = resolver_args(var, resolver, template)?;
+    let command = fill_placeholders(template, &args).map_err(|placeholder| {
+        anyhow!(format!(
+            "Resolver '{}' needs {{{}}}, which var {} doesn't give",
+            resolver, placeholder, name
+        ))
+    })?;
+    debug!("Trying resolver '{resolver}' for var: {name}");
+    let output = process::Command::new(DEFAULT_SHELL)
+        .arg("-c")
+        .arg(&command)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| anyhow!(format!("Cannot run resolver '{}': {}", resolver, e)))?;
+    if !output.status.success() {
+        return Err(anyhow!(format!(
+            "Resolver '{}' for var {} failed with {}",
+            resolver, name, output.status
+        )));
+    }
+    let value = String::from_utf8(output.stdout).map_err(|_| {
+        anyhow!(format!(
+            "Resolver '{}' for var {} printed a value that isn't UTF-8",
+            resolver, name
+        ))
+    })?;
+    // Like `$(...)` in the shell.
+    Ok(Some(value.trim_end_matches('\n').to_owned()))
+}
+
+static PLACEHOLDER_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{(\w+)\}").expect("placeholder pattern is valid"));
+
+/// Check the keys of an `ask` entry that aren't settings against its resolver, if the root has
+/// it, so a misspelled setting isn't passed over.  Without a resolver, there shouldn't be any.
+pub(crate) fn check_ask_keys(var: &AskVar, resolvers: &HashMap<String, String>) -> Result<()> {
+    match &var.resolver {
+        Some(resolver) => resolvers.get(resolver).map_or(Ok(()), |template| {
+            resolver_args(var, resolver, template).map(drop)
+        }),
+        None if var.resolver_args.is_empty() => Ok(()),
+        None => {
+            let keys: Vec<&str> = var.resolver_args.keys().map(String::as_str).collect();
+            Err(anyhow!(format!(
+                "Var {} has unknown key(s): {}",
+                var.name,
+                keys.join(", ")
+            )))
+        }
+    }
+}
+
+/// The values the var gives for the placeholders of its resolver's command, erroring on any
+/// other key, or a value that isn't a string.
+fn resolver_args(var: &AskVar, resolver: &str, template: &str) -> Result<BTreeMap<String, String>> {
+    let mut args = BTreeMap::new();
+    for (key, value) in &var.resolver_args {
+        if !PLACEHOLDER_PATTERN
+            .captures_iter(template)
+            .any(|caps| caps[1] == *key)
+        {
+            return Err(anyhow!(format!(
+                "Var {} gives {}, which is neither a setting nor a placeholder of resolver '{}'",
+                var.name, key, resolver
+            )));
+        }
+        let Some(value) = value.as_str() else {
+            return Err(anyhow!(format!(
+                "Var {} gives {} as {}, but placeholder values must be strings",
+                var.name, key, value
+            )));
+        };
+        args.insert(key.clone(), value.to_owned());
+    }
+    Ok(args)
+}
+
+/// Replace each `{name}` in the template with the shell-quoted value of `name`, or return the
+/// first name that has no value.
+fn fill_placeholders(
+    template: &str,
+    values: &BTreeMap<String, String>,
+) -> StdResult<String, String> {
+    if let Some(caps) = PLACEHOLDER_PATTERN
+        .captures_iter(template)
+        .find(|caps| !values.contains_key(&caps[1]))
+    {
+        return Err(caps[1].to_owned());
+    }
+    let filled = PLACEHOLDER_PATTERN.replace_all(template, |caps: &Captures| {
+        format!("'{}'", values[&caps[1]].replace('\'', r"'\''"))
+    });
+    Ok(filled.into_owned())
+}
+
 fn try_var_from_cmd(name: &str, config: &Config) -> Option<String> {
-    debug!("Trying cmd line for var: {}", name);
+    debug!("Trying cmd line for var: {name}");
     config.get_cmd_var(name)
 }
 
 fn try_var_from_env(name: &str, config: &Config) -> Option<String> {
     if config.allow_env {
-        debug!("Trying environment for var: {}", name);
+        debug!("Trying environment for var: {name}");
 
         if let Ok(val) = env::var(name) {
             return Some(val);
@@ -82,44 +600,1075 @@ fn try_var_from_env(name: &str, config: &Config) -> Option<String> {
     None
 }
 
-pub(crate) fn fill_asked(mut spec: JobSpec, answers: &EnvMap) -> Result<ReadyJob> {
-    let mut map = EnvMap::new();
-    for raw in spec.ask_for_vars {
-        let (name, _) = secure_name_check(raw);
+/// Build a ready job from its spec and the answers to its variables.
+///
+/// Values asked for with the secure suffix are secure, as are those of any env key matching one
+/// of the root's `secure_patterns`.
+pub(crate) fn fill_asked(
+    mut spec: JobSpec,
+    answers: &EnvMap,
+    secure_suffix: &str,
+    patterns: &SecurePatterns,
+) -> Result<ReadyJob> {
+    let mut asked = EnvMap::new();
+    let mut secure_vars = HashSet::new();
+    for var in &spec.ask_for_vars {
+        let (name, is_secure) = secure_name_check(var.name.as_str(), secure_suffix);
         if let Some(value) = answers.get(&name) {
-            map.insert(name, value.to_owned());
+            if is_secure {
+                secure_vars.insert(encode_key(&name));
+            }
+            asked.insert(name, value.to_owned());
         } else {
             return Err(anyhow!(format!("Unresolvable variable: {}", name)));
         }
     }
 
-    for (k, v) in spec.provided_env.drain() {
-        map.insert(encode_key(&k), v);
+    let provided: EnvMap = spec.provided_env.drain().collect();
+    let provided_keys = provided.keys().map(|key| encode_key(key)).collect();
+
+    // These are taken as-is, so they aren't provided keys subject to `${KEY}` expansion.
+    let mut from_vars = EnvMap::new();
+    for (k, var) in spec.env_from_vars.drain() {
+        let (name, is_secure) = secure_name_check(var, secure_suffix);
+        let value = answers
+            .get(&name)
+            .ok_or_else(|| anyhow!(format!("Unresolvable variable: {}", name)))?;
+        if is_secure {
+            secure_vars.insert(encode_key(&k));
+        }
+        from_vars.insert(k, value.to_owned());
     }
 
-    Ok(ReadyJob::new(
-        spec.name,
-        map,
-        spec.depends,
-        spec.has_deps_script,
-    ))
+    // Asked values, then literal env, then env taken from variables.
+    let map: EnvMap = merge_env(merge_env(asked, provided), from_vars);
+    secure_vars.extend(map.keys().filter(|key| patterns.is_secure(key)).cloned());
+    Ok(ReadyJob::new(spec, map, secure_vars, provided_keys))
+}
+
+/// The variables the jobs ask for.  Jobs asking for the same variable share it, so their
+/// settings are merged.
+fn merged_ask_vars(specs: &[JobSpec], suffix: &str) -> Result<Vec<AskVar>> {
+    let mut vars: Vec<AskVar> = Vec::new();
+    for var in specs.iter().flat_map(|spec| spec.get_ask_vars(suffix)) {
+        let name = secure_name_check(var.name.as_str(), suffix).0;
+        if let Some(existing) = vars
+            .iter_mut()
+            .find(|v| secure_name_check(v.name.as_str(), suffix).0 == name)
+        {
+            existing.merge(&var)?;
+        } else {
+            vars.push(var);
+        }
+    }
+    Ok(vars)
 }
 
 pub(crate) fn query(specs: &[JobSpec], config: &Config) -> Result<EnvMap> {
-    let var_names: Vec<&str> = specs
+    let vars = merged_ask_vars(specs, &config.secure_suffix)?;
+    for name in &config.reask {
+        if !vars
+            .iter()
+            .any(|var| &secure_name_check(var.name.as_str(), &config.secure_suffix).0 == name)
+        {
+            return Err(anyhow!(format!(
+                "Cannot re-ask var {}: no scheduled job asks for it",
+                name
+            )));
+        }
+    }
+    let mut new_env = EnvMap::new();
+    let mut sources = Vec::with_capacity(vars.len());
+
+    for var in &vars {
+        let (key, value, source) = query_single_var(var, config)?;
+        sources.push((key.clone(), source));
+        new_env.insert(key, value);
+    }
+
+    if config.review_vars {
+        let patterns = load_secure_patterns(&config.root_dir)?;
+        review_vars(
+            &vars,
+            &mut new_env,
+            &mut sources,
+            &config.secure_suffix,
+            &patterns,
+            prompt_to_edit,
+        )?;
+    }
+    if config.prompt_missing {
+        println!("{}", resolution_summary(&sources));
+    }
+    Ok(new_env)
+}
+
+/// Resolve every variable the scheduled jobs ask for, without prompting, and report the ones
+/// that don't resolve.  Unlike `query`, this carries on past failures so they're all listed.
+///
+/// `--check-vars` conflicts with the prompting options, so `config.interactive` is unset here.
+pub(crate) fn check_vars(config: &Config) -> Result<()> {
+    let (scheduled, _) = load_specs(&config.root_dir, config)?;
+    let vars = merged_ask_vars(&scheduled, &config.secure_suffix)?;
+    let (lines, failed) = check_lines(&vars, config);
+    for line in lines {
+        println!("{line}");
+    }
+    if failed > 0 {
+        return Err(anyhow!(format!(
+            "{} of {} variables cannot be resolved without prompting",
+            failed,
+            vars.len()
+        )));
+    }
+    println!("check-vars: ok, {} variables resolved", vars.len());
+    Ok(())
+}
+
+/// A line per variable saying where it resolved from or why it didn't, and how many didn't.
+fn check_lines(vars: &[AskVar], config: &Config) -> (Vec<String>, usize) {
+    let mut failed = 0;
+    let lines = vars
         .iter()
-        .flat_map(JobSpec::get_ask_vars)
-        .map(String::as_str)
+        .map(|var| {
+            let name = secure_name_check(var.name.as_str(), &config.secure_suffix).0;
+            match query_single_var(var, config) {
+                Ok((_, _, source)) => {
+                    let from = source.map_or("fixed or default value", VarSource::name);
+                    format!("ok      {name} <- {from}")
+                }
+                Err(e) => {
+                    failed += 1;
+                    format!("FAILED  {name}: {e}")
+                }
+            }
+        })
         .collect();
-    let mut new_env = EnvMap::new();
+    (lines, failed)
+}
 
-    for name in var_names {
-        if new_env.contains_key(name) {
+/// List where each variable came from, so it's clear which ones had to be prompted for.
+fn resolution_summary(sources: &[(String, Option<VarSource>)]) -> String {
+    let prompted = sources
+        .iter()
+        .filter(|(_, source)| *source == Some(VarSource::Prompt))
+        .count();
+    let mut lines = vec![format!(
+        "Variables: {} resolved automatically, {} prompted",
+        sources.len() - prompted,
+        prompted
+    )];
+    let width = sources
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or_default();
+    for (name, source) in sources {
+        let from = source.map_or("fixed or default value", VarSource::name);
+        lines.push(format!("  {name:width$} <- {from}"));
+    }
+    lines.join("\n")
+}
+
+/// Write the resolved answers to `path` as `KEY=value` lines that `--ask-file` can read back.
+///
+/// Secure vars, including those matching the root's `secure_patterns`, are left out unless
+/// `--include-secure` is set.  Values the askfile format can't
+/// hold, like empty or multi-line ones, are skipped with a warning.
+pub(crate) fn write_env_file(
+    path: &Path,
+    specs: &[JobSpec],
+    answers: &EnvMap,
+    config: &Config,
+) -> Result<()> {
+    let suffix = config.secure_suffix.as_str();
+    let patterns = load_secure_patterns(&config.root_dir)?;
+    let secure: HashSet<String> = specs
+        .iter()
+        .flat_map(|spec| spec.get_ask_vars(suffix))
+        .map(|var| secure_name_check(var.name.as_str(), suffix))
+        .filter_map(|(name, is_secure)| if is_secure { Some(name) } else { None })
+        .collect();
+    let mut keys: Vec<&String> = answers.keys().collect();
+    keys.sort();
+
+    let mut contents = String::new();
+    for key in keys {
+        if (secure.contains(key) || patterns.is_secure(key)) && !config.include_secure {
+            debug!("Leaving secure var out of env file: {key}");
             continue;
         }
-        let (key, value) = query_single_var(name, config)?;
-        new_env.insert(key, value);
+        let value = &answers[key];
+        // A leading `@` would be read back as a file path, so escape it.
+        let escaped = if value.starts_with('@') {
+            format!("@{value}")
+        } else {
+            value.clone()
+        };
+        let line = format!("{key}={escaped}");
+        match try_parse_var_string(&line, "env file") {
+            Ok(Some((parsed_key, parsed_value)))
+                if parsed_key == *key && parsed_value == *value =>
+            {
+                contents.push_str(&line);
+                contents.push('\n');
+            }
+            _ => warn!("Value of {key} can't be written to an env file, skipping it"),
+        }
     }
 
-    Ok(new_env)
+    debug!("Writing env file: {}", path.display());
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+static REFERENCE_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").expect("reference pattern is valid")
+});
+
+/// Substitute `${NAME}` references in `value` using `env`, erroring on unknown names.
+pub(crate) fn expand_vars(value: &str, env: &EnvMap) -> Result<String> {
+    let mut missing = None;
+    let expanded = REFERENCE_PATTERN.replace_all(value, |captures: &Captures| {
+        let name = &captures[1];
+        env.get(name).cloned().unwrap_or_else(|| {
+            missing.get_or_insert_with(|| name.to_owned());
+            String::new()
+        })
+    });
+    missing.map_or_else(
+        || Ok(expanded.into_owned()),
+        |name| {
+            Err(anyhow!(format!(
+                "Unknown variable reference: ${{{}}}",
+                name
+            )))
+        },
+    )
+}
+
+/// Resolve `${NAME}` references among the `templated` keys of `env`.
+///
+/// References may point at any key in `env`; references between templated keys are resolved
+/// in dependency order, and cycles between them are an error.
+pub(crate) fn resolve_env_references(env: &mut EnvMap, templated: &HashSet<String>) -> Result<()> {
+    let mut resolved = EnvMap::new();
+    let mut keys: Vec<&String> = templated.iter().filter(|k| env.contains_key(*k)).collect();
+    keys.sort();
+    for key in keys {
+        resolve_env_key(key, env, templated, &mut resolved, &mut Vec::new())?;
+    }
+    env.extend(resolved);
+    Ok(())
+}
+
+fn resolve_env_key(
+    key: &str,
+    env: &EnvMap,
+    templated: &HashSet<String>,
+    resolved: &mut EnvMap,
+    visiting: &mut Vec<String>,
+) -> Result<String> {
+    if let Some(value) = resolved.get(key) {
+        return Ok(value.clone());
+    }
+    if visiting.iter().any(|k| k == key) {
+        visiting.push(key.to_owned());
+        return Err(anyhow!(format!(
+            "Cycle in env references: {}",
+            visiting.join(" -> ")
+        )));
+    }
+    let raw = env
+        .get(key)
+        .ok_or_else(|| anyhow!(format!("Unknown variable reference: ${{{}}}", key)))?;
+    if !templated.contains(key) {
+        return Ok(raw.clone());
+    }
+    visiting.push(key.to_owned());
+    let mut lookup = EnvMap::new();
+    for captures in REFERENCE_PATTERN.captures_iter(raw) {
+        let name = &captures[1];
+        let value = resolve_env_key(name, env, templated, resolved, visiting)?;
+        lookup.insert(name.to_owned(), value);
+    }
+    visiting.pop();
+    let value = expand_vars(raw, &lookup)?;
+    resolved.insert(key.to_owned(), value.clone());
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    use crate::info::{AskEntry, KeyringEntry};
+    use crate::jobs::RunContext;
+    use crate::keyring::Keyring;
+
+    /// A keyring holding one value, or one that can't be read.
+    struct FakeKeyring(Option<&'static str>);
+
+    impl Keyring for FakeKeyring {
+        fn get(&self, _entry: &KeyringEntry) -> Result<Option<String>> {
+            self.0
+                .map(|value| Some(value.to_owned()))
+                .ok_or_else(|| anyhow!("the keyring is locked"))
+        }
+
+        fn set(&self, _entry: &KeyringEntry, _value: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn resolver_root(resolvers: &str) -> TempDir {
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::write(
+            root.path().join(SETTINGS_FILE),
+            format!("[resolvers]\n{resolvers}"),
+        )
+        .unwrap();
+        root
+    }
+
+    fn resolved_var(resolver: &str, args: &[(&str, &str)]) -> AskVar {
+        AskVar {
+            name: "TOKEN".to_owned(),
+            resolver: Some(resolver.to_owned()),
+            resolver_args: args
+                .iter()
+                .map(|&(key, value)| (key.to_owned(), value.into()))
+                .collect(),
+            ..AskVar::default()
+        }
+    }
+
+    #[test]
+    fn resolver_output_is_the_value() {
+        let root = resolver_root(r#"echo = "printf '%s\\n' {greeting}""#);
+        let config = Config::for_test(root.path(), &[]);
+        let var = resolved_var("echo", &[("greeting", "it's here")]);
+        let value = try_var_from_resolver(&var, "TOKEN", &config).unwrap();
+        assert_eq!(value.as_deref(), Some("it's here"));
+    }
+
+    #[test]
+    fn resolver_failures_fail_the_var() {
+        let root = resolver_root("broken = \"exit 3\"\nneedy = \"echo {path}\"");
+        let config = Config::for_test(root.path(), &[]);
+
+        let failed = try_var_from_resolver(&resolved_var("broken", &[]), "TOKEN", &config);
+        assert!(failed.unwrap_err().to_string().contains("failed with"));
+
+        let unknown = try_var_from_resolver(&resolved_var("vault", &[]), "TOKEN", &config);
+        assert!(unknown
+            .unwrap_err()
+            .to_string()
+            .contains("isn't among the resolvers"));
+
+        let missing = try_var_from_resolver(&resolved_var("needy", &[]), "TOKEN", &config);
+        assert_eq!(
+            missing.unwrap_err().to_string(),
+            "Resolver 'needy' needs {path}, which var TOKEN doesn't give"
+        );
+    }
+
+    #[test]
+    fn vars_without_a_resolver_are_passed_over() {
+        let root = resolver_root("");
+        let config = Config::for_test(root.path(), &[]);
+        let var = AskVar::default();
+        assert_eq!(try_var_from_resolver(&var, "TOKEN", &config).unwrap(), None);
+    }
+
+    #[test]
+    fn fill_placeholders_quotes_each_value() {
+        let values = BTreeMap::from([("path".to_owned(), "a b'c".to_owned())]);
+        assert_eq!(
+            fill_placeholders("get {path} {path}", &values).unwrap(),
+            r"get 'a b'\''c' 'a b'\''c'"
+        );
+        assert_eq!(
+            fill_placeholders("get {field}", &values).unwrap_err(),
+            "field"
+        );
+    }
+
+    #[test]
+    fn ask_keys_must_be_placeholders_of_the_resolver() {
+        let resolvers = HashMap::from([(
+            "vault".to_owned(),
+            "vault kv get -field={field} {path}".to_owned(),
+        )]);
+        let var = resolved_var("vault", &[("path", "secret/db"), ("field", "password")]);
+        check_ask_keys(&var, &resolvers).unwrap();
+
+        let misspelled = resolved_var("vault", &[("path", "secret/db"), ("feild", "password")]);
+        assert_eq!(
+            check_ask_keys(&misspelled, &resolvers)
+                .unwrap_err()
+                .to_string(),
+            "Var TOKEN gives feild, which is neither a setting nor a placeholder of resolver \
+             'vault'"
+        );
+
+        let mut numeric = resolved_var("vault", &[("field", "password")]);
+        numeric.resolver_args.insert("path".to_owned(), 5.into());
+        assert_eq!(
+            check_ask_keys(&numeric, &resolvers)
+                .unwrap_err()
+                .to_string(),
+            "Var TOKEN gives path as 5, but placeholder values must be strings"
+        );
+
+        // An unknown resolver is reported when the var is resolved.
+        check_ask_keys(&resolved_var("other", &[("path", "x")]), &resolvers).unwrap();
+
+        let unresolved = AskVar {
+            resolver: None,
+            ..resolved_var("vault", &[("min_lenght", "5")])
+        };
+        assert_eq!(
+            check_ask_keys(&unresolved, &resolvers)
+                .unwrap_err()
+                .to_string(),
+            "Var TOKEN has unknown key(s): min_lenght"
+        );
+    }
+
+    fn keyring_var() -> AskVar {
+        AskVar {
+            keyring: Some(KeyringEntry {
+                service: "devmaker".to_owned(),
+                account: "token".to_owned(),
+            }),
+            ..resolved_var("echo", &[])
+        }
+    }
+
+    #[test]
+    fn keyring_comes_after_the_command_line_and_before_resolvers() {
+        let root = resolver_root("echo = \"echo from-resolver\"");
+        let mut config = Config::for_test(root.path(), &[]);
+        config.keyring = Box::new(FakeKeyring(Some("from-keyring")));
+        let (_, value, source) = resolve_single_var(&keyring_var(), &config).unwrap();
+        assert_eq!(value, "from-keyring");
+        assert_eq!(source, Some(VarSource::Keyring));
+
+        let mut config = Config::for_test(root.path(), &["--with-vars", "TOKEN=from-cli"]);
+        config.keyring = Box::new(FakeKeyring(Some("from-keyring")));
+        let (_, value, source) = resolve_single_var(&keyring_var(), &config).unwrap();
+        assert_eq!(value, "from-cli");
+        assert_eq!(source, Some(VarSource::Command));
+    }
+
+    #[test]
+    fn unreadable_keyring_falls_through_to_the_next_source() {
+        let root = resolver_root("echo = \"echo from-resolver\"");
+        let mut config = Config::for_test(root.path(), &[]);
+        config.keyring = Box::new(FakeKeyring(None));
+        let (_, value, source) = resolve_single_var(&keyring_var(), &config).unwrap();
+        assert_eq!(value, "from-resolver");
+        assert_eq!(source, Some(VarSource::Resolver));
+    }
+
+    fn env(pairs: &[(&str, &str)]) -> EnvMap {
+        pairs
+            .iter()
+            .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn references_resolve_along_a_chain() {
+        let mut chained = env(&[("A", "${B}/a"), ("B", "${C}/b"), ("C", "/c"), ("D", "${C}")]);
+        let templated = ["A", "B"].iter().map(|&key| key.to_owned()).collect();
+        resolve_env_references(&mut chained, &templated).unwrap();
+        assert_eq!(chained["A"], "/c/b/a");
+        assert_eq!(chained["B"], "/c/b");
+        // Only templated keys are expanded.
+        assert_eq!(chained["D"], "${C}");
+    }
+
+    #[test]
+    fn reference_cycles_are_an_error() {
+        let mut cyclic = env(&[("A", "${B}"), ("B", "${C}"), ("C", "${A}")]);
+        let templated = ["A", "B", "C"].iter().map(|&key| key.to_owned()).collect();
+        let error = resolve_env_references(&mut cyclic, &templated).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cycle in env references: A -> B -> C -> A"
+        );
+    }
+
+    #[test]
+    fn unknown_references_are_an_error() {
+        let error = expand_vars("${MISSING}/bin", &env(&[("PATH", "/bin")])).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown variable reference: ${MISSING}");
+    }
+
+    #[test]
+    fn merge_env_treats_spellings_of_a_key_as_one() {
+        assert_eq!(encode_key("my-key"), "MY_KEY");
+        assert_eq!(encode_key("my key"), "MY_KEY");
+        let merged = merge_env(env(&[("my-key", "base")]), env(&[("MY_KEY", "overlay")]));
+        assert_eq!(merged, env(&[("MY_KEY", "overlay")]));
+    }
+
+    #[test]
+    fn filled_env_layers_literal_over_asked_and_from_var_over_literal() {
+        let info = serde_json::from_str(
+            r#"{"ask": ["SHARED", "ANSWER"],
+                "env": {"shared": "literal",
+                        "LAYERED": "literal", "layered": {"from_var": "ANSWER"}}}"#,
+        )
+        .unwrap();
+        let spec = JobSpec::new("build".to_owned(), info, false);
+        let answers = env(&[("SHARED", "asked"), ("ANSWER", "answered")]);
+        let job = fill_asked(spec, &answers, "_SECURE", &SecurePatterns::default()).unwrap();
+        assert_eq!(job.env()["SHARED"], "literal");
+        assert_eq!(job.env()["LAYERED"], "answered");
+        assert_eq!(job.env()["ANSWER"], "answered");
+    }
+
+    #[test]
+    fn env_prefix_applies_to_literal_and_from_var_keys() {
+        let info = serde_json::from_str(
+            r#"{"ask": ["PORT"], "env_prefix": "db_",
+                "env": {"host": "db.local", "port": {"from_var": "PORT"}}}"#,
+        )
+        .unwrap();
+        let spec = JobSpec::new("db".to_owned(), info, false);
+        let answers = env(&[("PORT", "5432")]);
+        let job = fill_asked(spec, &answers, "_SECURE", &SecurePatterns::default()).unwrap();
+
+        let root = TempDir::new("devmaker-test").unwrap();
+        let config = Config::for_test(root.path(), &[]);
+        let context = RunContext::new(vec!["db".to_owned()], &config);
+        let child_env = job.create_proc_env(root.path(), &context).unwrap();
+        assert_eq!(child_env["DB_HOST"], "db.local");
+        assert_eq!(child_env["DB_PORT"], "5432");
+        assert!(!child_env.contains_key("HOST"));
+        assert_eq!(child_env["PORT"], "5432");
+    }
+
+    #[test]
+    fn env_keys_matching_a_secure_pattern_are_redacted() {
+        console::set_colors_enabled(false);
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::write(
+            root.path().join("devmaker.toml"),
+            "secure_patterns = [\".*TOKEN.*\"]\n",
+        )
+        .unwrap();
+        let patterns = load_secure_patterns(root.path()).unwrap();
+        let info = serde_json::from_str(
+            r#"{"env": {"GITHUB_TOKEN": "ghp_abc123", "GITHUB_USER": "octocat"}}"#,
+        )
+        .unwrap();
+        let spec = JobSpec::new("gh".to_owned(), info, false);
+        let job = fill_asked(spec, &EnvMap::new(), "_SECURE", &patterns).unwrap();
+
+        let config = Config::for_test(root.path(), &["--dry-run"]);
+        let context = RunContext::new(vec!["gh".to_owned()], &config);
+        let report = job.report(root.path(), 0, None, &context);
+        assert!(report.contains("  Env: GITHUB_TOKEN -> ***"), "{}", report);
+        assert!(
+            report.contains("  Env: GITHUB_USER  -> octocat"),
+            "{}",
+            report
+        );
+        assert!(!report.contains("ghp_abc123"));
+    }
+
+    fn unset_var(default_empty: bool) -> AskVar {
+        AskVar {
+            name: "DEVMAKER_TEST_UNSET".to_owned(),
+            default_empty,
+            ..AskVar::default()
+        }
+    }
+
+    #[test]
+    fn default_empty_vars_fall_back_to_empty() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let config = Config::for_test(root.path(), &[]);
+        let (_, value, source) = resolve_single_var(&unset_var(true), &config).unwrap();
+        assert_eq!((value.as_str(), source), ("", None));
+
+        let error = resolve_single_var(&unset_var(false), &config).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cound not resolve var: DEVMAKER_TEST_UNSET"
+        );
+    }
+
+    #[test]
+    fn default_empty_comes_after_the_other_sources() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let config = Config::for_test(root.path(), &["-w", "DEVMAKER_TEST_UNSET=given"]);
+        let (_, value, _) = resolve_single_var(&unset_var(true), &config).unwrap();
+        assert_eq!(value, "given");
+    }
+
+    #[test]
+    fn env_file_round_trips_through_the_askfile_parser() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let path = root.path().join("answers.env");
+        let info = r#"{"ask": ["HOST", "KEY", "PASSWORD_SECURE", "NOTES"]}"#;
+        let specs = [JobSpec::new(
+            "db".to_owned(),
+            serde_json::from_str(info).unwrap(),
+            false,
+        )];
+        let answers = env(&[
+            ("HOST", "db.local"),
+            ("KEY", "@not-a-file"),
+            ("PASSWORD", "hunter2"),
+            ("NOTES", "two\nlines"),
+        ]);
+        let path = path.to_str().unwrap();
+        let config = Config::for_test(root.path(), &["--env-file-out", path]);
+        write_env_file(Path::new(path), &specs, &answers, &config).unwrap();
+
+        let read_back = Config::for_test(root.path(), &["--ask-file", path]).ask_file_vars;
+        assert_eq!(
+            read_back,
+            Some(env(&[("HOST", "db.local"), ("KEY", "@not-a-file")]))
+        );
+
+        let config = Config::for_test(root.path(), &["--env-file-out", path, "--include-secure"]);
+        write_env_file(Path::new(path), &specs, &answers, &config).unwrap();
+        let read_back = Config::for_test(root.path(), &["--ask-file", path]).ask_file_vars;
+        assert_eq!(read_back.unwrap()["PASSWORD"], "hunter2");
+    }
+
+    #[test]
+    fn env_from_a_secure_var_is_secure_under_its_own_key() {
+        let info = serde_json::from_str(
+            r#"{"ask": ["GITHUB_TOKEN_SECURE"],
+                "env": {"TOKEN": {"from_var": "GITHUB_TOKEN_SECURE"}, "USER": "ci"}}"#,
+        )
+        .unwrap();
+        let spec = JobSpec::new("deploy".to_owned(), info, false);
+        let answers = env(&[("GITHUB_TOKEN", "ghp_secret")]);
+        let job = fill_asked(spec, &answers, "_SECURE", &SecurePatterns::default()).unwrap();
+        assert_eq!(job.env()["TOKEN"], "ghp_secret");
+        assert_eq!(job.env()["USER"], "ci");
+        assert!(job.secure_vars().contains("TOKEN"));
+        assert!(job.secure_vars().contains("GITHUB_TOKEN"));
+        assert!(!job.secure_vars().contains("USER"));
+    }
+
+    #[test]
+    fn env_from_an_unanswered_var_is_an_error() {
+        let info = serde_json::from_str(r#"{"env": {"TOKEN": {"from_var": "MISSING"}}}"#).unwrap();
+        let spec = JobSpec::new("deploy".to_owned(), info, false);
+        let error = fill_asked(spec, &EnvMap::new(), "_SECURE", &SecurePatterns::default())
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Unresolvable variable: MISSING");
+    }
+
+    fn restricted_var(sources: &[VarSource]) -> AskVar {
+        AskVar {
+            name: "DEVMAKER_TEST_RESTRICTED".to_owned(),
+            sources: Some(sources.to_vec()),
+            ..AskVar::default()
+        }
+    }
+
+    #[test]
+    fn restricted_vars_skip_disallowed_sources() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let askfile = root.path().join("answers.env");
+        fs::write(&askfile, "DEVMAKER_TEST_RESTRICTED=from-file\n").unwrap();
+        let askfile = askfile.to_str().unwrap();
+        let config = Config::for_test(
+            root.path(),
+            &[
+                "-w",
+                "DEVMAKER_TEST_RESTRICTED=from-cli",
+                "--ask-file",
+                askfile,
+            ],
+        );
+
+        let unrestricted = AskVar {
+            sources: None,
+            ..restricted_var(&[])
+        };
+        let (_, _, source) = resolve_single_var(&unrestricted, &config).unwrap();
+        assert_eq!(source, Some(VarSource::Command));
+
+        let (_, value, source) =
+            resolve_single_var(&restricted_var(&[VarSource::Askfile]), &config).unwrap();
+        assert_eq!(
+            (value.as_str(), source),
+            ("from-file", Some(VarSource::Askfile))
+        );
+
+        let config = Config::for_test(root.path(), &["--ask-file", askfile]);
+        let error =
+            resolve_single_var(&restricted_var(&[VarSource::Command]), &config).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cound not resolve var: DEVMAKER_TEST_RESTRICTED"
+        );
+    }
+
+    #[test]
+    fn timed_prompts_fall_back_to_the_default_or_fail() {
+        use std::os::unix::io::{AsRawFd, FromRawFd};
+
+        let root = TempDir::new("devmaker-test").unwrap();
+        let config = Config::for_test(root.path(), &[]);
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (input, mut writer) =
+            unsafe { (fs::File::from_raw_fd(fds[0]), fs::File::from_raw_fd(fds[1])) };
+        let ask = |var: &AskVar| {
+            resolve_with_prompt(var, &config, |var, name, _, secure| {
+                prompt_with_timeout(
+                    var,
+                    name,
+                    secure,
+                    Duration::from_millis(50),
+                    input.as_raw_fd(),
+                )
+            })
+        };
+        let mut var = AskVar {
+            name: "DEVMAKER_TEST_TIMED".to_owned(),
+            default_empty: true,
+            ..AskVar::default()
+        };
+        let (_, value, source) = ask(&var).unwrap();
+        assert_eq!((value.as_str(), source), ("", None));
+
+        var.default_empty = false;
+        assert_eq!(
+            ask(&var).unwrap_err().to_string(),
+            "Timed out waiting for input for DEVMAKER_TEST_TIMED"
+        );
+
+        writer.write_all(b"db.local\nleft for later\n").unwrap();
+        let (_, value, source) = ask(&var).unwrap();
+        assert_eq!(
+            (value.as_str(), source),
+            ("db.local", Some(VarSource::Prompt))
+        );
+        let (_, value, _) = ask(&var).unwrap();
+        assert_eq!(value, "left for later");
+    }
+
+    #[test]
+    fn only_reasked_vars_are_prompted_for() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let askfile = root.path().join("answers.env");
+        fs::write(&askfile, "HOST=db.local\nPORT=5432\n").unwrap();
+        let askfile = askfile.to_str().unwrap();
+        let config = Config::for_test(root.path(), &["--ask-file", askfile, "--reask", "PORT"]);
+        let mut prompted = Vec::new();
+        let mut resolve = |name: &str| {
+            let var = AskVar {
+                name: name.to_owned(),
+                ..AskVar::default()
+            };
+            let (_, value, source) = resolve_with_prompt(&var, &config, |_, name, _, _| {
+                prompted.push(name.to_owned());
+                Ok(Some("6543".to_owned()))
+            })
+            .unwrap();
+            (value, source)
+        };
+        assert_eq!(
+            resolve("HOST"),
+            ("db.local".to_owned(), Some(VarSource::Askfile))
+        );
+        assert_eq!(
+            resolve("PORT"),
+            ("6543".to_owned(), Some(VarSource::Prompt))
+        );
+        assert_eq!(prompted, ["PORT"]);
+
+        let config = Config::for_test(
+            root.path(),
+            &["--ask-file", askfile, "--reask", "DEVMAKER_TEST_RESTRICTED"],
+        );
+        let error =
+            resolve_single_var(&restricted_var(&[VarSource::Askfile]), &config).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cannot re-ask var DEVMAKER_TEST_RESTRICTED: its sources don't include prompt"
+        );
+        let specs = [asking_spec("db", r#"["HOST"]"#)];
+        assert_eq!(
+            query(&specs, &config).unwrap_err().to_string(),
+            "Cannot re-ask var DEVMAKER_TEST_RESTRICTED: no scheduled job asks for it"
+        );
+    }
+
+    fn asking_spec(name: &str, ask: &str) -> JobSpec {
+        let info = serde_json::from_str(&format!(r#"{{"ask": {ask}}}"#)).unwrap();
+        JobSpec::new(name.to_owned(), info, false)
+    }
+
+    #[test]
+    fn fixed_values_win_over_every_source_and_reach_the_env() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let config = Config::for_test(root.path(), &["-w", "FEATURE_FLAG=off"]);
+        let specs = [
+            asking_spec("app", r#"[{"name": "FEATURE_FLAG", "value": "on"}]"#),
+            asking_spec("web", r#"["FEATURE_FLAG"]"#),
+        ];
+        let answers = query(&specs, &config).unwrap();
+        assert_eq!(answers, env(&[("FEATURE_FLAG", "on")]));
+
+        let [app, _] = specs;
+        let job = fill_asked(app, &answers, "_SECURE", &SecurePatterns::default()).unwrap();
+        assert_eq!(job.env()["FEATURE_FLAG"], "on");
+    }
+
+    #[test]
+    fn conflicting_fixed_values_are_an_error() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let config = Config::for_test(root.path(), &[]);
+        let specs = [
+            asking_spec("app", r#"[{"name": "FEATURE_FLAG", "value": "on"}]"#),
+            asking_spec("web", r#"[{"name": "FEATURE_FLAG", "value": "off"}]"#),
+        ];
+        assert_eq!(
+            query(&specs, &config).unwrap_err().to_string(),
+            "Variable FEATURE_FLAG is given conflicting fixed values"
+        );
+    }
+
+    #[test]
+    fn summary_separates_automatic_and_prompted_vars() {
+        let sources = [
+            ("HOST".to_owned(), Some(VarSource::Askfile)),
+            ("PASSWORD".to_owned(), Some(VarSource::Prompt)),
+            ("PORT".to_owned(), None),
+        ];
+        assert_eq!(
+            resolution_summary(&sources),
+            "Variables: 2 resolved automatically, 1 prompted\n  \
+             HOST     <- askfile\n  \
+             PASSWORD <- prompt\n  \
+             PORT     <- fixed or default value"
+        );
+    }
+
+    #[test]
+    fn provided_vars_are_not_prompted_for_with_prompt_missing() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let askfile = root.path().join("answers.env");
+        fs::write(&askfile, "HOST=db.local\n").unwrap();
+        let askfile = askfile.to_str().unwrap();
+        let config = Config::for_test(
+            root.path(),
+            &["--prompt-missing", "--ask-file", askfile, "-w", "PORT=5432"],
+        );
+        let specs = [asking_spec("db", r#"["HOST", "PORT"]"#)];
+        let answers = query(&specs, &config).unwrap();
+        assert_eq!(answers, env(&[("HOST", "db.local"), ("PORT", "5432")]));
+    }
+
+    #[test]
+    fn entering_nothing_or_the_same_value_keeps_it() {
+        assert_eq!(edited_value(String::new(), "cached"), None);
+        assert_eq!(edited_value("cached".to_owned(), "cached"), None);
+        assert_eq!(
+            edited_value("changed".to_owned(), "cached"),
+            Some("changed".to_owned())
+        );
+    }
+
+    #[test]
+    fn reviewed_vars_take_edits_and_keep_skipped_values() {
+        let vars: Vec<AskVar> = [
+            r#""HOST""#,
+            r#""PORT""#,
+            r#"{"name": "TOKEN_SECURE", "min_length": 8}"#,
+            r#"{"name": "MODE", "value": "fast"}"#,
+        ]
+        .iter()
+        .map(|entry| AskVar::from(serde_json::from_str::<AskEntry>(entry).unwrap()))
+        .collect();
+        let mut env = env(&[
+            ("HOST", "db.local"),
+            ("PORT", "5432"),
+            ("TOKEN", "old-token"),
+            ("MODE", "fast"),
+        ]);
+        let mut sources = vec![
+            ("HOST".to_owned(), Some(VarSource::Askfile)),
+            ("PORT".to_owned(), Some(VarSource::Env)),
+            ("TOKEN".to_owned(), Some(VarSource::Keyring)),
+            ("MODE".to_owned(), None),
+        ];
+        let mut answers =
+            vec![Some("db.internal"), None, Some("short"), Some("new-token")].into_iter();
+        let mut offered = Vec::new();
+        review_vars(
+            &vars,
+            &mut env,
+            &mut sources,
+            "_SECURE",
+            &SecurePatterns::default(),
+            |name, current, secure| {
+                offered.push(format!("{name}={current} {secure}"));
+                Ok(answers.next().unwrap().map(ToOwned::to_owned))
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            offered,
+            [
+                "HOST=db.local false",
+                "PORT=5432 false",
+                "TOKEN=old-token true",
+                "TOKEN=old-token true",
+            ]
+        );
+        assert_eq!(env["HOST"], "db.internal");
+        assert_eq!(env["PORT"], "5432");
+        assert_eq!(env["TOKEN"], "new-token");
+        assert_eq!(
+            sources,
+            [
+                ("HOST".to_owned(), Some(VarSource::Prompt)),
+                ("PORT".to_owned(), Some(VarSource::Env)),
+                ("TOKEN".to_owned(), Some(VarSource::Prompt)),
+                ("MODE".to_owned(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_changed_vars_are_not_offered_without_prompt_as_a_source() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        let askfile = root.path().join("answers.env");
+        fs::write(&askfile, "DEVMAKER_TEST_RESTRICTED=cached\n").unwrap();
+        let askfile = askfile.to_str().unwrap();
+        let config = Config::for_test(root.path(), &["--only-changed-vars", "--ask-file", askfile]);
+        let var = restricted_var(&[VarSource::Askfile]);
+        let (_, value, source) = resolve_single_var(&var, &config).unwrap();
+        assert_eq!(
+            (value.as_str(), source),
+            ("cached", Some(VarSource::Askfile))
+        );
+
+        // Only askfile values are offered for changing, not those given on the command line.
+        let config = Config::for_test(
+            root.path(),
+            &[
+                "--only-changed-vars",
+                "-w",
+                "DEVMAKER_TEST_RESTRICTED=given",
+            ],
+        );
+        let var = AskVar {
+            sources: None,
+            ..restricted_var(&[])
+        };
+        let (_, value, source) = resolve_single_var(&var, &config).unwrap();
+        assert_eq!(
+            (value.as_str(), source),
+            ("given", Some(VarSource::Command))
+        );
+    }
+
+    fn token_var() -> AskVar {
+        AskVar {
+            name: "TOKEN_SECURE".to_owned(),
+            min_length: Some(20),
+            max_length: Some(40),
+            ..AskVar::default()
+        }
+    }
+
+    /// Resolve `var` as if each of `answers` was entered at the prompt in turn.
+    fn scripted(var: &AskVar, source: VarSource, answers: &[&str]) -> (Result<String>, usize) {
+        let mut answers = answers.iter();
+        let mut asked = 0;
+        let resolved = checked_length(var, || {
+            asked += 1;
+            let answer = answers.next().expect("asked more often than answered");
+            Ok((var.name.clone(), (*answer).to_owned(), Some(source)))
+        });
+        (resolved.map(|(_, value, _)| value), asked)
+    }
+
+    #[test]
+    fn entered_values_of_the_wrong_length_are_asked_again() {
+        let token = "a".repeat(24);
+        let (value, asked) = scripted(&token_var(), VarSource::Prompt, &["", "short", &token]);
+        assert_eq!(value.unwrap(), token);
+        assert_eq!(asked, 3);
+
+        let (value, asked) = scripted(&token_var(), VarSource::Prompt, &[&"a".repeat(20)]);
+        assert!(value.is_ok());
+        assert_eq!(asked, 1);
+    }
+
+    #[test]
+    fn values_of_the_wrong_length_from_elsewhere_are_an_error() {
+        let (value, asked) = scripted(&token_var(), VarSource::Env, &["hunter2"]);
+        let error = value.unwrap_err().to_string();
+        assert_eq!(error, "Value for TOKEN_SECURE is too short (min 20)");
+        assert_eq!(asked, 1);
+
+        let long = "a".repeat(41);
+        let (value, _) = scripted(&token_var(), VarSource::Askfile, &[&long]);
+        let error = value.unwrap_err().to_string();
+        assert_eq!(error, "Value for TOKEN_SECURE is too long (max 40)");
+        assert!(!error.contains(&long));
+    }
+
+    #[test]
+    fn check_vars_reports_every_unresolvable_var() {
+        let root = TempDir::new("devmaker-test").unwrap();
+        fs::create_dir(root.path().join("app")).unwrap();
+        fs::write(
+            root.path().join("app/info.json"),
+            r#"{"run": "true", "ask": ["DEVMAKER_TEST_GIVEN", "DEVMAKER_TEST_UNSET"]}"#,
+        )
+        .unwrap();
+        let config = Config::for_test(
+            root.path(),
+            &["--check-vars", "-w", "DEVMAKER_TEST_GIVEN=yes"],
+        );
+        let specs = [asking_spec(
+            "app",
+            r#"["DEVMAKER_TEST_GIVEN", "DEVMAKER_TEST_UNSET"]"#,
+        )];
+        let vars = merged_ask_vars(&specs, "_SECURE").unwrap();
+        let (lines, failed) = check_lines(&vars, &config);
+        assert_eq!(
+            lines,
+            [
+                "ok      DEVMAKER_TEST_GIVEN <- command",
+                "FAILED  DEVMAKER_TEST_UNSET: Cound not resolve var: DEVMAKER_TEST_UNSET",
+            ]
+        );
+        assert_eq!(failed, 1);
+
+        assert_eq!(
+            check_vars(&config).unwrap_err().to_string(),
+            "1 of 2 variables cannot be resolved without prompting"
+        );
+    }
 }